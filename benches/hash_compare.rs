@@ -0,0 +1,30 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use jacs::crypt::hash::{hash_string, hashes_match};
+
+// the three cases should report the same time per iteration.
+// a naive == returns sooner when the first byte differs than when the last one does
+fn benchmark_hashes_match(c: &mut Criterion) {
+    let expected = hash_string(&"jacs timing check".to_string());
+    let mut first_byte_differs = expected.clone().into_bytes();
+    first_byte_differs[0] ^= 1;
+    let first_byte_differs = String::from_utf8(first_byte_differs).unwrap();
+    let mut last_byte_differs = expected.clone().into_bytes();
+    let last = last_byte_differs.len() - 1;
+    last_byte_differs[last] ^= 1;
+    let last_byte_differs = String::from_utf8(last_byte_differs).unwrap();
+
+    let mut group = c.benchmark_group("hashes_match");
+    group.bench_function("equal", |b| {
+        b.iter(|| hashes_match(black_box(&expected), black_box(&expected)))
+    });
+    group.bench_function("first_byte_differs", |b| {
+        b.iter(|| hashes_match(black_box(&expected), black_box(&first_byte_differs)))
+    });
+    group.bench_function("last_byte_differs", |b| {
+        b.iter(|| hashes_match(black_box(&expected), black_box(&last_byte_differs)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_hashes_match);
+criterion_main!(benches);
@@ -0,0 +1,26 @@
+#![no_main]
+// cargo +nightly fuzz run load_document
+// seed with: mkdir -p fuzz/corpus/load_document && cp examples/documents/*.json fuzz/corpus/load_document/
+use jacs::agent::document::Document;
+use jacs::agent::Agent;
+use libfuzzer_sys::fuzz_target;
+use std::cell::RefCell;
+
+thread_local! {
+    static AGENT: RefCell<Agent> = RefCell::new(
+        Agent::new(&"v1".to_string(), &"v1".to_string(), &"v1".to_string())
+            .expect("agent schemas should load"),
+    );
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(document_string) = std::str::from_utf8(data) {
+        AGENT.with(|agent| {
+            let mut agent = agent.borrow_mut();
+            // drop what loaded so the agent does not grow across runs
+            if let Ok(document) = agent.load_document(&document_string.to_string()) {
+                let _ = agent.remove_document(&document.getkey());
+            }
+        });
+    }
+});
@@ -1,23 +1,55 @@
 use crate::agent::boilerplate::BoilerPlate;
-use crate::agent::document::{Document, JACSDocument};
+use crate::agent::document::{canonical_content_hash, Document, JACSDocument};
 use crate::agent::loaders::FileLoader;
 use crate::agent::Agent;
 use crate::agent::JACS_VERSION_DATE_FIELDNAME;
 use crate::agent::JACS_VERSION_FIELDNAME;
 use crate::agent::{
-    AGENT_AGREEMENT_FIELDNAME, DOCUMENT_AGREEMENT_HASH_FIELDNAME, JACS_PREVIOUS_VERSION_FIELDNAME,
-    SHA256_FIELDNAME,
+    AGENT_AGREEMENT_FIELDNAME, AGENT_REGISTRATION_SIGNATURE_FIELDNAME,
+    DOCUMENT_AGENT_SIGNATURE_FIELDNAME, DOCUMENT_AGREEMENT_HASH_FIELDNAME,
+    JACS_HASH_ALGORITHM_FIELDNAME, JACS_LOG_ID_FIELDNAME, JACS_LOG_INDEX_FIELDNAME,
+    JACS_LOG_PREV_HASH_FIELDNAME, JACS_PREVIOUS_VERSION_FIELDNAME, SHA256_FIELDNAME,
 };
 
 use crate::crypt::hash::hash_public_key;
 use crate::crypt::hash::hash_string;
-use crate::schema::utils::ValueExt;
+use crate::crypt::hash::hashes_match;
+use crate::crypt::SignatureEncoding;
+use crate::schema::utils::{parse_untrusted_json, ValueExt};
 use log::debug;
 use serde::ser::StdError;
 use serde_json::json;
 use serde_json::Value;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use strum_macros::{AsRefStr, Display, EnumString};
+
+/// optional map in an agreement from agent id to the public key hash that agent must sign with
+pub const AGREEMENT_PINNED_KEYS_FIELDNAME: &str = "agentPublicKeyHashes";
+
+/// what create_agreement_with_terms records besides the agents, question and context
+#[derive(Debug, Default)]
+struct AgreementTerms<'a> {
+    /// agents must sign in agentids order
+    ordered: bool,
+    /// (agent id, public key hash) pairs, each agent's signature must use that key
+    pinned_public_key_hashes: &'a [(String, String)],
+}
+
+/// which signature counts when an agreement holds more than one from the same agent,
+/// e.g. an agent that signed twice with different keys
+#[derive(Debug, Clone, Copy, PartialEq, AsRefStr, Display, EnumString)]
+pub enum DuplicateSignaturePolicy {
+    /// the earliest signature from each agent is checked, later ones are ignored
+    #[strum(serialize = "first-wins")]
+    FirstWins,
+    /// the latest signature from each agent is checked, earlier ones are ignored
+    #[strum(serialize = "last-wins")]
+    LastWins,
+    /// the agreement fails with a duplicate_signatures error listing each repeated agent
+    #[strum(serialize = "reject")]
+    RejectDuplicates,
+}
 
 pub trait Agreement {
     /// given a document id and a list of agents, return an updated document with an agreement field
@@ -32,6 +64,40 @@ pub trait Agreement {
         context: Option<&String>,
         agreement_fieldname: Option<String>,
     ) -> Result<JACSDocument, Box<dyn Error>>;
+    /// create_agreement for callers that work with JSON, such as language bindings.
+    /// returns {document_id, document_key, agreement_field, requested_signers,
+    /// document_json}, so the handles for the next calls need no re-parsing
+    fn create_agreement_detailed(
+        &mut self,
+        document_key: &String,
+        agentids: &Vec<String>,
+        question: Option<&String>,
+        context: Option<&String>,
+        agreement_fieldname: Option<String>,
+    ) -> Result<String, Box<dyn Error>>;
+    /// like create_agreement, but agents must sign in the order of agentids.
+    /// sign_agreement refuses an agent whose predecessors have not signed yet
+    fn create_ordered_agreement(
+        &mut self,
+        document_key: &String,
+        agentids: &[String],
+        question: Option<&String>,
+        context: Option<&String>,
+        agreement_fieldname: Option<String>,
+    ) -> Result<JACSDocument, Box<dyn Error>>;
+    /// like create_agreement, but each signer is an (agent id, public key hash) pair.
+    /// a signature from a listed agent made with any other key is rejected, so an agent
+    /// that rotated, or lost, its key cannot satisfy the agreement with the new one.
+    /// the pins are part of jacsAgreementHash, which every agreement signature covers, so
+    /// stripping or changing them breaks the agreement
+    fn create_pinned_agreement(
+        &mut self,
+        document_key: &String,
+        signers: &[(String, String)],
+        question: Option<&String>,
+        context: Option<&String>,
+        agreement_fieldname: Option<String>,
+    ) -> Result<JACSDocument, Box<dyn Error>>;
     /// given a document id and a list of agents, return an updated document
     fn add_agents_to_agreement(
         &mut self,
@@ -52,12 +118,85 @@ pub trait Agreement {
         document_key: &String,
         agreement_fieldname: Option<String>,
     ) -> Result<JACSDocument, Box<dyn Error>>;
-    /// given a document, check all agreement signatures
+    /// given a document, check all agreement signatures. when the agreement pins public
+    /// keys, a signer using another key fails it with a key_mismatch flag for every signer
     fn check_agreement(
         &self,
         document_key: &String,
         agreement_fieldname: Option<String>,
     ) -> Result<String, Box<dyn Error>>;
+    /// check_agreement with an explicit choice of what happens when an agent signed more
+    /// than once. with None every signature, duplicates included, has to verify
+    fn check_agreement_with_policy(
+        &self,
+        document_key: &String,
+        agreement_fieldname: Option<String>,
+        duplicate_policy: Option<DuplicateSignaturePolicy>,
+    ) -> Result<String, Box<dyn Error>>;
+
+    /// cryptographically checks every collected signature in an agreement, without
+    /// stopping at the first failure or requiring all agents to have signed.
+    /// returns a JSON array of {agent_id, signature_valid}, plus error for failures
+    /// and key_mismatch when the agreement pins public keys
+    fn verify_agreement(
+        &self,
+        document_string: &str,
+        agreement_fieldname: Option<String>,
+    ) -> Result<String, Box<dyn Error>>;
+
+    /// every field of the document holding an agreement (an object with agentIDs), with
+    /// its completion status: a JSON array of {agreement_fieldname, question, agent_ids,
+    /// signed_agents, unsigned_agents, complete}, plus next_expected_signer when ordered.
+    /// signatures are counted, not verified; use check_agreement or verify_agreement for that
+    fn list_agreements(&self, document_string: &str) -> Result<String, Box<dyn Error>>;
+
+    /// for a signer who never touches the live document, e.g. an air-gapped agent reached by
+    /// QR code or manual transfer. returns a JSON signing request {document_id,
+    /// agreement_fieldname, agreement_hash, question, context, fields, signing_payload,
+    /// document}; signing_payload is the exact string an agreement signature covers
+    fn export_agreement_signing_request(
+        &self,
+        document_string: &str,
+        agreement_fieldname: Option<String>,
+    ) -> Result<String, Box<dyn Error>>;
+
+    /// what the signer is asked to agree to, {document_id, agreement_fieldname,
+    /// agreement_hash, question, context}, taken from the document the request carries
+    /// after checking the request against it. show this, not the raw request
+    fn read_agreement_signing_request(
+        &self,
+        signing_request: &str,
+    ) -> Result<String, Box<dyn Error>>;
+
+    /// the offline half: signs an exported request with this agent's key and returns the
+    /// agreement signature as JSON. the payload is rebuilt from the document the request
+    /// carries, after checking its hashes, and a request whose other fields differ from
+    /// that rebuild is refused. this agent must be one of the agreement's agents
+    fn sign_agreement_signing_request(
+        &mut self,
+        signing_request: &str,
+    ) -> Result<String, Box<dyn Error>>;
+
+    /// verifies a signature from sign_agreement_signing_request against the document, using
+    /// the signer's public key from the key directory, then adds it to the agreement.
+    /// the signer must be one of the agreement's agents and not have signed already
+    fn import_agreement_signature(
+        &mut self,
+        document_string: &str,
+        signing_request_response: &str,
+        agreement_fieldname: Option<String>,
+    ) -> Result<JACSDocument, Box<dyn Error>>;
+
+    /// combines two copies of the same agreement that were circulated in parallel and came
+    /// back with different signers. both copies must be the same document with the same
+    /// agreement hash, agents, question and context. signatures from document_b that
+    /// document_a lacks, by agentID, are verified and added to document_a
+    fn merge_agreement_signatures(
+        &mut self,
+        document_a: &str,
+        document_b: &str,
+        agreement_fieldname: Option<String>,
+    ) -> Result<JACSDocument, Box<dyn Error>>;
 
     /// agreements update documents
     /// however this updates the document, which updates, version, lastversion and version date
@@ -82,6 +221,21 @@ pub trait Agreement {
         document_key: &std::string::String,
         agreement_fieldname: Option<String>,
     ) -> Result<(String, String), Box<dyn Error>>;
+
+    /// persists a document with an open agreement so signatures can be collected
+    /// across restarts, encrypted or compressed like other stored documents. returns the
+    /// storage id, which is the document's jacsId, and the agents that still have to sign
+    fn save_agreement_progress(
+        &self,
+        document_key: &std::string::String,
+        agreement_fieldname: Option<String>,
+    ) -> Result<(String, Vec<String>), Box<dyn Error>>;
+
+    /// loads and validates saved agreement progress, ready for sign_agreement
+    fn load_agreement_progress(
+        &mut self,
+        agreement_id: &std::string::String,
+    ) -> Result<JACSDocument, Box<dyn Error>>;
 }
 
 impl Agreement for Agent {
@@ -90,8 +244,13 @@ impl Agreement for Agent {
         value: Value,
         agreement_fieldname: &String,
     ) -> Result<String, Box<dyn Error>> {
-        let (values_as_string, _fields) =
-            self.trim_fields_for_hashing_and_signing(value, agreement_fieldname)?;
+        let (values_as_string, _fields) = agreed_values(&value, agreement_fieldname)?;
+        // pins live in the agreement field, which the values leave out, so add them
+        let values_as_string =
+            match value[agreement_fieldname.as_str()].get(AGREEMENT_PINNED_KEYS_FIELDNAME) {
+                Some(pins) => format!("{} {}", values_as_string, pins),
+                None => values_as_string,
+            };
         Ok(hash_string(&values_as_string))
     }
 
@@ -101,17 +260,21 @@ impl Agreement for Agent {
         value: Value,
         agreement_fieldname: &String,
     ) -> Result<(String, Vec<String>), Box<dyn Error>> {
-        let mut new_obj: Value = value.clone();
-        new_obj.as_object_mut().map(|obj| {
-            obj.remove(DOCUMENT_AGREEMENT_HASH_FIELDNAME);
-            obj.remove(JACS_PREVIOUS_VERSION_FIELDNAME);
-            obj.remove(JACS_VERSION_FIELDNAME);
-            return obj.remove(JACS_VERSION_DATE_FIELDNAME);
-        });
-
-        let (values_as_string, fields) =
-            Agent::get_values_as_string(&new_obj, None, &agreement_fieldname)?;
-        return Ok((values_as_string, fields));
+        let (mut values_as_string, mut fields) = agreed_values(&value, agreement_fieldname)?;
+        // a pinned agreement's hash covers its pins, so signing the hash binds them too
+        if value[agreement_fieldname.as_str()]
+            .get(AGREEMENT_PINNED_KEYS_FIELDNAME)
+            .is_some()
+        {
+            let agreement_hash = value
+                .get_str(DOCUMENT_AGREEMENT_HASH_FIELDNAME)
+                .ok_or(format!("{} missing", DOCUMENT_AGREEMENT_HASH_FIELDNAME))?;
+            values_as_string = format!("{} {}", values_as_string, agreement_hash)
+                .trim()
+                .to_string();
+            fields.push(DOCUMENT_AGREEMENT_HASH_FIELDNAME.to_string());
+        }
+        Ok((values_as_string, fields))
     }
 
     fn create_agreement(
@@ -122,52 +285,88 @@ impl Agreement for Agent {
         context: Option<&String>,
         agreement_fieldname: Option<String>,
     ) -> Result<JACSDocument, Box<(dyn StdError + 'static)>> {
-        let agreement_fieldname_key = match agreement_fieldname {
-            Some(key) => key,
-            _ => AGENT_AGREEMENT_FIELDNAME.to_string(),
-        };
-        let document = self.get_document(document_key)?;
-        let mut value = document.value;
-
-        let context_string = match context {
-            Some(cstring) => cstring,
-            _ => "",
-        };
-
-        let question_string = match question {
-            Some(qstring) => qstring,
-            _ => "",
-        };
-        // todo error if value[AGENT_AGREEMENT_FIELDNAME] exists.validate
-        let agreement_hash_value =
-            json!(self.agreement_hash(value.clone(), &agreement_fieldname_key)?);
-        value[DOCUMENT_AGREEMENT_HASH_FIELDNAME] = agreement_hash_value.clone();
-        value[agreement_fieldname_key.clone()] = json!({
-            // based on v1
-            "signatures": [],
-            "agentIDs": agentids,
-            "question": question_string,
-            "context": context_string
-        });
-        let updated_document =
-            self.update_document(document_key, &serde_json::to_string(&value)?, None, None)?;
+        self.create_agreement_with_terms(
+            document_key,
+            agentids,
+            question,
+            context,
+            agreement_fieldname,
+            AgreementTerms::default(),
+        )
+    }
 
-        let agreement_hash_value_after =
-            json!(self.agreement_hash(updated_document.value.clone(), &agreement_fieldname_key)?);
-        // could be unit test, but want this in for safety
-        if agreement_hash_value != agreement_hash_value_after {
-            return Err(format!(
-                "Agreement field hashes don't match for document_key {}",
-                document_key
-            )
-            .into());
-        }
+    fn create_agreement_detailed(
+        &mut self,
+        document_key: &std::string::String,
+        agentids: &Vec<String>,
+        question: Option<&String>,
+        context: Option<&String>,
+        agreement_fieldname: Option<String>,
+    ) -> Result<String, Box<dyn Error>> {
+        let agreement_fieldname_key =
+            self.agreement_fieldname_or_default(agreement_fieldname.as_ref())?;
+        let document = self.create_agreement(
+            document_key,
+            agentids,
+            question,
+            context,
+            Some(agreement_fieldname_key.clone()),
+        )?;
+        let requested_signers =
+            document.agreement_requested_agents(Some(agreement_fieldname_key.clone()))?;
+        Ok(serde_json::to_string(&json!({
+            "document_id": document.id,
+            "document_key": document.getkey(),
+            "agreement_field": agreement_fieldname_key,
+            "requested_signers": requested_signers,
+            "document_json": document.to_string(),
+        }))?)
+    }
 
-        if value[SHA256_FIELDNAME] == updated_document.value[SHA256_FIELDNAME] {
-            return Err(format!("document hashes should have changed {}", document_key).into());
-        };
+    fn create_ordered_agreement(
+        &mut self,
+        document_key: &std::string::String,
+        agentids: &[String],
+        question: Option<&String>,
+        context: Option<&String>,
+        agreement_fieldname: Option<String>,
+    ) -> Result<JACSDocument, Box<dyn Error>> {
+        self.create_agreement_with_terms(
+            document_key,
+            agentids,
+            question,
+            context,
+            agreement_fieldname,
+            AgreementTerms {
+                ordered: true,
+                ..Default::default()
+            },
+        )
+    }
 
-        Ok(updated_document)
+    fn create_pinned_agreement(
+        &mut self,
+        document_key: &std::string::String,
+        signers: &[(String, String)],
+        question: Option<&String>,
+        context: Option<&String>,
+        agreement_fieldname: Option<String>,
+    ) -> Result<JACSDocument, Box<dyn Error>> {
+        let agentids: Vec<String> = signers
+            .iter()
+            .map(|(agent_id, _)| agent_id.clone())
+            .collect();
+        self.create_agreement_with_terms(
+            document_key,
+            &agentids,
+            question,
+            context,
+            agreement_fieldname,
+            AgreementTerms {
+                pinned_public_key_hashes: signers,
+                ..Default::default()
+            },
+        )
     }
 
     /// TODO also remove their signature
@@ -177,10 +376,8 @@ impl Agreement for Agent {
         agentids: &Vec<String>,
         agreement_fieldname: Option<String>,
     ) -> Result<JACSDocument, Box<(dyn StdError + 'static)>> {
-        let agreement_fieldname_key = match agreement_fieldname {
-            Some(key) => key,
-            _ => AGENT_AGREEMENT_FIELDNAME.to_string(),
-        };
+        let agreement_fieldname_key =
+            self.agreement_fieldname_or_default(agreement_fieldname.as_ref())?;
         let document = self.get_document(document_key)?;
         let mut value = document.value;
         let binding = value[DOCUMENT_AGREEMENT_HASH_FIELDNAME].clone();
@@ -218,10 +415,8 @@ impl Agreement for Agent {
         agentids: &Vec<String>,
         agreement_fieldname: Option<String>,
     ) -> Result<JACSDocument, Box<(dyn StdError + 'static)>> {
-        let agreement_fieldname_key = match agreement_fieldname {
-            Some(key) => key,
-            _ => AGENT_AGREEMENT_FIELDNAME.to_string(),
-        };
+        let agreement_fieldname_key =
+            self.agreement_fieldname_or_default(agreement_fieldname.as_ref())?;
         let document = self.get_document(document_key)?;
         let mut value = document.value;
         let binding = value[DOCUMENT_AGREEMENT_HASH_FIELDNAME].clone();
@@ -233,7 +428,7 @@ impl Agreement for Agent {
                         &agents_array
                             .iter()
                             .map(|v| v.as_str().unwrap().to_string())
-                            .collect(),
+                            .collect::<Vec<String>>(),
                         agentids,
                     );
                     *agents = json!(merged_agents);
@@ -263,19 +458,40 @@ impl Agreement for Agent {
         document_key: &std::string::String,
         agreement_fieldname: Option<String>,
     ) -> Result<JACSDocument, Box<dyn Error>> {
-        let agreement_fieldname_key = match agreement_fieldname {
-            Some(ref key) => key.to_string(),
-            _ => AGENT_AGREEMENT_FIELDNAME.to_string(),
-        };
+        let agreement_fieldname_key =
+            self.agreement_fieldname_or_default(agreement_fieldname.as_ref())?;
 
         let document = self.get_document(document_key)?;
+        let signing_agent_id = self.get_id().expect("agent id");
+        if let Some(next_signer) =
+            document.agreement_next_expected_signer(Some(agreement_fieldname_key.clone()))?
+        {
+            if next_signer != signing_agent_id {
+                return Err(format!(
+                    "agreement is ordered: {} must sign before {}",
+                    next_signer, signing_agent_id
+                )
+                .into());
+            }
+        }
+        if let Some(pinned_hash) = document.value[&agreement_fieldname_key]
+            [AGREEMENT_PINNED_KEYS_FIELDNAME]
+            .get_str(&signing_agent_id)
+        {
+            if !hashes_match(&pinned_hash, &hash_public_key(self.get_public_key()?)) {
+                return Err(format!(
+                    "key_mismatch: the agreement pins {} to public key {}",
+                    signing_agent_id, pinned_hash
+                )
+                .into());
+            }
+        }
         let mut value = document.value;
         let binding = value[DOCUMENT_AGREEMENT_HASH_FIELDNAME].clone();
         let original_agreement_hash_value = binding.as_str();
         // todo use this
         let _calculated_agreement_hash_value =
             self.agreement_hash(value.clone(), &agreement_fieldname_key)?;
-        let signing_agent_id = self.get_id().expect("agent id");
         //  generate signature object
         let (_values_as_string, fields) =
             self.trim_fields_for_hashing_and_signing(value.clone(), &agreement_fieldname_key)?;
@@ -326,7 +542,9 @@ impl Agreement for Agent {
             self.agreement_hash(updated_document.value.clone(), &agreement_fieldname_key)?;
 
         // could be unit test, but want this in for safety
-        if original_agreement_hash_value != Some(&agreement_hash_value_after) {
+        if !original_agreement_hash_value
+            .is_some_and(|original| hashes_match(original, &agreement_hash_value_after))
+        {
             return Err(format!(
                 "aborting signature on agreement. field hashes don't match for document_key {} \n {} {}",
                 agent_complete_key, original_agreement_hash_value.expect("original_agreement_hash_value"), agreement_hash_value_after
@@ -341,16 +559,40 @@ impl Agreement for Agent {
         Ok(updated_document)
     }
 
+    fn save_agreement_progress(
+        &self,
+        document_key: &std::string::String,
+        agreement_fieldname: Option<String>,
+    ) -> Result<(String, Vec<String>), Box<dyn Error>> {
+        let agreement_fieldname_key =
+            self.agreement_fieldname_or_default(agreement_fieldname.as_ref())?;
+        let document = self.get_document(document_key)?;
+        let unsigned = document.agreement_unsigned_agents(Some(agreement_fieldname_key))?;
+        let agreement_id = document.id.clone();
+        self.fs_agreement_save(&agreement_id, &document.to_string())?;
+        debug!(
+            "saved agreement progress {} still waiting on {:?}",
+            agreement_id, unsigned
+        );
+        Ok((agreement_id, unsigned))
+    }
+
+    fn load_agreement_progress(
+        &mut self,
+        agreement_id: &std::string::String,
+    ) -> Result<JACSDocument, Box<dyn Error>> {
+        let document_string = self.fs_agreement_load(agreement_id)?;
+        self.load_document(&document_string)
+    }
+
     /// get human readable fields
     fn agreement_get_question_and_context(
         &self,
         document_key: &std::string::String,
         agreement_fieldname: Option<String>,
     ) -> Result<(String, String), Box<dyn Error>> {
-        let agreement_fieldname_key = match agreement_fieldname {
-            Some(key) => key,
-            _ => AGENT_AGREEMENT_FIELDNAME.to_string(),
-        };
+        let agreement_fieldname_key =
+            self.agreement_fieldname_or_default(agreement_fieldname.as_ref())?;
 
         let document = self.get_document(document_key)?;
         let error_message = format!("{} missing", DOCUMENT_AGREEMENT_HASH_FIELDNAME);
@@ -359,7 +601,10 @@ impl Agreement for Agent {
             .expect(&error_message);
         let calculated_agreement_hash_value =
             self.agreement_hash(document.value.clone(), &agreement_fieldname_key)?;
-        if original_agreement_hash_value != calculated_agreement_hash_value {
+        if !hashes_match(
+            original_agreement_hash_value,
+            &calculated_agreement_hash_value,
+        ) {
             return Err("check_agreement: agreement hashes don't match".into());
         }
 
@@ -383,10 +628,17 @@ impl Agreement for Agent {
         document_key: &std::string::String,
         agreement_fieldname: Option<String>,
     ) -> Result<String, Box<(dyn StdError + 'static)>> {
-        let agreement_fieldname_key: String = match agreement_fieldname {
-            Some(ref key) => key.to_string(),
-            _ => AGENT_AGREEMENT_FIELDNAME.to_string(),
-        };
+        self.check_agreement_with_policy(document_key, agreement_fieldname, None)
+    }
+
+    fn check_agreement_with_policy(
+        &self,
+        document_key: &String,
+        agreement_fieldname: Option<String>,
+        duplicate_policy: Option<DuplicateSignaturePolicy>,
+    ) -> Result<String, Box<dyn Error>> {
+        let agreement_fieldname_key =
+            self.agreement_fieldname_or_default(agreement_fieldname.as_ref())?;
 
         let document = self.get_document(document_key)?;
         let local_doc_value = document.value.clone();
@@ -396,11 +648,23 @@ impl Agreement for Agent {
             .expect(&error_message);
         let calculated_agreement_hash_value =
             self.agreement_hash(document.value.clone(), &agreement_fieldname_key)?;
-        if original_agreement_hash_value != calculated_agreement_hash_value {
+        if !hashes_match(
+            original_agreement_hash_value,
+            &calculated_agreement_hash_value,
+        ) {
             return Err("check_agreement: agreement hashes don't match".into());
         }
 
-        let unsigned = document.agreement_unsigned_agents(agreement_fieldname.clone())?;
+        let unsigned = document.agreement_unsigned_agents(Some(agreement_fieldname_key.clone()))?;
+        if let Some(next_signer) =
+            document.agreement_next_expected_signer(Some(agreement_fieldname_key.clone()))?
+        {
+            return Err(format!(
+                "not all agents have signed: {:?}, next_expected_signer: {}",
+                unsigned, next_signer
+            )
+            .into());
+        }
         if unsigned.len() > 0 {
             return Err(format!(
                 "not all agents have signed: {:?} {:?}",
@@ -409,64 +673,60 @@ impl Agreement for Agent {
             )
             .into());
         }
+        if document.value[&agreement_fieldname_key]["ordered"]
+            .as_bool()
+            .unwrap_or(false)
+        {
+            let requested =
+                document.agreement_requested_agents(Some(agreement_fieldname_key.clone()))?;
+            let signed: Vec<String> = select_agreement_signatures(
+                &document.value[&agreement_fieldname_key],
+                duplicate_policy,
+            )?
+            .iter()
+            .filter_map(|signature| signature.get_str("agentID"))
+            .filter(|agent_id| requested.contains(agent_id))
+            .collect();
+            if signed != requested {
+                return Err(format!(
+                    "ordered agreement signed out of order: {:?}, expected {:?}",
+                    signed, requested
+                )
+                .into());
+            }
+        }
 
         if let Some(jacs_agreement) = document.value.get(agreement_fieldname_key.clone()) {
             if let Some(signatures) = jacs_agreement.get("signatures") {
-                if let Some(signatures_array) = signatures.as_array() {
-                    for signature in signatures_array {
-                        // todo validate each signature
-                        let agent_id_and_version = format!(
-                            "{}:{}",
-                            signature
-                                .get_str("agentID")
-                                .expect("REASON agreement signature agentID")
-                                .to_string(),
-                            signature
-                                .get_str("agentVersion")
-                                .expect("REASON agreement signature agentVersion")
-                                .to_string()
-                        )
-                        .to_string();
-
-                        let noted_hash = signature
-                            .get_str("publicKeyHash")
-                            .expect("REASON noted_hash")
-                            .to_string();
-
-                        let public_key_enc_type = signature
-                            .get_str("signingAlgorithm")
-                            .expect("REASON public_key_enc_type")
-                            .to_string();
-                        let agents_signature = signature
-                            .get_str("signature")
-                            .expect("REASON public_key_enc_type")
-                            .to_string();
-                        let agents_public_key = self.fs_load_public_key(&noted_hash)?;
-                        let new_hash = hash_public_key(agents_public_key.clone());
-                        if new_hash != noted_hash {
+                if signatures.is_array() {
+                    let signatures_array =
+                        &select_agreement_signatures(jacs_agreement, duplicate_policy)?;
+                    if jacs_agreement
+                        .get(AGREEMENT_PINNED_KEYS_FIELDNAME)
+                        .is_some()
+                    {
+                        let signers = signatures_array
+                            .iter()
+                            .map(|signature| {
+                                Ok(json!({
+                                    "agent_id": signature.get_str("agentID"),
+                                    "key_mismatch": agreement_key_mismatch(jacs_agreement, signature)?,
+                                }))
+                            })
+                            .collect::<Result<Vec<Value>, Box<dyn Error>>>()?;
+                        if signers.iter().any(|signer| signer["key_mismatch"] == true) {
                             return Err(format!(
-                                "wrong public key for {} , {}",
-                                agent_id_and_version, noted_hash
+                                "check_agreement: key_mismatch {}",
+                                serde_json::to_string(&signers)?
                             )
                             .into());
                         }
-                        debug!(
-                            "testing agreement sig agent_id_and_version {} {} {} ",
-                            agent_id_and_version, noted_hash, public_key_enc_type
-                        );
-                        let (_values_as_string, fields) = self
-                            .trim_fields_for_hashing_and_signing(
-                                local_doc_value.clone(),
-                                &agreement_fieldname_key,
-                            )?;
-                        let result = self.signature_verification_procedure(
-                            &document.value,
-                            Some(&fields),
-                            &agreement_fieldname_key.to_string(),
-                            agents_public_key,
-                            Some(public_key_enc_type.clone()),
-                            Some(noted_hash.clone()),
-                            Some(agents_signature),
+                    }
+                    for signature in signatures_array {
+                        self.verify_agreement_signature(
+                            &local_doc_value,
+                            &agreement_fieldname_key,
+                            signature,
                         )?;
                     }
                     return Ok("All signatures passed".to_string());
@@ -475,18 +735,703 @@ impl Agreement for Agent {
         }
         return Err("check_agreement: document has no agreement".into());
     }
+
+    fn verify_agreement(
+        &self,
+        document_string: &str,
+        agreement_fieldname: Option<String>,
+    ) -> Result<String, Box<dyn Error>> {
+        let agreement_fieldname_key =
+            self.agreement_fieldname_or_default(agreement_fieldname.as_ref())?;
+        let document_value = self.schema.validate_header(document_string)?;
+        self.verify_hash(&document_value)?;
+        let original_agreement_hash_value = document_value
+            .get_str(DOCUMENT_AGREEMENT_HASH_FIELDNAME)
+            .ok_or(format!("{} missing", DOCUMENT_AGREEMENT_HASH_FIELDNAME))?;
+        let calculated_agreement_hash_value =
+            self.agreement_hash(document_value.clone(), &agreement_fieldname_key)?;
+        if !hashes_match(
+            &original_agreement_hash_value,
+            &calculated_agreement_hash_value,
+        ) {
+            return Err("verify_agreement: agreement hashes don't match".into());
+        }
+
+        let agreement = &document_value[&agreement_fieldname_key];
+        let signatures = match agreement.get("signatures") {
+            Some(Value::Array(signatures)) => signatures.clone(),
+            Some(_) => return Err("verify_agreement: signatures is not an array".into()),
+            None => Vec::new(),
+        };
+        let results: Vec<Value> = signatures
+            .iter()
+            .map(|signature| {
+                let agent_id = signature.get_str("agentID").unwrap_or_default();
+                let mut result = match self.verify_agreement_signature(
+                    &document_value,
+                    &agreement_fieldname_key,
+                    signature,
+                ) {
+                    Ok(_) => json!({"agent_id": agent_id, "signature_valid": true}),
+                    Err(e) => json!({
+                        "agent_id": agent_id,
+                        "signature_valid": false,
+                        "error": e.to_string()
+                    }),
+                };
+                if agreement.get(AGREEMENT_PINNED_KEYS_FIELDNAME).is_some() {
+                    result["key_mismatch"] =
+                        json!(agreement_key_mismatch(agreement, signature).unwrap_or(true));
+                }
+                result
+            })
+            .collect();
+        Ok(serde_json::to_string(&results)?)
+    }
+
+    fn export_agreement_signing_request(
+        &self,
+        document_string: &str,
+        agreement_fieldname: Option<String>,
+    ) -> Result<String, Box<dyn Error>> {
+        let agreement_fieldname_key =
+            self.agreement_fieldname_or_default(agreement_fieldname.as_ref())?;
+        let document_value = self.schema.validate_header(document_string)?;
+        self.verify_hash(&document_value)?;
+        let request = self.signing_request_for(&document_value, &agreement_fieldname_key)?;
+        Ok(serde_json::to_string(&request)?)
+    }
+
+    fn read_agreement_signing_request(
+        &self,
+        signing_request: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        let (request, _) = self.verified_signing_request(signing_request)?;
+        Ok(serde_json::to_string(&json!({
+            "document_id": request["document_id"],
+            "agreement_fieldname": request["agreement_fieldname"],
+            "agreement_hash": request["agreement_hash"],
+            "question": request["question"],
+            "context": request["context"],
+        }))?)
+    }
+
+    fn sign_agreement_signing_request(
+        &mut self,
+        signing_request: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        let (request, agreement_fieldname_key) = self.verified_signing_request(signing_request)?;
+        let agent_id = self.get_id()?;
+        let requested = request["document"][&agreement_fieldname_key]["agentIDs"]
+            .as_array()
+            .is_some_and(|agent_ids| {
+                agent_ids
+                    .iter()
+                    .any(|id| id.as_str() == Some(agent_id.as_str()))
+            });
+        if !requested {
+            return Err(format!("{} is not an agent in this agreement", agent_id).into());
+        }
+        let signing_payload = request
+            .get_str("signing_payload")
+            .ok_or("signing request has no signing_payload")?;
+        let fields: Vec<String> = serde_json::from_value(request["fields"].clone())?;
+        let signature = self.signature_for_values_string(
+            &signing_payload,
+            fields,
+            SignatureEncoding::default(),
+        )?;
+        Ok(serde_json::to_string(&signature)?)
+    }
+
+    fn import_agreement_signature(
+        &mut self,
+        document_string: &str,
+        signing_request_response: &str,
+        agreement_fieldname: Option<String>,
+    ) -> Result<JACSDocument, Box<dyn Error>> {
+        let agreement_fieldname_key =
+            self.agreement_fieldname_or_default(agreement_fieldname.as_ref())?;
+        let document_value = self.schema.validate_header(document_string)?;
+        self.verify_hash(&document_value)?;
+        let original_agreement_hash_value =
+            self.checked_agreement_hash(&document_value, &agreement_fieldname_key)?;
+        let signature = parse_untrusted_json(signing_request_response)?;
+        self.schema.validate_signature(&signature)?;
+        let signer_id = signature
+            .get_str("agentID")
+            .ok_or("agreement signature has no agentID")?;
+
+        let document = self.load_document(&document_string.to_string())?;
+        let requested =
+            document.agreement_requested_agents(Some(agreement_fieldname_key.clone()))?;
+        if !requested.contains(&signer_id) {
+            return Err(format!("{} is not an agent in this agreement", signer_id).into());
+        }
+        let unsigned = document.agreement_unsigned_agents(Some(agreement_fieldname_key.clone()))?;
+        if !unsigned.contains(&signer_id) {
+            return Err(format!("{} has already signed this agreement", signer_id).into());
+        }
+        if let Some(next_signer) =
+            document.agreement_next_expected_signer(Some(agreement_fieldname_key.clone()))?
+        {
+            if next_signer != signer_id {
+                return Err(format!(
+                    "agreement is ordered: {} must sign before {}",
+                    next_signer, signer_id
+                )
+                .into());
+            }
+        }
+        self.verify_agreement_signature(&document_value, &agreement_fieldname_key, &signature)?;
+
+        let mut value = document_value;
+        match value[&agreement_fieldname_key]["signatures"].as_array_mut() {
+            Some(signatures) => signatures.push(signature),
+            None => value[&agreement_fieldname_key]["signatures"] = json!([signature]),
+        }
+        let updated_document = self.update_document(
+            &document.getkey(),
+            &serde_json::to_string(&value)?,
+            None,
+            None,
+        )?;
+        let agreement_hash_value_after =
+            self.agreement_hash(updated_document.value.clone(), &agreement_fieldname_key)?;
+        if !hashes_match(&original_agreement_hash_value, &agreement_hash_value_after) {
+            return Err(format!(
+                "aborting signature import. field hashes don't match for document_key {}",
+                document.getkey()
+            )
+            .into());
+        }
+        Ok(updated_document)
+    }
+
+    fn merge_agreement_signatures(
+        &mut self,
+        document_a: &str,
+        document_b: &str,
+        agreement_fieldname: Option<String>,
+    ) -> Result<JACSDocument, Box<dyn Error>> {
+        let agreement_fieldname_key =
+            self.agreement_fieldname_or_default(agreement_fieldname.as_ref())?;
+        let value_a = self.schema.validate_header(document_a)?;
+        let value_b = self.schema.validate_header(document_b)?;
+        self.verify_hash(&value_a)?;
+        self.verify_hash(&value_b)?;
+        if value_a.get_str("jacsId") != value_b.get_str("jacsId") {
+            return Err("merge_agreement_signatures: copies are different documents".into());
+        }
+        let agreement_hash_a = self.checked_agreement_hash(&value_a, &agreement_fieldname_key)?;
+        let agreement_hash_b = self.checked_agreement_hash(&value_b, &agreement_fieldname_key)?;
+        // the agreement hash only covers string fields, so compare everything else too
+        if !hashes_match(&agreement_hash_a, &agreement_hash_b)
+            || canonical_content_hash(&agreed_content(&value_a, &agreement_fieldname_key))
+                != canonical_content_hash(&agreed_content(&value_b, &agreement_fieldname_key))
+        {
+            return Err("merge_agreement_signatures: agreed content differs between copies".into());
+        }
+        let agreement_a = &value_a[&agreement_fieldname_key];
+        let agreement_b = &value_b[&agreement_fieldname_key];
+        for term in [
+            "agentIDs",
+            "question",
+            "context",
+            "ordered",
+            AGREEMENT_PINNED_KEYS_FIELDNAME,
+        ] {
+            if agreement_a.get(term) != agreement_b.get(term) {
+                return Err(format!(
+                    "merge_agreement_signatures: {} differs between copies",
+                    term
+                )
+                .into());
+            }
+        }
+
+        let document = self.load_document(&document_a.to_string())?;
+        let requested =
+            document.agreement_requested_agents(Some(agreement_fieldname_key.clone()))?;
+        let mut signatures: Vec<Value> = match agreement_a.get("signatures") {
+            Some(Value::Array(signatures)) => signatures.clone(),
+            _ => Vec::new(),
+        };
+        let mut signed: HashSet<String> = signatures
+            .iter()
+            .filter_map(|signature| signature.get_str("agentID"))
+            .collect();
+        let mut added = 0;
+        if let Some(Value::Array(signatures_b)) = agreement_b.get("signatures") {
+            for signature in signatures_b {
+                let signer_id = signature
+                    .get_str("agentID")
+                    .ok_or("agreement signature has no agentID")?;
+                if signed.contains(&signer_id) {
+                    continue;
+                }
+                if !requested.contains(&signer_id) {
+                    return Err(format!("{} is not an agent in this agreement", signer_id).into());
+                }
+                self.schema.validate_signature(signature)?;
+                self.verify_agreement_signature(&value_a, &agreement_fieldname_key, signature)?;
+                signatures.push(signature.clone());
+                signed.insert(signer_id);
+                added += 1;
+            }
+        }
+        if added == 0 {
+            return Ok(document);
+        }
+        // each copy of an ordered agreement holds a prefix of agentIDs, keep their union in order
+        if agreement_a["ordered"].as_bool().unwrap_or(false) {
+            signatures.sort_by_key(|signature| {
+                signature
+                    .get_str("agentID")
+                    .and_then(|signer_id| requested.iter().position(|id| *id == signer_id))
+            });
+        }
+
+        let mut value = value_a.clone();
+        value[&agreement_fieldname_key]["signatures"] = json!(signatures);
+        let updated_document = self.update_document(
+            &document.getkey(),
+            &serde_json::to_string(&value)?,
+            None,
+            None,
+        )?;
+        let agreement_hash_value_after =
+            self.agreement_hash(updated_document.value.clone(), &agreement_fieldname_key)?;
+        if !hashes_match(&agreement_hash_a, &agreement_hash_value_after) {
+            return Err(format!(
+                "aborting agreement merge. field hashes don't match for document_key {}",
+                document.getkey()
+            )
+            .into());
+        }
+        Ok(updated_document)
+    }
+
+    fn list_agreements(&self, document_string: &str) -> Result<String, Box<dyn Error>> {
+        let document_value = self.schema.validate_header(document_string)?;
+        let fields = document_value
+            .as_object()
+            .ok_or("list_agreements: document is not an object")?;
+        let mut agreements: Vec<Value> = Vec::new();
+        for (agreement_fieldname, agreement) in fields {
+            let agent_ids: Vec<String> = match agreement.get("agentIDs") {
+                Some(Value::Array(agent_ids)) => agent_ids
+                    .iter()
+                    .filter_map(|agent_id| agent_id.as_str().map(String::from))
+                    .collect(),
+                _ => continue,
+            };
+            let signed_agents: Vec<String> = match agreement.get("signatures") {
+                Some(Value::Array(signatures)) => signatures
+                    .iter()
+                    .filter_map(|signature| signature.get_str("agentID"))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            let unsigned_agents = subtract_vecs(&agent_ids, &signed_agents);
+            let mut status = json!({
+                "agreement_fieldname": agreement_fieldname,
+                "question": agreement.get_str("question").unwrap_or_default(),
+                "agent_ids": agent_ids,
+                "signed_agents": signed_agents,
+                "unsigned_agents": unsigned_agents,
+                "complete": unsigned_agents.is_empty(),
+            });
+            if agreement["ordered"].as_bool().unwrap_or(false) {
+                status["next_expected_signer"] = json!(unsigned_agents.first());
+            }
+            agreements.push(status);
+        }
+        Ok(serde_json::to_string(&agreements)?)
+    }
 }
 
-pub fn merge_without_duplicates(vec1: &Vec<String>, vec2: &Vec<String>) -> Vec<String> {
-    let mut set: HashSet<String> = HashSet::new();
+/// the document's string values the agreement covers, leaving out the agreement field
+/// itself, its hash and the fields every new version changes
+fn agreed_values(
+    value: &Value,
+    agreement_fieldname: &String,
+) -> Result<(String, Vec<String>), Box<dyn Error>> {
+    let mut new_obj: Value = value.clone();
+    if let Some(obj) = new_obj.as_object_mut() {
+        obj.remove(DOCUMENT_AGREEMENT_HASH_FIELDNAME);
+        obj.remove(JACS_PREVIOUS_VERSION_FIELDNAME);
+        obj.remove(JACS_VERSION_FIELDNAME);
+        obj.remove(JACS_VERSION_DATE_FIELDNAME);
+    }
+    Agent::get_values_as_string(&new_obj, None, agreement_fieldname)
+}
 
-    for item in vec1 {
-        set.insert(item.to_string());
+/// a copy of an agreement document without what signing the agreement changes: the
+/// version fields, the document signature and hash, and the agreement's signatures
+fn agreed_content(value: &Value, agreement_fieldname: &str) -> Value {
+    let mut content = value.clone();
+    if let Some(fields) = content.as_object_mut() {
+        for field in [
+            JACS_VERSION_FIELDNAME,
+            JACS_VERSION_DATE_FIELDNAME,
+            JACS_PREVIOUS_VERSION_FIELDNAME,
+            DOCUMENT_AGENT_SIGNATURE_FIELDNAME,
+            SHA256_FIELDNAME,
+        ] {
+            fields.remove(field);
+        }
+        if let Some(Value::Object(agreement)) = fields.get_mut(agreement_fieldname) {
+            agreement.remove("signatures");
+        }
+    }
+    content
+}
+
+/// header and signature fields an agreement must never be written over
+const RESERVED_AGREEMENT_FIELDNAMES: [&str; 17] = [
+    "$schema",
+    "jacsId",
+    "jacsType",
+    "jacsFiles",
+    "jacsOriginalVersion",
+    "jacsOriginalDate",
+    JACS_VERSION_FIELDNAME,
+    JACS_VERSION_DATE_FIELDNAME,
+    JACS_PREVIOUS_VERSION_FIELDNAME,
+    SHA256_FIELDNAME,
+    DOCUMENT_AGENT_SIGNATURE_FIELDNAME,
+    AGENT_REGISTRATION_SIGNATURE_FIELDNAME,
+    DOCUMENT_AGREEMENT_HASH_FIELDNAME,
+    JACS_LOG_ID_FIELDNAME,
+    JACS_LOG_INDEX_FIELDNAME,
+    JACS_LOG_PREV_HASH_FIELDNAME,
+    JACS_HASH_ALGORITHM_FIELDNAME,
+];
+
+/// resolves the agreement field, defaulting to jacsAgreement.
+/// custom names must be plain identifiers and may not collide with reserved JACS fields,
+/// otherwise signing the agreement could overwrite the document's own signature or hash
+pub fn resolve_agreement_fieldname(
+    agreement_fieldname: Option<&String>,
+) -> Result<String, Box<dyn Error>> {
+    let key = match agreement_fieldname {
+        Some(key) => key.to_string(),
+        _ => return Ok(AGENT_AGREEMENT_FIELDNAME.to_string()),
+    };
+    let mut chars = key.chars();
+    let valid = match chars.next() {
+        Some(first) => {
+            first.is_ascii_alphabetic() && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    };
+    if !valid {
+        return Err(format!("invalid agreement fieldname {:?}", key).into());
+    }
+    if RESERVED_AGREEMENT_FIELDNAMES.contains(&key.as_str()) {
+        return Err(format!("agreement fieldname {} is a reserved JACS field", key).into());
     }
-    for item in vec2 {
-        set.insert(item.to_string());
+    Ok(key)
+}
+
+/// the agreement's signatures that count under `duplicate_policy`, in their original order.
+/// None keeps them all
+fn select_agreement_signatures(
+    agreement: &Value,
+    duplicate_policy: Option<DuplicateSignaturePolicy>,
+) -> Result<Vec<Value>, Box<dyn Error>> {
+    let signatures = match agreement.get("signatures") {
+        Some(Value::Array(signatures)) => signatures,
+        _ => return Ok(Vec::new()),
+    };
+    let agent_ids: Vec<String> = signatures
+        .iter()
+        .map(|signature| signature.get_str("agentID").unwrap_or_default())
+        .collect();
+    if duplicate_policy == Some(DuplicateSignaturePolicy::RejectDuplicates) {
+        let mut counts: HashMap<&String, usize> = HashMap::new();
+        for agent_id in &agent_ids {
+            *counts.entry(agent_id).or_insert(0) += 1;
+        }
+        let mut duplicates: Vec<(&String, usize)> =
+            counts.into_iter().filter(|(_, count)| *count > 1).collect();
+        if !duplicates.is_empty() {
+            duplicates.sort();
+            let duplicates: Vec<Value> = duplicates
+                .into_iter()
+                .map(|(agent_id, count)| json!({"agent_id": agent_id, "signatures": count}))
+                .collect();
+            return Err(format!(
+                "duplicate_signatures: {}",
+                serde_json::to_string(&duplicates)?
+            )
+            .into());
+        }
+    }
+    let kept = |index: usize| -> bool {
+        let agent_id = &agent_ids[index];
+        match duplicate_policy {
+            Some(DuplicateSignaturePolicy::FirstWins) => !agent_ids[..index].contains(agent_id),
+            Some(DuplicateSignaturePolicy::LastWins) => !agent_ids[index + 1..].contains(agent_id),
+            _ => true,
+        }
+    };
+    Ok(signatures
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| kept(*index))
+        .map(|(_, signature)| signature.clone())
+        .collect())
+}
+
+/// true when the agreement pins the signature's agent to a public key hash other than the
+/// one it signed with. agents without a pin never mismatch
+fn agreement_key_mismatch(agreement: &Value, signature: &Value) -> Result<bool, Box<dyn Error>> {
+    let agent_id = signature
+        .get_str("agentID")
+        .ok_or("agreement signature has no agentID")?;
+    let pinned_hash = match agreement[AGREEMENT_PINNED_KEYS_FIELDNAME].get_str(&agent_id) {
+        Some(pinned_hash) => pinned_hash,
+        None => return Ok(false),
+    };
+    let public_key_hash = signature
+        .get_str("publicKeyHash")
+        .ok_or("agreement signature has no publicKeyHash")?;
+    Ok(!hashes_match(&pinned_hash, &public_key_hash))
+}
+
+impl Agent {
+    /// checks one entry of an agreement's signatures array against the signer's stored
+    /// public key. returns the signer's id:version
+    fn verify_agreement_signature(
+        &self,
+        document_value: &Value,
+        agreement_fieldname_key: &String,
+        signature: &Value,
+    ) -> Result<String, Box<dyn Error>> {
+        let agent_id_and_version = format!(
+            "{}:{}",
+            signature
+                .get_str("agentID")
+                .ok_or("agreement signature has no agentID")?,
+            signature
+                .get_str("agentVersion")
+                .ok_or("agreement signature has no agentVersion")?
+        );
+        let noted_hash = signature
+            .get_str("publicKeyHash")
+            .ok_or("agreement signature has no publicKeyHash")?;
+        let public_key_enc_type = signature
+            .get_str("signingAlgorithm")
+            .ok_or("agreement signature has no signingAlgorithm")?;
+        let agents_signature = signature
+            .get_str("signature")
+            .ok_or("agreement signature has no signature")?;
+        Agent::signature_format(signature)?;
+        let agents_public_key = self.fs_load_public_key(&noted_hash)?;
+        let new_hash = hash_public_key(agents_public_key.clone());
+        if !hashes_match(&noted_hash, &new_hash) {
+            return Err(format!(
+                "wrong public key for {} , {}",
+                agent_id_and_version, noted_hash
+            )
+            .into());
+        }
+        if agreement_key_mismatch(&document_value[agreement_fieldname_key], signature)? {
+            return Err(format!(
+                "key_mismatch: {} signed with {}, not its pinned public key",
+                agent_id_and_version, noted_hash
+            )
+            .into());
+        }
+        debug!(
+            "testing agreement sig agent_id_and_version {} {} {} ",
+            agent_id_and_version, noted_hash, public_key_enc_type
+        );
+        let (_values_as_string, fields) = self
+            .trim_fields_for_hashing_and_signing(document_value.clone(), agreement_fieldname_key)?;
+        self.signature_verification_procedure(
+            document_value,
+            Some(&fields),
+            agreement_fieldname_key,
+            agents_public_key,
+            Some(public_key_enc_type),
+            Some(noted_hash),
+            Some(agents_signature),
+        )?;
+        Ok(agent_id_and_version)
+    }
+
+    /// the signing request for an agreement in a document whose hash was already checked.
+    /// everything in it is derived from the document, which travels with it
+    fn signing_request_for(
+        &self,
+        document_value: &Value,
+        agreement_fieldname_key: &String,
+    ) -> Result<Value, Box<dyn Error>> {
+        let agreement_hash_value =
+            self.checked_agreement_hash(document_value, agreement_fieldname_key)?;
+        let agreement = document_value
+            .get(agreement_fieldname_key)
+            .ok_or("export_agreement_signing_request: document has no agreement")?;
+        let (signing_payload, fields) = self
+            .trim_fields_for_hashing_and_signing(document_value.clone(), agreement_fieldname_key)?;
+        Ok(json!({
+            "document_id": document_value.get_str("jacsId"),
+            "agreement_fieldname": agreement_fieldname_key,
+            "agreement_hash": agreement_hash_value,
+            "question": agreement.get_str("question").unwrap_or_default(),
+            "context": agreement.get_str("context").unwrap_or_default(),
+            "fields": fields,
+            "signing_payload": signing_payload,
+            "document": document_value,
+        }))
+    }
+
+    /// checks the document a signing request carries and rebuilds the request from it.
+    /// a request that differs from the rebuild, e.g. one with a payload for some other
+    /// document, is refused. returns the request and its agreement fieldname
+    fn verified_signing_request(
+        &self,
+        signing_request: &str,
+    ) -> Result<(Value, String), Box<dyn Error>> {
+        let request = parse_untrusted_json(signing_request)?;
+        let document = request
+            .get("document")
+            .filter(|document| document.is_object())
+            .ok_or("signing request has no document")?;
+        let agreement_fieldname_key =
+            self.agreement_fieldname_or_default(request.get_str("agreement_fieldname").as_ref())?;
+        let document_value = self.schema.validate_header(&document.to_string())?;
+        self.verify_hash(&document_value)?;
+        let rebuilt = self.signing_request_for(&document_value, &agreement_fieldname_key)?;
+        if rebuilt != request {
+            return Err("signing request does not match the document it carries".into());
+        }
+        Ok((rebuilt, agreement_fieldname_key))
+    }
+
+    /// the document's jacsAgreementHash, after checking it still matches its content
+    fn checked_agreement_hash(
+        &self,
+        document_value: &Value,
+        agreement_fieldname_key: &String,
+    ) -> Result<String, Box<dyn Error>> {
+        let original_agreement_hash_value = document_value
+            .get_str(DOCUMENT_AGREEMENT_HASH_FIELDNAME)
+            .ok_or(format!("{} missing", DOCUMENT_AGREEMENT_HASH_FIELDNAME))?;
+        let calculated_agreement_hash_value =
+            self.agreement_hash(document_value.clone(), agreement_fieldname_key)?;
+        if !hashes_match(
+            &original_agreement_hash_value,
+            &calculated_agreement_hash_value,
+        ) {
+            return Err("agreement hashes don't match".into());
+        }
+        Ok(original_agreement_hash_value)
+    }
+
+    /// creates the agreement with its terms recorded in the agreement field
+    fn create_agreement_with_terms(
+        &mut self,
+        document_key: &std::string::String,
+        agentids: &[String],
+        question: Option<&String>,
+        context: Option<&String>,
+        agreement_fieldname: Option<String>,
+        terms: AgreementTerms,
+    ) -> Result<JACSDocument, Box<dyn Error>> {
+        let agreement_fieldname_key =
+            self.agreement_fieldname_or_default(agreement_fieldname.as_ref())?;
+        let document = self.get_document(document_key)?;
+        let mut value = document.value;
+
+        let context_string = match context {
+            Some(cstring) => cstring,
+            _ => "",
+        };
+
+        let question_string = match question {
+            Some(qstring) => qstring,
+            _ => "",
+        };
+        // todo error if value[AGENT_AGREEMENT_FIELDNAME] exists.validate
+        value[agreement_fieldname_key.clone()] = json!({
+            // based on v1
+            "signatures": [],
+            "agentIDs": agentids,
+            "question": question_string,
+            "context": context_string
+        });
+        if terms.ordered {
+            value[agreement_fieldname_key.clone()]["ordered"] = json!(true);
+        }
+        if !terms.pinned_public_key_hashes.is_empty() {
+            let pins: serde_json::Map<String, Value> = terms
+                .pinned_public_key_hashes
+                .iter()
+                .map(|(agent_id, public_key_hash)| (agent_id.clone(), json!(public_key_hash)))
+                .collect();
+            value[agreement_fieldname_key.clone()][AGREEMENT_PINNED_KEYS_FIELDNAME] = json!(pins);
+        }
+        // hashed once the agreement is in place, since the hash covers its pins
+        let agreement_hash_value =
+            json!(self.agreement_hash(value.clone(), &agreement_fieldname_key)?);
+        value[DOCUMENT_AGREEMENT_HASH_FIELDNAME] = agreement_hash_value.clone();
+        let updated_document =
+            self.update_document(document_key, &serde_json::to_string(&value)?, None, None)?;
+
+        let agreement_hash_value_after =
+            json!(self.agreement_hash(updated_document.value.clone(), &agreement_fieldname_key)?);
+        // could be unit test, but want this in for safety
+        if agreement_hash_value != agreement_hash_value_after {
+            return Err(format!(
+                "Agreement field hashes don't match for document_key {}",
+                document_key
+            )
+            .into());
+        }
+
+        if value[SHA256_FIELDNAME] == updated_document.value[SHA256_FIELDNAME] {
+            return Err(format!("document hashes should have changed {}", document_key).into());
+        };
+
+        Ok(updated_document)
+    }
+
+    /// an explicit agreement fieldname wins, then the agent's default, then jacsAgreement
+    fn agreement_fieldname_or_default(
+        &self,
+        agreement_fieldname: Option<&String>,
+    ) -> Result<String, Box<dyn Error>> {
+        resolve_agreement_fieldname(
+            agreement_fieldname.or(self.default_agreement_fieldname.as_ref()),
+        )
+    }
+
+    /// the agreement field used by the Agreement methods when none is passed.
+    /// validated like any agreement fieldname
+    pub fn set_default_agreement_fieldname(
+        &mut self,
+        fieldname: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let fieldname = resolve_agreement_fieldname(Some(&fieldname.to_string()))?;
+        self.default_agreement_fieldname = Some(fieldname);
+        Ok(())
+    }
+}
+
+/// keeps the order of vec1, then appends what is new in vec2. ordered agreements rely on this
+pub fn merge_without_duplicates(vec1: &[String], vec2: &[String]) -> Vec<String> {
+    let mut set: HashSet<&String> = HashSet::new();
+    let mut merged: Vec<String> = Vec::new();
+    for item in vec1.iter().chain(vec2.iter()) {
+        if set.insert(item) {
+            merged.push(item.to_string());
+        }
     }
-    set.into_iter().collect()
+    merged
 }
 
 pub fn subtract_vecs(vec1: &Vec<String>, vec2: &Vec<String>) -> Vec<String> {
@@ -0,0 +1,100 @@
+use crate::agent::boilerplate::BoilerPlate;
+use crate::agent::document::{check_document_size, max_document_bytes, Document, JACSDocument};
+use crate::agent::Agent;
+use crate::agent::{AGENT_SIGNATURE_FIELDNAME, DOCUMENT_AGENT_SIGNATURE_FIELDNAME};
+use crate::schema::utils::{parse_untrusted_json, ValueExt};
+use log::error;
+use serde_json::json;
+use std::error::Error;
+
+/// self-contained evidence for air-gapped verification.
+/// a bundle is a JSON object:
+/// {
+///   "document":   the signed JACS document,
+///   "agent":      the signer's agent document,
+///   "public_key": the signer's public key bytes, base64 encoded,
+///   "algorithm":  the signing algorithm, e.g. "RSA-PSS"
+/// }
+pub trait OfflineBundle {
+    /// bundle a loaded document with this agent's record and public key
+    fn create_offline_bundle(&self, document_key: &String) -> Result<String, Box<dyn Error>>;
+
+    /// verify a bundle entirely in memory, with no network or key directory lookups.
+    /// the agent's hash and self-signature are checked with the embedded key, then the
+    /// document's hash and signature, which must name that same agent id and version
+    fn verify_offline_bundle(&self, bundle_json: &str) -> Result<JACSDocument, Box<dyn Error>>;
+}
+
+impl OfflineBundle for Agent {
+    fn create_offline_bundle(&self, document_key: &String) -> Result<String, Box<dyn Error>> {
+        let document = self.get_document(document_key)?;
+        let algorithm = document.value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME]
+            .get_str("signingAlgorithm")
+            .ok_or("document has no signingAlgorithm")?;
+        let agent_value = self.value.clone().ok_or("agent is not loaded")?;
+        let bundle = json!({
+            "document": document.value,
+            "agent": agent_value,
+            "public_key": base64::encode(self.get_public_key()?),
+            "algorithm": algorithm,
+        });
+        Ok(serde_json::to_string_pretty(&bundle)?)
+    }
+
+    fn verify_offline_bundle(&self, bundle_json: &str) -> Result<JACSDocument, Box<dyn Error>> {
+        check_document_size(bundle_json, max_document_bytes())?;
+        let bundle = parse_untrusted_json(bundle_json)?;
+        let (document, agent) = match (bundle.get("document"), bundle.get("agent")) {
+            (Some(document), Some(agent)) => (document, agent),
+            _ => return Err("bundle needs both document and agent".into()),
+        };
+        let public_key = base64::decode(
+            bundle
+                .get_str("public_key")
+                .ok_or("bundle has no public_key")?,
+        )?;
+        let algorithm = bundle
+            .get_str("algorithm")
+            .ok_or("bundle has no algorithm")?;
+
+        let agent_value = self.schema.validate_agent(&agent.to_string())?;
+        self.verify_hash(&agent_value)?;
+        self.signature_verification_procedure(
+            &agent_value,
+            None,
+            &AGENT_SIGNATURE_FIELDNAME.to_string(),
+            public_key.clone(),
+            Some(algorithm.clone()),
+            None,
+            None,
+        )
+        .map_err(|e| format!("bundle agent self-signature invalid: {}", e))?;
+
+        let document_value = self.schema.validate_header(&document.to_string())?;
+        self.verify_hash(&document_value)?;
+        let signature = &document_value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME];
+        if signature.get_str("agentID") != agent_value.get_str("jacsId")
+            || signature.get_str("agentVersion") != agent_value.get_str("jacsVersion")
+        {
+            let error_message = "bundle document was not signed by the bundled agent";
+            error!("{}", error_message);
+            return Err(error_message.into());
+        }
+        self.signature_verification_procedure(
+            &document_value,
+            None,
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+            public_key,
+            Some(algorithm),
+            None,
+            None,
+        )
+        .map_err(|e| format!("bundle document signature invalid: {}", e))?;
+
+        Ok(JACSDocument {
+            id: document_value.get_str("jacsId").unwrap_or_default(),
+            version: document_value.get_str("jacsVersion").unwrap_or_default(),
+            value: document_value,
+        })
+    }
+}
@@ -0,0 +1,108 @@
+use crate::agent::boilerplate::BoilerPlate;
+use crate::agent::loaders::FileLoader;
+use crate::agent::Agent;
+use crate::agent::AGENT_SIGNATURE_FIELDNAME;
+use crate::crypt::hash::{hash_public_key, hashes_match};
+use crate::crypt::KeyManager;
+use crate::schema::utils::{parse_untrusted_json, ValueExt};
+use serde_json::json;
+use serde_json::Value;
+use std::error::Error;
+
+/// CloudEvents extension attributes carrying a JACS signature. extension names must be
+/// lowercase alphanumeric, so these don't follow the jacsCamelCase document fields
+pub const CLOUDEVENT_SIGNATURE_ATTRIBUTE: &str = "jacssignature";
+pub const CLOUDEVENT_AGENT_ID_ATTRIBUTE: &str = "jacsagentid";
+pub const CLOUDEVENT_PUBLIC_KEY_HASH_ATTRIBUTE: &str = "jacspublickeyhash";
+pub const CLOUDEVENT_SIGNING_ALGORITHM_ATTRIBUTE: &str = "jacssigningalgorithm";
+
+/// attributes every CloudEvent must have, per the 1.0 spec
+const CLOUDEVENT_REQUIRED_ATTRIBUTES: [&str; 4] = ["specversion", "id", "source", "type"];
+
+/// signs CloudEvents in structured JSON mode. the signature covers the data and every
+/// attribute, including the other jacs* extensions, so the event stays verifiable as it
+/// passes through standard CloudEvents tooling
+pub trait CloudEvents {
+    /// returns the event with jacsagentid (id:version), jacspublickeyhash,
+    /// jacssigningalgorithm and jacssignature (base64) extension attributes added
+    fn sign_cloudevent(&mut self, cloudevent_json: &str) -> Result<String, Box<dyn Error>>;
+
+    /// checks jacssignature with the signer's public key from the key directory, found by
+    /// jacspublickeyhash. the key must be the one registered in the saved agent document
+    /// jacsagentid names, so the returned id:version is the verified signer
+    fn verify_cloudevent(&self, cloudevent_json: &str) -> Result<String, Box<dyn Error>>;
+}
+
+impl CloudEvents for Agent {
+    fn sign_cloudevent(&mut self, cloudevent_json: &str) -> Result<String, Box<dyn Error>> {
+        let mut event = parse_cloudevent(cloudevent_json)?;
+        event[CLOUDEVENT_AGENT_ID_ATTRIBUTE] = json!(self.get_lookup_id()?);
+        event[CLOUDEVENT_PUBLIC_KEY_HASH_ATTRIBUTE] =
+            json!(hash_public_key(self.get_public_key()?));
+        event[CLOUDEVENT_SIGNING_ALGORITHM_ATTRIBUTE] = json!(self.key_algorithm()?);
+        let signature = self.sign_string(&cloudevent_signed_content(&event), None)?;
+        event[CLOUDEVENT_SIGNATURE_ATTRIBUTE] = json!(signature);
+        Ok(serde_json::to_string(&event)?)
+    }
+
+    fn verify_cloudevent(&self, cloudevent_json: &str) -> Result<String, Box<dyn Error>> {
+        let event = parse_cloudevent(cloudevent_json)?;
+        let attribute = |name: &str| {
+            event
+                .get_str(name)
+                .ok_or_else(|| format!("CloudEvent has no {} attribute", name))
+        };
+        let signature = attribute(CLOUDEVENT_SIGNATURE_ATTRIBUTE)?;
+        let signer = attribute(CLOUDEVENT_AGENT_ID_ATTRIBUTE)?;
+        let public_key_hash = attribute(CLOUDEVENT_PUBLIC_KEY_HASH_ATTRIBUTE)?;
+        let signing_algorithm = attribute(CLOUDEVENT_SIGNING_ALGORITHM_ATTRIBUTE)?;
+
+        let public_key = self.fs_load_public_key(&public_key_hash)?;
+        if !hashes_match(&public_key_hash, &hash_public_key(public_key.clone())) {
+            return Err(format!("wrong public key for {}", signer).into());
+        }
+        // bind the key to the claimed agent, or any stored key could speak for anyone
+        let agent_value = self.schema.validate_agent(&self.fs_agent_load(&signer)?)?;
+        self.verify_hash(&agent_value)?;
+        let registered_key_hash = agent_value[AGENT_SIGNATURE_FIELDNAME]
+            .get_str("publicKeyHash")
+            .unwrap_or_default();
+        if !hashes_match(&registered_key_hash, &public_key_hash) {
+            return Err(format!(
+                "{} is not the registered key of {}",
+                public_key_hash, signer
+            )
+            .into());
+        }
+        self.verify_string(
+            &cloudevent_signed_content(&event),
+            &signature,
+            public_key,
+            Some(signing_algorithm),
+            None,
+        )?;
+        Ok(signer)
+    }
+}
+
+fn parse_cloudevent(cloudevent_json: &str) -> Result<Value, Box<dyn Error>> {
+    let event = parse_untrusted_json(cloudevent_json)?;
+    if !event.is_object() {
+        return Err("a structured CloudEvent must be a JSON object".into());
+    }
+    for required in CLOUDEVENT_REQUIRED_ATTRIBUTES {
+        if event.get_str(required).is_none() {
+            return Err(format!("CloudEvent is missing the {} attribute", required).into());
+        }
+    }
+    Ok(event)
+}
+
+/// everything but jacssignature, with keys sorted and no whitespace
+fn cloudevent_signed_content(event: &Value) -> String {
+    let mut content = event.clone();
+    if let Some(attributes) = content.as_object_mut() {
+        attributes.remove(CLOUDEVENT_SIGNATURE_ATTRIBUTE);
+    }
+    content.to_string()
+}
@@ -1,29 +1,54 @@
 use crate::agent::agreement::subtract_vecs;
 use crate::agent::boilerplate::BoilerPlate;
-use crate::agent::loaders::FileLoader;
-use crate::agent::security::check_data_directory;
+use crate::agent::loaders::{encode_attachment_contents, FileLoader};
+use crate::agent::security::{check_data_directory, quarantine_document};
 use crate::agent::Agent;
 use crate::agent::AGENT_AGREEMENT_FIELDNAME;
+use crate::agent::AGENT_SIGNATURE_FIELDNAME;
 use crate::agent::DOCUMENT_AGENT_SIGNATURE_FIELDNAME;
+use crate::agent::JACS_HASH_ALGORITHM_FIELDNAME;
 use crate::agent::SHA256_FIELDNAME;
-use crate::crypt::hash::hash_string;
+use crate::agent::{
+    JACS_PREVIOUS_VERSION_FIELDNAME, JACS_VERSION_DATE_FIELDNAME, JACS_VERSION_FIELDNAME,
+};
+use crate::crypt::hash::{
+    configured_hash_algorithm, hash_public_key, hash_string, hash_string_with, hashes_match,
+    HashAlgorithm,
+};
+use crate::crypt::{enforce_pq_only, require_post_quantum, SignatureEncoding};
 use crate::schema::utils::ValueExt;
+use crate::schema::utils::{parse_untrusted_json, MAX_JSON_DEPTH};
+use crate::schema::{configured_id_strategy, IdStrategy};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
 use chrono::Local;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use difference::{Changeset, Difference};
 use flate2::read::GzDecoder;
-use log::error;
+use json_patch::PatchOperation;
+use log::{debug, error, info};
 use regex::Regex;
 use serde_json::json;
 use serde_json::Value;
 use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::env;
 use std::error::Error;
 use std::fmt;
 use std::fs::{self, File};
 use std::io::Read;
 use std::io::Write;
 use std::path::Path;
-use uuid::Uuid;
+use std::str::FromStr;
+
+/// fields set by update_document that a patch may not write to directly
+const JACS_MANAGED_FIELDS: [&str; 6] = [
+    "jacsId",
+    JACS_VERSION_FIELDNAME,
+    JACS_VERSION_DATE_FIELDNAME,
+    JACS_PREVIOUS_VERSION_FIELDNAME,
+    DOCUMENT_AGENT_SIGNATURE_FIELDNAME,
+    SHA256_FIELDNAME,
+];
 
 #[derive(Clone, Debug)]
 pub struct JACSDocument {
@@ -101,6 +126,26 @@ impl JACSDocument {
         return Err("no agreement or agents in agreement".into());
     }
 
+    /// for an ordered agreement, the first agent in agentIDs that has not signed.
+    /// None when the agreement is unordered or everyone has signed
+    pub fn agreement_next_expected_signer(
+        &self,
+        agreement_fieldname: Option<String>,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let agreement_fieldname_key = match agreement_fieldname {
+            Some(key) => key,
+            _ => AGENT_AGREEMENT_FIELDNAME.to_string(),
+        };
+        let ordered = self.value[&agreement_fieldname_key]["ordered"]
+            .as_bool()
+            .unwrap_or(false);
+        if !ordered {
+            return Ok(None);
+        }
+        let unsigned = self.agreement_unsigned_agents(Some(agreement_fieldname_key))?;
+        Ok(unsigned.into_iter().next())
+    }
+
     pub fn signing_agent(&self) -> Result<String, Box<dyn Error>> {
         let value: &serde_json::Value = &self.value;
         if let Some(jacs_signature) = value.get(DOCUMENT_AGENT_SIGNATURE_FIELDNAME) {
@@ -142,6 +187,477 @@ impl fmt::Display for JACSDocument {
     }
 }
 
+/// sha256 of the whole document, signature and jacsSha256 included, serialized with
+/// sorted keys and no whitespace so formatting differences hash the same
+pub fn canonical_content_hash(value: &Value) -> String {
+    hash_string(&value.to_string())
+}
+
+/// jacsVersionDate as a timestamp, used to order documents.
+/// None when it's missing or not RFC 3339, which sorts ahead of every dated document
+pub fn document_version_date(value: &Value) -> Option<DateTime<Utc>> {
+    let version_date = value.get_str(JACS_VERSION_DATE_FIELDNAME)?;
+    DateTime::parse_from_rfc3339(&version_date)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// default for JACS_MAX_DOCUMENT_BYTES, 64 MiB
+pub const DEFAULT_MAX_DOCUMENT_BYTES: usize = 64 * 1024 * 1024;
+
+/// largest document string accepted before parsing, from JACS_MAX_DOCUMENT_BYTES
+pub fn max_document_bytes() -> usize {
+    env::var("JACS_MAX_DOCUMENT_BYTES")
+        .ok()
+        .and_then(|limit| limit.trim().parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_DOCUMENT_BYTES)
+}
+
+/// rejects oversized input up front so a huge document can't exhaust memory in the parser
+pub(crate) fn check_document_size(
+    document_string: &str,
+    max_document_bytes: usize,
+) -> Result<(), Box<dyn Error>> {
+    if document_string.len() > max_document_bytes {
+        let error_message = format!(
+            "document is {} bytes, larger than the {} byte limit (JACS_MAX_DOCUMENT_BYTES)",
+            document_string.len(),
+            max_document_bytes
+        );
+        error!("{}", error_message);
+        return Err(error_message.into());
+    }
+    Ok(())
+}
+
+/// the (agentID, agentVersion) a document's jacsSignature CLAIMS, read without checking
+/// the hash or signature. UNVERIFIED: anyone can write these fields, so use the result only
+/// to route or shard documents ahead of verification, never for authorization
+pub fn document_claimed_signer(document_json: &str) -> Result<(String, String), Box<dyn Error>> {
+    check_document_size(document_json, max_document_bytes())?;
+    let value = parse_untrusted_json(document_json)?;
+    let signature = value
+        .get(DOCUMENT_AGENT_SIGNATURE_FIELDNAME)
+        .ok_or("document has no jacsSignature")?;
+    let agent_id = signature
+        .get_str("agentID")
+        .ok_or("jacsSignature has no agentID")?;
+    let agent_version = signature
+        .get_str("agentVersion")
+        .ok_or("jacsSignature has no agentVersion")?;
+    Ok((agent_id, agent_version))
+}
+
+/// checks bytes extracted from a document's attachment against the sha256 recorded for it in
+/// jacsFiles[attachment_index], without loading or verifying the rest of the document.
+/// the recorded hash is only as trustworthy as the document, so verify that separately
+pub fn verify_attachment(
+    document_json: &str,
+    attachment_index: usize,
+    attachment_bytes: &[u8],
+) -> Result<bool, Box<dyn Error>> {
+    check_document_size(document_json, max_document_bytes())?;
+    let value = parse_untrusted_json(document_json)?;
+    let attachment = value
+        .get("jacsFiles")
+        .and_then(|files| files.as_array())
+        .and_then(|files| files.get(attachment_index))
+        .ok_or(format!("document has no attachment {}", attachment_index))?;
+    let expected_hash = attachment
+        .get_str("sha256")
+        .ok_or(format!("attachment {} has no sha256", attachment_index))?;
+    let mut hasher = Sha256::new();
+    hasher.update(encode_attachment_contents(attachment_bytes)?);
+    let actual_hash = format!("{:x}", hasher.finalize());
+    Ok(hashes_match(&expected_hash, &actual_hash))
+}
+
+/// substitutes {{name}} placeholders in a JSON template. a string that is only a placeholder
+/// becomes the variable's value, whatever its type; a placeholder inside a longer string is
+/// replaced by the value's text. substitution happens on parsed JSON, so values cannot
+/// change the document's structure. any placeholder left without a variable is an error
+pub fn fill_template(
+    template_json: &str,
+    variables: &HashMap<String, Value>,
+) -> Result<Value, Box<dyn Error>> {
+    check_document_size(template_json, max_document_bytes())?;
+    let template = parse_untrusted_json(template_json)?;
+    let placeholder = Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_.-]*)\s*\}\}")?;
+    let mut unfilled: BTreeSet<String> = BTreeSet::new();
+    let filled = fill_template_value(&template, variables, &placeholder, &mut unfilled);
+    if !unfilled.is_empty() {
+        return Err(format!("template placeholders without a value: {:?}", unfilled).into());
+    }
+    Ok(filled)
+}
+
+fn fill_template_value(
+    value: &Value,
+    variables: &HashMap<String, Value>,
+    placeholder: &Regex,
+    unfilled: &mut BTreeSet<String>,
+) -> Value {
+    match value {
+        Value::String(text) => {
+            if let Some(captures) = placeholder.captures(text) {
+                if captures[0].len() == text.len() {
+                    return match variables.get(&captures[1]) {
+                        Some(variable) => variable.clone(),
+                        None => {
+                            unfilled.insert(captures[1].to_string());
+                            value.clone()
+                        }
+                    };
+                }
+            }
+            let replaced = placeholder.replace_all(text, |captures: &regex::Captures| {
+                match variables.get(&captures[1]) {
+                    Some(Value::String(variable)) => variable.clone(),
+                    Some(variable) => variable.to_string(),
+                    None => {
+                        unfilled.insert(captures[1].to_string());
+                        captures[0].to_string()
+                    }
+                }
+            });
+            Value::String(replaced.into_owned())
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| fill_template_value(item, variables, placeholder, unfilled))
+                .collect(),
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, item)| {
+                    (
+                        key.clone(),
+                        fill_template_value(item, variables, placeholder, unfilled),
+                    )
+                })
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+/// shows what the document's jacsSignature covers, to debug signatures that fail or
+/// that another implementation computes differently. returns JSON
+/// {signed_fields, excluded_fields, canonical_preview}: the fields whose values are in the
+/// exact string the signature was made over, every other top-level field, and that string.
+/// only string values are signed, so a field the signature lists (or picks by default) that
+/// is missing or holds a number, bool, object or array is reported as excluded. nothing is
+/// verified
+pub fn explain_signature(document_json: &str) -> Result<String, Box<dyn Error>> {
+    check_document_size(document_json, max_document_bytes())?;
+    let value = parse_untrusted_json(document_json)?;
+    let fields = value.as_object().ok_or("document is not a JSON object")?;
+    let (canonical_preview, selected_fields) =
+        signed_content(&value, DOCUMENT_AGENT_SIGNATURE_FIELDNAME)?;
+    let (signed_fields, missing_fields): (Vec<String>, Vec<String>) = selected_fields
+        .into_iter()
+        .partition(|field| fields.get(field).is_some_and(Value::is_string));
+    let excluded_fields: Vec<&String> = fields
+        .keys()
+        .filter(|key| !signed_fields.contains(key))
+        .chain(
+            missing_fields
+                .iter()
+                .filter(|field| !fields.contains_key(field.as_str())),
+        )
+        .collect();
+    Ok(serde_json::to_string(&json!({
+        "signed_fields": signed_fields,
+        "excluded_fields": excluded_fields,
+        "canonical_preview": canonical_preview,
+    }))?)
+}
+
+/// the exact bytes the signing algorithm ran over for the signature in `signature_field`
+/// (jacsSignature, jacsRegistration, ...), rebuilt from the document as verification does,
+/// so a disputed signature can be checked independently in another tool. nothing is verified
+pub fn signed_bytes_for_field(
+    document_json: &str,
+    signature_field: &str,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    check_document_size(document_json, max_document_bytes())?;
+    let value = parse_untrusted_json(document_json)?;
+    let (signed_string, _) = signed_content(&value, signature_field)?;
+    Ok(signed_string.into_bytes())
+}
+
+/// the string a signature covers and the fields that went into it, from the fields the
+/// signature records or, when it records none, the ones chosen by default
+fn signed_content(
+    value: &Value,
+    signature_field: &str,
+) -> Result<(String, Vec<String>), Box<dyn Error>> {
+    let signature = value
+        .get(signature_field)
+        .filter(|signature| signature.is_object())
+        .ok_or(format!("document has no {} signature", signature_field))?;
+    Agent::signature_format(signature)?;
+    let recorded_fields: Option<Vec<String>> = match signature.get("fields") {
+        Some(Value::Array(fields)) => Some(
+            fields
+                .iter()
+                .filter_map(|field| field.as_str().map(String::from))
+                .collect(),
+        ),
+        _ => None,
+    };
+    Agent::get_values_as_string(value, recorded_fields, &signature_field.to_string())
+}
+
+/// identifies one successful signature check. a signature over immutable content can't stop
+/// verifying, so a match means the crypto can be skipped. the key covers everything the
+/// check depended on: the hash of the full document as it is now (not the jacsSha256 it
+/// claims, so a tampered copy with the same id and version misses), the signature field,
+/// the fields checked, and the public key and algorithm used
+fn verified_signature_cache_key(
+    document_value: &Value,
+    signature_key_from: &String,
+    fields: Option<&Vec<String>>,
+    public_key: &[u8],
+    public_key_enc_type: Option<&String>,
+) -> String {
+    format!(
+        "{}:{}:{}:{}:{:?}:{}:{}",
+        document_value.get_str("jacsId").unwrap_or_default(),
+        document_value.get_str("jacsVersion").unwrap_or_default(),
+        canonical_content_hash(document_value),
+        signature_key_from,
+        fields,
+        hash_public_key(public_key.to_vec()),
+        public_key_enc_type.cloned().unwrap_or_default()
+    )
+}
+
+/// the base64 publicKey embedded in a signature, checked against its publicKeyHash
+fn embedded_public_key(signature: &Value) -> Result<Vec<u8>, Box<dyn Error>> {
+    let public_key = match signature.get_str("publicKey") {
+        Some(encoded) => STANDARD.decode(encoded)?,
+        None => return Err("signature has no embedded publicKey".into()),
+    };
+    let declared_hash = signature.get_str("publicKeyHash").unwrap_or_default();
+    if !hashes_match(&declared_hash, &hash_public_key(public_key.clone())) {
+        let error_message = format!(
+            "embedded public key does not match publicKeyHash {}",
+            declared_hash
+        );
+        error!("{}", error_message);
+        return Err(error_message.into());
+    }
+    Ok(public_key)
+}
+
+/// the jacsSha256 value JACS computes for a JSON document, as checked by verify_hash.
+///
+/// every top-level field except jacsSha256 itself is included, so jacsId, jacsVersion,
+/// jacsVersionDate, jacsSignature, agreements and jacsFiles all count. the document is
+/// serialized with keys sorted at every level and no whitespace, then hex encoded using the
+/// digest named in jacsHashAlgorithm, SHA-256 when that field is absent.
+/// create_document_and_load hashes after adding the header fields and signature, so to
+/// reproduce a stored jacsSha256 hash the complete stored document
+pub fn document_content_hash(document_json: &str) -> Result<String, Box<dyn Error>> {
+    let value: Value = serde_json::from_str(document_json)?;
+    value_content_hash(&value)
+}
+
+/// encodes a JACS JSON document as CBOR for compact transport. keys are written in sorted
+/// order, as the hashes and signatures see them, so document_from_cbor gives back a
+/// document that still verifies
+pub fn document_to_cbor(document_json: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let value = parse_untrusted_json(document_json)?;
+    if !value.is_object() {
+        return Err("a JACS document must be a JSON object".into());
+    }
+    let mut cbor: Vec<u8> = Vec::new();
+    ciborium::ser::into_writer(&value, &mut cbor)?;
+    Ok(cbor)
+}
+
+/// decodes CBOR from document_to_cbor back to JSON. maps must have unique text keys and
+/// nesting is capped at MAX_JSON_DEPTH, as for JSON input
+pub fn document_from_cbor(cbor: &[u8]) -> Result<String, Box<dyn Error>> {
+    let cbor_value: ciborium::value::Value =
+        ciborium::de::from_reader_with_recursion_limit(cbor, MAX_JSON_DEPTH)
+            .map_err(|e| format!("invalid CBOR document: {}", e))?;
+    check_cbor_map_keys(&cbor_value)?;
+    let value: Value = cbor_value
+        .deserialized()
+        .map_err(|e| format!("CBOR document is not representable as JSON: {}", e))?;
+    if !value.is_object() {
+        return Err("a JACS document must be a JSON object".into());
+    }
+    Ok(serde_json::to_string(&value)?)
+}
+
+fn check_cbor_map_keys(value: &ciborium::value::Value) -> Result<(), Box<dyn Error>> {
+    match value {
+        ciborium::value::Value::Map(entries) => {
+            let mut seen = std::collections::HashSet::new();
+            for (key, entry) in entries {
+                let key = key.as_text().ok_or("CBOR map keys must be text")?;
+                if !seen.insert(key) {
+                    return Err(format!("duplicate key in CBOR document: {}", key).into());
+                }
+                check_cbor_map_keys(entry)?;
+            }
+        }
+        ciborium::value::Value::Array(items) => {
+            for item in items {
+                check_cbor_map_keys(item)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// records the configured digest in jacsHashAlgorithm before signing. a document hashed
+/// with the sha256 default is left without the field, so legacy documents keep the same
+/// fields, and agreement hashes, across updates
+pub(crate) fn stamp_hash_algorithm(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    let hash_algorithm = configured_hash_algorithm()?;
+    if hash_algorithm != HashAlgorithm::Sha256 || value.get(JACS_HASH_ALGORITHM_FIELDNAME).is_some()
+    {
+        value[JACS_HASH_ALGORITHM_FIELDNAME] = json!(hash_algorithm.to_string());
+    }
+    Ok(())
+}
+
+fn value_content_hash(value: &Value) -> Result<String, Box<dyn Error>> {
+    let hash_algorithm = match value.get_str(JACS_HASH_ALGORITHM_FIELDNAME) {
+        Some(algorithm) => HashAlgorithm::from_str(&algorithm).map_err(|_| {
+            format!(
+                "unsupported {} {}",
+                JACS_HASH_ALGORITHM_FIELDNAME, algorithm
+            )
+        })?,
+        None => HashAlgorithm::Sha256,
+    };
+    let mut doc_copy = value.clone();
+    doc_copy
+        .as_object_mut()
+        .map(|obj| obj.remove(SHA256_FIELDNAME));
+    let doc_string = serde_json::to_string(&doc_copy)?;
+    Ok(hash_string_with(&doc_string, hash_algorithm))
+}
+
+/// most checks the verification cache remembers. past this the oldest are forgotten, so
+/// a long-running verifier's memory stays bounded
+pub const MAX_VERIFIED_SIGNATURES: usize = 10_000;
+
+/// successful checks remembered by verify_document_signature, oldest evicted first
+#[derive(Debug, Default)]
+pub(crate) struct VerifiedSignatureCache {
+    keys: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl VerifiedSignatureCache {
+    fn contains(&self, cache_key: &str) -> bool {
+        self.keys.contains(cache_key)
+    }
+
+    fn insert(&mut self, cache_key: String) {
+        if !self.keys.insert(cache_key.clone()) {
+            return;
+        }
+        self.order.push_back(cache_key);
+        while self.order.len() > MAX_VERIFIED_SIGNATURES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.keys.clear();
+        self.order.clear();
+    }
+}
+
+impl Agent {
+    /// turns on caching of successful verify_document_signature checks, off by default.
+    /// worthwhile when the same document versions are verified over and over; only
+    /// successes are cached and each entry is tied to the exact content and key checked.
+    /// at most MAX_VERIFIED_SIGNATURES are kept, oldest dropped first. a cached success
+    /// is returned without re-checking, so call clear_verification_cache whenever keys
+    /// are revoked or rotated or the verification policy changes. disabling drops the cache
+    pub fn set_verification_cache(&mut self, enabled: bool) {
+        self.verified_signatures = match enabled {
+            true => Some(self.verified_signatures.take().unwrap_or_default()),
+            false => None,
+        };
+    }
+
+    /// forgets every cached verification. needed after a key is revoked or rotated, or the
+    /// verification policy changes, since a cached success skips the checks it passed
+    pub fn clear_verification_cache(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(cache) = &self.verified_signatures {
+            cache.lock().map_err(|e| e.to_string())?.clear();
+        }
+        Ok(())
+    }
+
+    /// the id strategy for documents this agent creates, used ahead of
+    /// JACS_DOCUMENT_ID_STRATEGY. None goes back to the env setting
+    pub fn set_id_strategy(&mut self, id_strategy: Option<IdStrategy>) {
+        self.id_strategy = id_strategy;
+    }
+
+    /// the agent's id strategy, else the configured one
+    fn id_strategy(&self) -> Result<IdStrategy, Box<dyn Error>> {
+        match self.id_strategy {
+            Some(id_strategy) => Ok(id_strategy),
+            None => configured_id_strategy(),
+        }
+    }
+
+    /// attaches files, then signs, hashes and stores a freshly created document
+    fn sign_and_load_new_document(
+        &mut self,
+        mut instance: Value,
+        attachments: Option<Vec<String>>,
+        embed: Option<bool>,
+    ) -> Result<JACSDocument, Box<dyn std::error::Error + 'static>> {
+        if let Some(attachment_list) = attachments {
+            let mut files_array: Vec<Value> = Vec::new();
+
+            // Iterate over each attachment
+            for attachment_path in attachment_list {
+                let final_embed = embed.unwrap_or(false);
+                let file_json = self
+                    .create_file_json(&attachment_path, final_embed)
+                    .unwrap();
+
+                // Add the file JSON to the files array
+                files_array.push(file_json);
+            }
+
+            // Create a new "files" field in the document
+            let instance_map = instance.as_object_mut().unwrap();
+            instance_map.insert("jacsFiles".to_string(), Value::Array(files_array));
+        }
+
+        stamp_hash_algorithm(&mut instance)?;
+        // sign document
+        instance[DOCUMENT_AGENT_SIGNATURE_FIELDNAME] = self.signing_procedure(
+            &instance,
+            None,
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+        )?;
+        // hash document
+        let document_hash = self.hash_doc(&instance)?;
+        instance[SHA256_FIELDNAME] = json!(format!("{}", document_hash));
+        self.store_jacs_document(&instance)
+    }
+}
+
 pub trait Document {
     fn verify_document_signature(
         &mut self,
@@ -152,6 +668,33 @@ pub trait Document {
         public_key_enc_type: Option<String>,
     ) -> Result<(), Box<dyn Error>>;
 
+    /// tries each (public key, algorithm) pair against the document signature,
+    /// returning the index of the first candidate that verifies.
+    /// useful for documents signed on either side of a key rotation
+    fn verify_document_multikey(
+        &self,
+        document_string: &str,
+        candidate_keys: Vec<(Vec<u8>, String)>,
+    ) -> Result<usize, Box<dyn Error>>;
+
+    /// verifies a document against a pinned snapshot of its signer's agent document, for
+    /// archived evidence whose signer has since rotated keys. `signer_public_key` is the
+    /// key pinned with the snapshot and must hash to its jacsSignature.publicKeyHash; the
+    /// snapshot's self-signature is checked with that key, then the document is verified
+    /// with exactly that key and no other lookup
+    fn verify_document_against_agent(
+        &self,
+        document_string: &str,
+        signer_agent_json: &str,
+        signer_public_key: Vec<u8>,
+    ) -> Result<bool, Box<dyn Error>>;
+
+    /// verifies a self-contained document using the base64 public key embedded in its
+    /// signature as publicKey. the key must hash to the signature's publicKeyHash.
+    /// Err when the document embeds no key
+    fn verify_document_self_contained(&self, document_string: &str)
+        -> Result<bool, Box<dyn Error>>;
+
     fn validate_document_with_custom_schema(
         &self,
         schema_path: &str,
@@ -163,13 +706,138 @@ pub trait Document {
         attachments: Option<Vec<String>>,
         embed: Option<bool>,
     ) -> Result<JACSDocument, Box<dyn std::error::Error + 'static>>;
+    /// create_document_and_load for a consumer that has not upgraded: with a
+    /// `header_version` such as "v1" the document is validated against, and its $schema set
+    /// to, that version's embedded header schema. None uses the agent's own header version
+    fn create_document_and_load_with_version(
+        &mut self,
+        json: &String,
+        attachments: Option<Vec<String>>,
+        embed: Option<bool>,
+        header_version: Option<&str>,
+    ) -> Result<JACSDocument, Box<dyn Error>>;
+    /// fills a template's {{name}} placeholders from `variables` with fill_template, then
+    /// validates, signs and loads the result like create_document_and_load
+    fn create_document_from_template(
+        &mut self,
+        template_json: &str,
+        variables: &HashMap<String, Value>,
+    ) -> Result<JACSDocument, Box<dyn Error>>;
+    /// first half of signing a new document with a key JACS never holds, e.g. in an HSM or
+    /// KMS. the document gets its ids and dates as in create_document_and_load, but nothing
+    /// is signed or loaded. returns {document, fields, signing_payload, digest,
+    /// digest_algorithm}: signing_payload is the exact string to sign, which Ed25519 and
+    /// Dilithium sign as is, and digest its sha256 in hex for an HSM that takes RSA-PSS
+    /// input prehashed
+    fn prepare_external_signature(
+        &mut self,
+        document_string: &str,
+    ) -> Result<String, Box<dyn Error>>;
+    /// second half: `prepared_document` is the document from prepare_external_signature,
+    /// unchanged, and `signature_base64` the signature over its signing_payload by
+    /// `public_key` with `algorithm`. the signature is checked before the document is
+    /// hashed and loaded. the signature names this agent as the signer, so `public_key`
+    /// must be the one registered in this agent's jacsSignature.publicKeyHash
+    fn apply_external_signature(
+        &mut self,
+        prepared_document: &str,
+        signature_base64: &str,
+        public_key: Vec<u8>,
+        algorithm: String,
+    ) -> Result<JACSDocument, Box<dyn Error>>;
+    /// create_document_and_load without header schema validation, for trusted internal
+    /// producers whose documents all share a known shape. ids, hashes and the signature
+    /// are still added, but nothing stops a malformed document from being signed and
+    /// stored, so never use this for input that crosses a trust boundary
+    fn create_document_and_load_unchecked(
+        &mut self,
+        json: &String,
+        attachments: Option<Vec<String>>,
+        embed: Option<bool>,
+    ) -> Result<JACSDocument, Box<dyn std::error::Error + 'static>>;
 
     fn load_document(&mut self, document_string: &String) -> Result<JACSDocument, Box<dyn Error>>;
+    /// load_document with a size limit for this call only, for the rare document that
+    /// legitimately exceeds JACS_MAX_DOCUMENT_BYTES
+    fn load_document_with_max_bytes(
+        &mut self,
+        document_string: &String,
+        max_document_bytes: usize,
+    ) -> Result<JACSDocument, Box<dyn Error>>;
+    /// ingestion in one call: verifies the document against its signer's key from the key
+    /// store, then saves it under documents/ and loads it, returning its key. the file is
+    /// written before the document is loaded, so nothing is accepted that was not persisted.
+    /// a document that fails verification is written to the quarantine directory instead
+    /// and the error names where
+    fn verify_and_store(&mut self, document_string: &String) -> Result<String, Box<dyn Error>>;
+    /// verifies the document against its signer's key from the key store, and fails
+    /// whatever the signature's validity if jacsSignature.signingAlgorithm is below
+    /// post-quantum strength. JACS_PQ_ONLY applies the same rule to every verification
+    fn verify_document_pq_only(&self, document_string: &str) -> Result<bool, Box<dyn Error>>;
+    /// checks the document against its embedded jacsSha256 only.
+    /// authenticity is NOT checked: no key is resolved and the signature is ignored,
+    /// so Ok(true) only means the content was not altered after hashing.
+    fn verify_hash_only(&self, document_string: &str) -> Result<bool, Box<dyn Error>>;
+    /// full hash and signature verification of a document string. on success returns the
+    /// canonical content hash, which a caller can store and hand to verify_document_cached.
+    /// uses the agent's own key when public_key is None
+    fn verify_document_and_hash(
+        &self,
+        document_string: &str,
+        public_key: Option<Vec<u8>>,
+        public_key_enc_type: Option<String>,
+    ) -> Result<String, Box<dyn Error>>;
+    /// verify_document_and_hash, then checks the document against the embedded schema the
+    /// caller expects, so a validly signed document of the wrong shape is still rejected.
+    /// returns the document only if both pass
+    fn verify_document_typed(
+        &self,
+        document_string: &str,
+        expected_schema: &str,
+        public_key: Option<Vec<u8>>,
+        public_key_enc_type: Option<String>,
+    ) -> Result<Value, Box<dyn Error>>;
+    /// verify_document_and_hash for a mixed-version fleet: fields the header or signature
+    /// schemas do not know are reported rather than rejected, while hash and signature are
+    /// checked as strictly as ever, and they cover the unknown fields too. returns
+    /// {valid, unknown_fields}, plus error when the hash or signature fails
+    fn verify_document_lenient(
+        &self,
+        document_string: &str,
+        public_key: Option<Vec<u8>>,
+        public_key_enc_type: Option<String>,
+    ) -> Result<String, Box<dyn Error>>;
+    /// fast path for documents already verified with verify_document_and_hash.
+    /// this trades the signature check for a hash comparison, so expected_hash must come
+    /// from storage the caller trusts. Ok(false) means a cache miss, verify normally
+    fn verify_document_cached(
+        &self,
+        document_string: &str,
+        expected_hash: &str,
+    ) -> Result<bool, Box<dyn Error>>;
+    /// signs the document's content and returns only the jacsSignature object as JSON,
+    /// for protocols that send the signature out of band. the document is not modified,
+    /// loaded or stored, and any signature it already carries is left out of the signed fields.
+    /// `encoding` defaults to base64
+    fn sign_document_detached(
+        &mut self,
+        document_string: &str,
+        encoding: Option<SignatureEncoding>,
+    ) -> Result<String, Box<dyn Error>>;
+    /// checks a signature from sign_document_detached against the document it was made for
+    fn verify_detached(
+        &self,
+        document_string: &str,
+        signature_json: &str,
+        public_key: Vec<u8>,
+        public_key_enc_type: String,
+    ) -> Result<(), Box<dyn Error>>;
     fn remove_document(&mut self, document_key: &String) -> Result<JACSDocument, Box<dyn Error>>;
     fn copy_document(&mut self, document_key: &String) -> Result<JACSDocument, Box<dyn Error>>;
     fn store_jacs_document(&mut self, value: &Value) -> Result<JACSDocument, Box<dyn Error>>;
     fn hash_doc(&self, doc: &Value) -> Result<String, Box<dyn Error>>;
     fn get_document(&self, document_key: &String) -> Result<JACSDocument, Box<dyn Error>>;
+    /// keys of loaded documents, oldest jacsVersionDate first, ties broken by key
     fn get_document_keys(&mut self) -> Vec<String>;
     fn diff_json_strings(
         &self,
@@ -191,6 +859,13 @@ pub trait Document {
         attachments: Option<Vec<String>>,
         embed: Option<bool>,
     ) -> Result<JACSDocument, Box<dyn Error>>;
+    /// applies an RFC 6902 JSON Patch to the stored document and saves the result
+    /// as a new, re-signed version. patches may not touch JACS-managed fields
+    fn patch_document(
+        &mut self,
+        document_key: &String,
+        patch_json: &str,
+    ) -> Result<JACSDocument, Box<dyn Error>>;
     fn create_file_json(
         &mut self,
         filepath: &String,
@@ -310,40 +985,137 @@ impl Document for Agent {
         attachments: Option<Vec<String>>,
         embed: Option<bool>,
     ) -> Result<JACSDocument, Box<dyn std::error::Error + 'static>> {
-        let mut instance = self.schema.create(json)?;
+        check_document_size(json, max_document_bytes())?;
+        let instance = self.schema.create(json, self.id_strategy()?)?;
+        self.sign_and_load_new_document(instance, attachments, embed)
+    }
 
-        if let Some(attachment_list) = attachments {
-            let mut files_array: Vec<Value> = Vec::new();
+    fn create_document_from_template(
+        &mut self,
+        template_json: &str,
+        variables: &HashMap<String, Value>,
+    ) -> Result<JACSDocument, Box<dyn Error>> {
+        let filled = fill_template(template_json, variables)?;
+        self.create_document_and_load(&filled.to_string(), None, None)
+    }
 
-            // Iterate over each attachment
-            for attachment_path in attachment_list {
-                let final_embed = embed.unwrap_or(false);
-                let file_json = self
-                    .create_file_json(&attachment_path, final_embed)
-                    .unwrap();
+    fn create_document_and_load_with_version(
+        &mut self,
+        json: &String,
+        attachments: Option<Vec<String>>,
+        embed: Option<bool>,
+        header_version: Option<&str>,
+    ) -> Result<JACSDocument, Box<dyn Error>> {
+        let header_version = match header_version {
+            Some(header_version) => header_version,
+            None => return self.create_document_and_load(json, attachments, embed),
+        };
+        check_document_size(json, max_document_bytes())?;
+        let instance =
+            self.schema
+                .create_for_header_version(json, header_version, self.id_strategy()?)?;
+        self.sign_and_load_new_document(instance, attachments, embed)
+    }
 
-                // Add the file JSON to the files array
-                files_array.push(file_json);
-            }
+    fn create_document_and_load_unchecked(
+        &mut self,
+        json: &String,
+        attachments: Option<Vec<String>>,
+        embed: Option<bool>,
+    ) -> Result<JACSDocument, Box<dyn std::error::Error + 'static>> {
+        check_document_size(json, max_document_bytes())?;
+        let instance = self.schema.create_unchecked(json, self.id_strategy()?)?;
+        self.sign_and_load_new_document(instance, attachments, embed)
+    }
 
-            // Create a new "files" field in the document
-            let instance_map = instance.as_object_mut().unwrap();
-            instance_map.insert("jacsFiles".to_string(), Value::Array(files_array));
-        }
+    fn prepare_external_signature(
+        &mut self,
+        document_string: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        check_document_size(document_string, max_document_bytes())?;
+        let mut instance = self.schema.create(document_string, self.id_strategy()?)?;
+        stamp_hash_algorithm(&mut instance)?;
+        let (signing_payload, fields) = Agent::get_values_as_string(
+            &instance,
+            None,
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+        )?;
+        Ok(serde_json::to_string(&json!({
+            "document": instance,
+            "fields": fields,
+            "digest": hash_string(&signing_payload),
+            "digest_algorithm": HashAlgorithm::Sha256.to_string(),
+            "signing_payload": signing_payload,
+        }))?)
+    }
 
-        // sign document
-        instance[DOCUMENT_AGENT_SIGNATURE_FIELDNAME] = self.signing_procedure(
+    fn apply_external_signature(
+        &mut self,
+        prepared_document: &str,
+        signature_base64: &str,
+        public_key: Vec<u8>,
+        algorithm: String,
+    ) -> Result<JACSDocument, Box<dyn Error>> {
+        check_document_size(prepared_document, max_document_bytes())?;
+        let mut instance = parse_untrusted_json(prepared_document)?;
+        if !instance.is_object() || instance.get_str("jacsId").is_none() {
+            return Err("expected the document from prepare_external_signature".into());
+        }
+        if instance.get(DOCUMENT_AGENT_SIGNATURE_FIELDNAME).is_some() {
+            return Err("document is already signed".into());
+        }
+        // the signature names this agent, so only this agent's registered key may make it
+        let registered_key_hash = self
+            .value
+            .as_ref()
+            .and_then(|agent| agent.get(AGENT_SIGNATURE_FIELDNAME))
+            .and_then(|signature| signature.get_str("publicKeyHash"))
+            .ok_or("agent has no jacsSignature.publicKeyHash")?;
+        if !hashes_match(&registered_key_hash, &hash_public_key(public_key.clone())) {
+            return Err(format!(
+                "external public key is not this agent's registered key {}",
+                registered_key_hash
+            )
+            .into());
+        }
+        let (_, fields) = Agent::get_values_as_string(
             &instance,
             None,
             &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
         )?;
-        // hash document
+        instance[DOCUMENT_AGENT_SIGNATURE_FIELDNAME] = self.signature_object(
+            signature_base64.to_string(),
+            algorithm.clone(),
+            public_key.clone(),
+            fields,
+            SignatureEncoding::Base64,
+        )?;
+        self.signature_verification_procedure(
+            &instance,
+            None,
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+            public_key,
+            Some(algorithm),
+            None,
+            None,
+        )
+        .map_err(|e| format!("external signature does not verify: {}", e))?;
         let document_hash = self.hash_doc(&instance)?;
         instance[SHA256_FIELDNAME] = json!(format!("{}", document_hash));
-        Ok(self.store_jacs_document(&instance)?)
+        let instance = self.schema.validate_header(&instance.to_string())?;
+        self.store_jacs_document(&instance)
     }
 
     fn load_document(&mut self, document_string: &String) -> Result<JACSDocument, Box<dyn Error>> {
+        self.load_document_with_max_bytes(document_string, max_document_bytes())
+    }
+
+    fn load_document_with_max_bytes(
+        &mut self,
+        document_string: &String,
+        max_document_bytes: usize,
+    ) -> Result<JACSDocument, Box<dyn Error>> {
+        check_document_size(document_string, max_document_bytes)?;
         match &self.validate_header(&document_string) {
             Ok(value) => {
                 return self.store_jacs_document(&value);
@@ -355,22 +1127,204 @@ impl Document for Agent {
         }
     }
 
+    fn verify_and_store(&mut self, document_string: &String) -> Result<String, Box<dyn Error>> {
+        let value = match check_document_size(document_string, max_document_bytes())
+            .and_then(|_| self.verify_with_stored_key(document_string))
+        {
+            Ok(value) => value,
+            Err(e) => {
+                return Err(match quarantine_document(document_string, &e.to_string()) {
+                    Ok(path) => format!("document rejected and quarantined as {}: {}", path, e),
+                    Err(quarantine_error) => format!(
+                        "document rejected: {}, and could not be quarantined: {}",
+                        e, quarantine_error
+                    ),
+                }
+                .into());
+            }
+        };
+        let document_key = format!(
+            "{}:{}",
+            value.get_str("jacsId").ok_or("document has no jacsId")?,
+            value
+                .get_str("jacsVersion")
+                .ok_or("document has no jacsVersion")?
+        );
+        self.fs_document_save(&document_key, &serde_json::to_string_pretty(&value)?, None)?;
+        Ok(self.store_jacs_document(&value)?.getkey())
+    }
+
+    fn verify_document_pq_only(&self, document_string: &str) -> Result<bool, Box<dyn Error>> {
+        check_document_size(document_string, max_document_bytes())?;
+        let value = parse_untrusted_json(document_string)?;
+        let signing_algorithm = value
+            .get(DOCUMENT_AGENT_SIGNATURE_FIELDNAME)
+            .and_then(|signature| signature.get_str("signingAlgorithm"))
+            .ok_or("document signature has no signingAlgorithm")?;
+        require_post_quantum(&signing_algorithm)?;
+        self.verify_with_stored_key(document_string)?;
+        Ok(true)
+    }
+
+    fn verify_hash_only(&self, document_string: &str) -> Result<bool, Box<dyn Error>> {
+        check_document_size(document_string, max_document_bytes())?;
+        let value = self.schema.validate_header(document_string)?;
+        let result = self.verify_hash(&value)?;
+        info!(
+            "hash verified for {}:{}, signature NOT checked",
+            value.get_str("jacsId").unwrap_or_default(),
+            value.get_str("jacsVersion").unwrap_or_default()
+        );
+        Ok(result)
+    }
+
+    fn verify_document_and_hash(
+        &self,
+        document_string: &str,
+        public_key: Option<Vec<u8>>,
+        public_key_enc_type: Option<String>,
+    ) -> Result<String, Box<dyn Error>> {
+        check_document_size(document_string, max_document_bytes())?;
+        let value = self.schema.validate_header(document_string)?;
+        self.verify_hash(&value)?;
+        let used_public_key = match public_key {
+            Some(public_key) => public_key,
+            None => self.get_public_key()?,
+        };
+        self.signature_verification_procedure(
+            &value,
+            None,
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+            used_public_key,
+            public_key_enc_type,
+            None,
+            None,
+        )?;
+        Ok(canonical_content_hash(&value))
+    }
+
+    fn verify_document_typed(
+        &self,
+        document_string: &str,
+        expected_schema: &str,
+        public_key: Option<Vec<u8>>,
+        public_key_enc_type: Option<String>,
+    ) -> Result<Value, Box<dyn Error>> {
+        self.verify_document_and_hash(document_string, public_key, public_key_enc_type)?;
+        let value: Value = serde_json::from_str(document_string)?;
+        self.schema.validate_embedded(expected_schema, &value)?;
+        Ok(value)
+    }
+
+    fn verify_document_lenient(
+        &self,
+        document_string: &str,
+        public_key: Option<Vec<u8>>,
+        public_key_enc_type: Option<String>,
+    ) -> Result<String, Box<dyn Error>> {
+        check_document_size(document_string, max_document_bytes())?;
+        let (value, unknown_fields) = self.schema.validate_header_lenient(document_string)?;
+        let verified = self.verify_hash(&value).and_then(|_| {
+            let used_public_key = match public_key {
+                Some(public_key) => public_key,
+                None => self.get_public_key()?,
+            };
+            self.signature_verification_procedure(
+                &value,
+                None,
+                &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+                used_public_key,
+                public_key_enc_type,
+                None,
+                None,
+            )
+        });
+        let mut result = json!({
+            "valid": verified.is_ok(),
+            "unknown_fields": unknown_fields,
+        });
+        if let Err(e) = verified {
+            result["error"] = json!(e.to_string());
+        }
+        Ok(result.to_string())
+    }
+
+    fn verify_document_cached(
+        &self,
+        document_string: &str,
+        expected_hash: &str,
+    ) -> Result<bool, Box<dyn Error>> {
+        check_document_size(document_string, max_document_bytes())?;
+        let value: Value = serde_json::from_str(document_string)?;
+        Ok(hashes_match(expected_hash, &canonical_content_hash(&value)))
+    }
+
+    fn sign_document_detached(
+        &mut self,
+        document_string: &str,
+        encoding: Option<SignatureEncoding>,
+    ) -> Result<String, Box<dyn Error>> {
+        check_document_size(document_string, max_document_bytes())?;
+        let value: Value = serde_json::from_str(document_string)?;
+        if !value.is_object() {
+            return Err("detached signatures require a JSON object".into());
+        }
+        let signature = self.signing_procedure_encoded(
+            &value,
+            None,
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+            encoding.unwrap_or_default(),
+        )?;
+        Ok(serde_json::to_string(&signature)?)
+    }
+
+    fn verify_detached(
+        &self,
+        document_string: &str,
+        signature_json: &str,
+        public_key: Vec<u8>,
+        public_key_enc_type: String,
+    ) -> Result<(), Box<dyn Error>> {
+        check_document_size(document_string, max_document_bytes())?;
+        let mut value: Value = serde_json::from_str(document_string)?;
+        let signature: Value = serde_json::from_str(signature_json)?;
+        let fields: Vec<String> = match signature.get("fields") {
+            Some(fields) => serde_json::from_value(fields.clone())?,
+            None => return Err("detached signature has no fields".into()),
+        };
+        if !value.is_object() {
+            return Err("detached signatures require a JSON object".into());
+        }
+        value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME] = signature;
+        self.signature_verification_procedure(
+            &value,
+            Some(&fields),
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+            public_key,
+            Some(public_key_enc_type),
+            None,
+            None,
+        )
+        .map_err(|e| {
+            let error_message = format!("detached signature not verifiable: {}", e);
+            error!("{}", error_message);
+            error_message.into()
+        })
+    }
+
     fn hash_doc(&self, doc: &Value) -> Result<String, Box<dyn Error>> {
-        let mut doc_copy = doc.clone();
-        doc_copy
-            .as_object_mut()
-            .map(|obj| obj.remove(SHA256_FIELDNAME));
-        let doc_string = serde_json::to_string(&doc_copy)?;
-        Ok(hash_string(&doc_string))
+        value_content_hash(doc)
     }
 
     fn store_jacs_document(&mut self, value: &Value) -> Result<JACSDocument, Box<dyn Error>> {
-        let mut documents = self.documents.lock().expect("JACSDocument lock");
         let doc = JACSDocument {
-            id: value.get_str("jacsId").expect("REASON").to_string(),
-            version: value.get_str("jacsVersion").expect("REASON").to_string(),
+            id: value.get_str("jacsId").ok_or("document has no jacsId")?,
+            version: value
+                .get_str("jacsVersion")
+                .ok_or("document has no jacsVersion")?,
             value: Some(value.clone()).into(),
         };
+        let mut documents = self.documents.lock().expect("JACSDocument lock");
         let key = doc.getkey();
         documents.insert(key.clone(), doc.clone());
         Ok(doc)
@@ -394,7 +1348,12 @@ impl Document for Agent {
 
     fn get_document_keys(&mut self) -> Vec<String> {
         let documents = self.documents.lock().expect("documents lock");
-        return documents.keys().map(|k| k.to_string()).collect();
+        let mut dated_keys: Vec<(Option<DateTime<Utc>>, String)> = documents
+            .iter()
+            .map(|(key, document)| (document_version_date(&document.value), key.to_string()))
+            .collect();
+        dated_keys.sort();
+        dated_keys.into_iter().map(|(_, key)| key).collect()
     }
 
     /// pass in modified doc
@@ -407,6 +1366,7 @@ impl Document for Agent {
         embed: Option<bool>,
     ) -> Result<JACSDocument, Box<dyn Error>> {
         // check that old document is found
+        check_document_size(new_document_string, max_document_bytes())?;
         let mut new_document: Value = self.schema.validate_header(new_document_string)?;
         let error_message = format!("original document {} not found", document_key);
         let original_document = self.get_document(document_key).expect(&error_message);
@@ -459,13 +1419,14 @@ impl Document for Agent {
         //TODO  show diff
 
         // validate schema
-        let new_version = Uuid::new_v4().to_string();
         let last_version = &value["jacsVersion"];
         let versioncreated = Utc::now().to_rfc3339();
 
         new_document["jacsLastVersion"] = last_version.clone();
+        let new_version = self.id_strategy()?.new_id(&new_document);
         new_document["jacsVersion"] = json!(format!("{}", new_version));
         new_document["jacsVersionDate"] = json!(format!("{}", versioncreated));
+        stamp_hash_algorithm(&mut new_document)?;
         // get all fields but reserved
         new_document[DOCUMENT_AGENT_SIGNATURE_FIELDNAME] = self.signing_procedure(
             &new_document,
@@ -479,17 +1440,58 @@ impl Document for Agent {
         Ok(self.store_jacs_document(&new_document)?)
     }
 
+    fn patch_document(
+        &mut self,
+        document_key: &String,
+        patch_json: &str,
+    ) -> Result<JACSDocument, Box<dyn Error>> {
+        let patch: json_patch::Patch =
+            serde_json::from_str(patch_json).map_err(|e| format!("invalid JSON Patch: {}", e))?;
+        for operation in &patch.0 {
+            let paths = match operation {
+                PatchOperation::Add(op) => vec![&op.path],
+                PatchOperation::Remove(op) => vec![&op.path],
+                PatchOperation::Replace(op) => vec![&op.path],
+                PatchOperation::Move(op) => vec![&op.from, &op.path],
+                PatchOperation::Copy(op) => vec![&op.path],
+                PatchOperation::Test(_) => vec![],
+            };
+            for path in paths {
+                // the first pointer token is the top level field, with ~1 and ~0 unescaped
+                let field = path
+                    .split('/')
+                    .nth(1)
+                    .unwrap_or("")
+                    .replace("~1", "/")
+                    .replace("~0", "~");
+                if path.is_empty() || JACS_MANAGED_FIELDS.contains(&field.as_str()) {
+                    return Err(format!(
+                        "patch for {} may not modify JACS managed field {:?}",
+                        document_key, path
+                    )
+                    .into());
+                }
+            }
+        }
+
+        let mut value = self.get_document(document_key)?.value;
+        json_patch::patch(&mut value, &patch)
+            .map_err(|e| format!("could not apply patch to {}: {}", document_key, e))?;
+        self.update_document(document_key, &serde_json::to_string(&value)?, None, None)
+    }
+
     /// copys document without modifications
     fn copy_document(&mut self, document_key: &String) -> Result<JACSDocument, Box<dyn Error>> {
         let original_document = self.get_document(document_key).unwrap();
         let mut value = original_document.value;
-        let new_version = Uuid::new_v4().to_string();
         let last_version = &value["jacsVersion"];
         let versioncreated = Utc::now().to_rfc3339();
 
         value["jacsLastVersion"] = last_version.clone();
+        let new_version = self.id_strategy()?.new_id(&value);
         value["jacsVersion"] = json!(format!("{}", new_version));
         value["jacsVersionDate"] = json!(format!("{}", versioncreated));
+        stamp_hash_algorithm(&mut value)?;
         // sign new version
         value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME] = self.signing_procedure(
             &value,
@@ -585,17 +1587,47 @@ impl Document for Agent {
         let _ = self
             .verify_document_files(&document_value)
             .expect("file verification");
+        let binding = &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string();
+        let signature_key_from_final = match signature_key_from {
+            Some(signature_key_from) => signature_key_from,
+            None => binding,
+        };
+
         // this is innefficient since I generate a whole document
+        let mut public_key_enc_type = public_key_enc_type;
         let used_public_key = match public_key {
             Some(public_key) => public_key,
+            // a verifier-only agent has no key of its own, so use the signer's stored one
+            None if self.is_verifier_only() => {
+                let signature = &document_value[signature_key_from_final];
+                let public_key_hash = signature
+                    .get_str("publicKeyHash")
+                    .ok_or("document signature has no publicKeyHash")?;
+                if public_key_enc_type.is_none() {
+                    public_key_enc_type = signature.get_str("signingAlgorithm");
+                }
+                self.fs_load_public_key(&public_key_hash)?
+            }
             None => self.get_public_key()?,
         };
 
-        let binding = &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string();
-        let signature_key_from_final = match signature_key_from {
-            Some(signature_key_from) => signature_key_from,
-            None => binding,
-        };
+        // a cache hit skips signature_verification_procedure, so its policy applies first
+        enforce_pq_only(public_key_enc_type.as_ref())?;
+        let cache_key = self.verified_signatures.as_ref().map(|_| {
+            verified_signature_cache_key(
+                document_value,
+                signature_key_from_final,
+                fields,
+                &used_public_key,
+                public_key_enc_type.as_ref(),
+            )
+        });
+        if let (Some(cache), Some(cache_key)) = (&self.verified_signatures, &cache_key) {
+            if cache.lock().map_err(|e| e.to_string())?.contains(cache_key) {
+                debug!("signature already verified for {}", document_key);
+                return Ok(());
+            }
+        }
 
         let result = self.signature_verification_procedure(
             &document_value,
@@ -607,7 +1639,12 @@ impl Document for Agent {
             None,
         );
         match result {
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                if let (Some(cache), Some(cache_key)) = (&self.verified_signatures, cache_key) {
+                    cache.lock().map_err(|e| e.to_string())?.insert(cache_key);
+                }
+                Ok(())
+            }
             Err(err) => {
                 let error_message =
                     format!("Signatures not verifiable {} {:?}! ", document_key, err);
@@ -617,6 +1654,118 @@ impl Document for Agent {
         }
     }
 
+    fn verify_document_multikey(
+        &self,
+        document_string: &str,
+        candidate_keys: Vec<(Vec<u8>, String)>,
+    ) -> Result<usize, Box<dyn Error>> {
+        check_document_size(document_string, max_document_bytes())?;
+        let document_value = self.schema.validate_header(document_string)?;
+        self.verify_hash(&document_value)?;
+        let signature_key_from = DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string();
+        for (index, (public_key, public_key_enc_type)) in candidate_keys.into_iter().enumerate() {
+            let result = self.signature_verification_procedure(
+                &document_value,
+                None,
+                &signature_key_from,
+                public_key,
+                Some(public_key_enc_type),
+                None,
+                None,
+            );
+            match result {
+                Ok(_) => return Ok(index),
+                Err(err) => debug!("candidate key {} did not verify: {}", index, err),
+            }
+        }
+        let error_message = format!(
+            "Signature not verifiable with any candidate key for {}:{}",
+            document_value.get_str("jacsId").unwrap_or_default(),
+            document_value.get_str("jacsVersion").unwrap_or_default()
+        );
+        error!("{}", error_message);
+        Err(error_message.into())
+    }
+
+    fn verify_document_self_contained(
+        &self,
+        document_string: &str,
+    ) -> Result<bool, Box<dyn Error>> {
+        check_document_size(document_string, max_document_bytes())?;
+        let document_value = self.schema.validate_header(document_string)?;
+        self.verify_hash(&document_value)?;
+        let signature = &document_value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME];
+        let public_key = embedded_public_key(signature)?;
+        self.signature_verification_procedure(
+            &document_value,
+            None,
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+            public_key,
+            signature.get_str("signingAlgorithm"),
+            None,
+            None,
+        )?;
+        Ok(true)
+    }
+
+    fn verify_document_against_agent(
+        &self,
+        document_string: &str,
+        signer_agent_json: &str,
+        signer_public_key: Vec<u8>,
+    ) -> Result<bool, Box<dyn Error>> {
+        check_document_size(signer_agent_json, max_document_bytes())?;
+        let agent_value = self.schema.validate_agent(signer_agent_json)?;
+        self.verify_hash(&agent_value)?;
+        let agent_signature = &agent_value[AGENT_SIGNATURE_FIELDNAME];
+        let pinned_key_hash = agent_signature.get_str("publicKeyHash").unwrap_or_default();
+        if !hashes_match(
+            &pinned_key_hash,
+            &hash_public_key(signer_public_key.clone()),
+        ) {
+            return Err(format!(
+                "public key does not match the pinned agent's publicKeyHash {}",
+                pinned_key_hash
+            )
+            .into());
+        }
+        let public_key = signer_public_key;
+        self.signature_verification_procedure(
+            &agent_value,
+            None,
+            &AGENT_SIGNATURE_FIELDNAME.to_string(),
+            public_key.clone(),
+            agent_signature.get_str("signingAlgorithm"),
+            None,
+            None,
+        )
+        .map_err(|e| format!("pinned agent's self-signature is invalid: {}", e))?;
+
+        check_document_size(document_string, max_document_bytes())?;
+        let document_value = self.schema.validate_header(document_string)?;
+        self.verify_hash(&document_value)?;
+        let signature = &document_value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME];
+        let signer_id = signature.get_str("agentID").unwrap_or_default();
+        let pinned_id = agent_value.get_str("jacsId").unwrap_or_default();
+        if signer_id != pinned_id {
+            return Err(format!(
+                "document was signed by {}, not the pinned agent {}",
+                signer_id, pinned_id
+            )
+            .into());
+        }
+        self.signature_verification_procedure(
+            &document_value,
+            None,
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+            public_key,
+            signature.get_str("signingAlgorithm"),
+            None,
+            None,
+        )?;
+        Ok(true)
+    }
+
     fn parse_attachement_arg(&mut self, attachments: Option<&String>) -> Option<Vec<String>> {
         match attachments {
             Some(path_str) => {
@@ -0,0 +1,165 @@
+use crate::agent::document::{Document, JACSDocument};
+use crate::agent::loaders::{use_filesystem, FileLoader};
+use crate::agent::Agent;
+use crate::agent::{
+    JACS_LOG_ID_FIELDNAME, JACS_LOG_INDEX_FIELDNAME, JACS_LOG_PREV_HASH_FIELDNAME, SHA256_FIELDNAME,
+};
+use crate::schema::utils::ValueExt;
+use log::error;
+use serde_json::json;
+use serde_json::Value;
+use std::error::Error;
+
+/// tamper-evident logs built from ordinary signed documents
+/// each entry carries the jacsSha256 of the entry before it, and since
+/// that field is signed and hashed, altering or reordering any entry breaks the chain.
+/// with JACS_USE_FILESYSTEM on, entries are saved like any other document and survive a
+/// restart; without it the log lives only in this agent's memory
+pub trait DocumentLog {
+    /// create a signed document for the payload and append it to the log
+    /// the payload must be a JSON object without jacsId or jacsVersion.
+    /// a log saved by an earlier run is loaded first, so the chain continues
+    fn append_to_log(
+        &mut self,
+        log_id: &String,
+        payload: Value,
+    ) -> Result<JACSDocument, Box<dyn Error>>;
+
+    /// walk the chain from the first entry checking indexes, previous hashes,
+    /// each entry's own hash and signature against this agent's key
+    /// fails with the index of the first bad entry. with JACS_USE_FILESYSTEM on, the
+    /// entries are the saved ones, loaded first if none are in memory
+    fn verify_log(&mut self, log_id: &String) -> Result<bool, Box<dyn Error>>;
+
+    /// loaded entries for a log, ordered by jacsLogIndex
+    fn get_log_entries(&self, log_id: &String) -> Vec<JACSDocument>;
+
+    /// loads a saved log's entries into memory. returns how many there are.
+    /// nothing is verified here, call verify_log afterwards
+    fn load_log(&mut self, log_id: &String) -> Result<usize, Box<dyn Error>>;
+}
+
+impl DocumentLog for Agent {
+    fn get_log_entries(&self, log_id: &String) -> Vec<JACSDocument> {
+        let documents = self.documents.lock().expect("documents lock");
+        let mut entries: Vec<JACSDocument> = documents
+            .values()
+            .filter(|doc| doc.value.get_str(JACS_LOG_ID_FIELDNAME).as_ref() == Some(log_id))
+            .cloned()
+            .collect();
+        entries.sort_by_key(|doc| doc.value.get_i64(JACS_LOG_INDEX_FIELDNAME).unwrap_or(-1));
+        entries
+    }
+
+    fn append_to_log(
+        &mut self,
+        log_id: &String,
+        payload: Value,
+    ) -> Result<JACSDocument, Box<dyn Error>> {
+        if !payload.is_object() {
+            return Err("log payload must be a JSON object".into());
+        }
+        let mut entries = self.get_log_entries(log_id);
+        if entries.is_empty() && use_filesystem() {
+            self.load_log(log_id)?;
+            entries = self.get_log_entries(log_id);
+        }
+        let (next_index, previous_hash) = match entries.last() {
+            Some(last) => {
+                let last_index = last
+                    .value
+                    .get_i64(JACS_LOG_INDEX_FIELDNAME)
+                    .ok_or("last log entry has no index")?;
+                let last_hash = last
+                    .value
+                    .get_str(SHA256_FIELDNAME)
+                    .ok_or("last log entry has no hash")?;
+                (last_index + 1, last_hash)
+            }
+            None => (0, "".to_string()),
+        };
+
+        let mut entry = payload;
+        entry[JACS_LOG_ID_FIELDNAME] = json!(log_id);
+        entry[JACS_LOG_INDEX_FIELDNAME] = json!(next_index);
+        entry[JACS_LOG_PREV_HASH_FIELDNAME] = json!(previous_hash);
+        let document = self.create_document_and_load(&entry.to_string(), None, None)?;
+        if use_filesystem() {
+            let document_key = document.getkey();
+            let saved = self
+                .fs_document_save(
+                    &document_key,
+                    &serde_json::to_string_pretty(&document.value)?,
+                    None,
+                )
+                .and_then(|_| self.fs_log_append(log_id, &document_key));
+            if let Err(e) = saved {
+                // an entry that was not persisted must not become the next one's predecessor
+                self.remove_document(&document_key)?;
+                return Err(format!("could not save entry for log {}: {}", log_id, e).into());
+            }
+        }
+        Ok(document)
+    }
+
+    fn load_log(&mut self, log_id: &String) -> Result<usize, Box<dyn Error>> {
+        let document_keys = self.fs_log_keys(log_id)?;
+        for document_key in &document_keys {
+            let document_string = self.fs_document_load(document_key)?;
+            self.load_document(&document_string)?;
+        }
+        Ok(document_keys.len())
+    }
+
+    fn verify_log(&mut self, log_id: &String) -> Result<bool, Box<dyn Error>> {
+        let mut entries = self.get_log_entries(log_id);
+        if use_filesystem() {
+            if entries.is_empty() {
+                self.load_log(log_id)?;
+            }
+            // the persisted key list is the log, whatever else is loaded with its log id
+            entries = self
+                .fs_log_keys(log_id)?
+                .iter()
+                .map(|document_key| self.get_document(document_key))
+                .collect::<Result<Vec<_>, _>>()?;
+        }
+        if entries.is_empty() {
+            return Err(format!("no entries found for log {}", log_id).into());
+        }
+
+        let mut previous_hash = "".to_string();
+        for (position, entry) in entries.iter().enumerate() {
+            let index = entry.value.get_i64(JACS_LOG_INDEX_FIELDNAME);
+            let claimed_previous = entry
+                .value
+                .get_str(JACS_LOG_PREV_HASH_FIELDNAME)
+                .unwrap_or_default();
+
+            let failure = if entry.value.get_str(JACS_LOG_ID_FIELDNAME).as_ref() != Some(log_id) {
+                Some("entry belongs to another log".to_string())
+            } else if index != Some(position as i64) {
+                Some(format!("expected index {} found {:?}", position, index))
+            } else if claimed_previous != previous_hash {
+                Some("previous entry hash does not match".to_string())
+            } else if let Err(e) = self.verify_hash(&entry.value) {
+                Some(e.to_string())
+            } else if let Err(e) =
+                self.verify_document_signature(&entry.getkey(), None, None, None, None)
+            {
+                Some(e.to_string())
+            } else {
+                None
+            };
+
+            if let Some(reason) = failure {
+                let error_message =
+                    format!("log {} broken at entry {}: {}", log_id, position, reason);
+                error!("{}", error_message);
+                return Err(error_message.into());
+            }
+            previous_hash = entry.value.get_str(SHA256_FIELDNAME).unwrap_or_default();
+        }
+        Ok(true)
+    }
+}
@@ -1,28 +1,50 @@
 use crate::agent::boilerplate::BoilerPlate;
+use crate::agent::document::{document_version_date, max_document_bytes};
 use crate::agent::security::check_data_directory;
 use crate::agent::Agent;
 use crate::crypt::aes_encrypt::decrypt_private_key;
 use crate::crypt::aes_encrypt::encrypt_private_key;
+use crate::crypt::aes_encrypt::{decrypt_with_storage_key, encrypt_with_storage_key};
+use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use regex::Regex;
 use secrecy::ExposeSecret;
+use serde_json::Value;
 
 use std::fs::File;
 use std::io::Read;
 use std::io::Write;
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use log::{debug, error, info, warn};
+use std::collections::BTreeMap;
 use std::env;
 use std::error::Error;
-use std::{fs, path::Path, path::PathBuf};
+use std::{fs, path::Component, path::Path, path::PathBuf};
+use uuid::Uuid;
 
 fn not_implemented_error() -> Box<dyn Error> {
     error!("NOT IMPLEMENTED");
     return "NOT IMPLEMENTED".into();
 }
 
+/// agents and documents are saved under <jacsId>:<jacsVersion>, each part as the header
+/// schema allows, so a key taken from a document can't name another file
+fn check_document_key(key: &str) -> Result<(), Box<dyn Error>> {
+    let valid_part = |part: &str| {
+        !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    match key.split_once(':') {
+        Some((id, version)) if valid_part(id) && valid_part(version) => Ok(()),
+        _ => {
+            let error_message = format!("invalid document key {:?}", key);
+            error!("{}", error_message);
+            Err(error_message.into())
+        }
+    }
+}
+
 /// This environment variable determine if files are saved to the filesystem at all
 /// if you are building something that passing data through to a database, you'd set this flag to 0 or False
 const JACS_USE_FILESYSTEM: &str = "JACS_USE_FILESYSTEM";
@@ -32,6 +54,39 @@ pub fn use_filesystem() -> bool {
     return matches!(env_var_value.to_lowercase().as_str(), "true" | "1");
 }
 
+/// when set to true or 1, documents are written gzipped as .json.gz
+/// reads accept either form, so directories can hold a mix
+const JACS_COMPRESS_STORAGE: &str = "JACS_COMPRESS_STORAGE";
+
+pub fn use_compression() -> bool {
+    let env_var_value = env::var(JACS_COMPRESS_STORAGE).unwrap_or_else(|_| "false".to_string());
+    matches!(env_var_value.to_lowercase().as_str(), "true" | "1")
+}
+
+/// when set to true or 1, documents are written AES-256-GCM encrypted as .json.enc, with a
+/// key derived from JACS_STORAGE_PASSWORD once per process, using
+/// JACS_STORAGE_KDF_ITERATIONS. this takes precedence over compression.
+/// reads decrypt transparently, and hashes and signatures cover the decrypted JSON
+const JACS_ENCRYPT_STORAGE: &str = "JACS_ENCRYPT_STORAGE";
+/// kept out of jacs.config.json on purpose, set it in the environment
+const JACS_STORAGE_PASSWORD: &str = "JACS_STORAGE_PASSWORD";
+
+pub fn use_encryption() -> bool {
+    let env_var_value = env::var(JACS_ENCRYPT_STORAGE).unwrap_or_else(|_| "false".to_string());
+    matches!(env_var_value.to_lowercase().as_str(), "true" | "1")
+}
+
+fn storage_password() -> Result<String, Box<dyn Error>> {
+    match env::var(JACS_STORAGE_PASSWORD) {
+        Ok(password) if !password.is_empty() => Ok(password),
+        _ => Err(format!(
+            "encrypted storage needs {} to be set",
+            JACS_STORAGE_PASSWORD
+        )
+        .into()),
+    }
+}
+
 /// The goal of fileloader is to prevent fileloading into arbitrary directories
 /// by centralizing all filesystem access
 /// Only an initilaized agent can perform some of the functions by calling isready()
@@ -51,7 +106,12 @@ pub trait FileLoader {
     fn fs_agent_load(&self, agentid: &String) -> Result<String, Box<dyn Error>>;
     // fn fs_agent_new(&self, filename: &String) -> Result<String, Box<dyn Error>>;
     // fn fs_document_new(&self, filename: &String) -> Result<String, Box<dyn Error>>;
-    // fn fs_document_load(&self, document_id: &String) -> Result<String, Box<dyn Error>>;
+    /// reads a saved document by id, whether it was stored plain or compressed
+    fn fs_document_load(&self, document_id: &String) -> Result<String, Box<dyn Error>>;
+    /// ids of saved documents, without the .json or .json.gz extension.
+    /// ordered oldest jacsVersionDate first, falling back to id order. dates are read from
+    /// documents/.index.json, which saving keeps current
+    fn fs_document_list(&self) -> Result<Vec<String>, Box<dyn Error>>;
     fn fs_preload_keys(
         &mut self,
         private_key_filename: &String,
@@ -74,6 +134,17 @@ pub trait FileLoader {
         document_string: &String,
         output_filename: Option<String>,
     ) -> Result<String, Box<dyn Error>>;
+    /// in-progress agreements are kept under agreements/ so they survive restarts
+    fn fs_agreement_save(
+        &self,
+        agreement_id: &String,
+        document_string: &String,
+    ) -> Result<String, Box<dyn Error>>;
+    fn fs_agreement_load(&self, agreement_id: &String) -> Result<String, Box<dyn Error>>;
+    /// a log's entries are saved as ordinary documents, and logs/<log_id>.keys lists
+    /// their document keys in the order they were appended
+    fn fs_log_append(&self, log_id: &String, document_key: &String) -> Result<(), Box<dyn Error>>;
+    fn fs_log_keys(&self, log_id: &String) -> Result<Vec<String>, Box<dyn Error>>;
 
     /// used to get base64 content from a filepath
     fn fs_get_document_content(&self, document_filepath: String) -> Result<String, Box<dyn Error>>;
@@ -98,6 +169,17 @@ impl FileLoader for Agent {
             return Err(error_message.into());
         }
 
+        // ids and versions come from documents, so a name must stay inside the data directory
+        if docid.is_empty()
+            || Path::new(docid)
+                .components()
+                .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            let error_message = format!("invalid {} file name {:?}", doctype, docid);
+            error!("{}", error_message);
+            return Err(error_message.into());
+        }
+
         let current_dir = env::current_dir()?;
         let jacs_dir = env::var("JACS_DATA_DIRECTORY").expect("JACS_DATA_DIRECTORY");
 
@@ -240,15 +322,89 @@ impl FileLoader for Agent {
     //     Err(not_implemented_error())
     // }
 
-    // fn fs_document_load(&self, _document_id: &String) -> Result<String, Box<dyn Error>> {
-    //     Err(not_implemented_error())
-    // }
+    fn fs_document_load(&self, document_id: &String) -> Result<String, Box<dyn Error>> {
+        let document_path = self.build_filepath(&"documents".to_string(), document_id)?;
+        load_stored_file(&document_path)
+            .map_err(|e| format!("could not load document {}: {}", document_id, e))?
+            .ok_or_else(|| format!("document {} not found", document_id).into())
+    }
+
+    fn fs_document_list(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        if !use_filesystem() {
+            return Err(
+                "fs_document_list Filesystem features set to off with JACS_USE_FILESYSTEM".into(),
+            );
+        }
+        let documents_dir = env::current_dir()?
+            .join(env::var("JACS_DATA_DIRECTORY")?)
+            .join("documents");
+        let mut document_ids = Vec::new();
+        if !documents_dir.is_dir() {
+            return Ok(document_ids);
+        }
+        for entry in fs::read_dir(&documents_dir)? {
+            let filename = entry?.file_name().to_string_lossy().to_string();
+            // skip temp files from interrupted writes and timestamped backups
+            if filename.starts_with('.') || filename.starts_with("backup-") {
+                continue;
+            }
+            let document_id = filename
+                .strip_suffix(".json.gz")
+                .or_else(|| filename.strip_suffix(".json.enc"))
+                .or_else(|| filename.strip_suffix(".json"));
+            if let Some(document_id) = document_id {
+                document_ids.push(document_id.to_string());
+            }
+        }
+        document_ids.sort();
+        document_ids.dedup();
+
+        // dates come from the index; only documents it does not know yet are opened
+        let mut index = self.read_document_index();
+        let indexed_count = index.len();
+        index.retain(|document_id, _| document_ids.binary_search(document_id).is_ok());
+        let mut index_changed = index.len() != indexed_count;
+        let mut dated_ids: Vec<(Option<DateTime<Utc>>, String)> = Vec::new();
+        for document_id in document_ids {
+            let indexed_date = index
+                .get(&document_id)
+                .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+                .map(|date| date.with_timezone(&Utc));
+            let version_date = match indexed_date {
+                Some(version_date) => Some(version_date),
+                None => {
+                    let version_date = self
+                        .fs_document_load(&document_id)
+                        .ok()
+                        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+                        .and_then(|value| document_version_date(&value));
+                    if let Some(version_date) = version_date {
+                        index.insert(document_id.clone(), version_date.to_rfc3339());
+                        index_changed = true;
+                    }
+                    version_date
+                }
+            };
+            dated_ids.push((version_date, document_id));
+        }
+        if index_changed {
+            if let Err(e) = self.write_document_index(&index) {
+                warn!("could not update the document index: {}", e);
+            }
+        }
+        dated_ids.sort();
+        Ok(dated_ids
+            .into_iter()
+            .map(|(_, document_id)| document_id)
+            .collect())
+    }
 
     fn fs_agent_save(
         &self,
         agentid: &String,
         agent_string: &String,
     ) -> Result<String, Box<dyn Error>> {
+        check_document_key(agentid)?;
         let agentpath = self.build_filepath(&"agent".to_string(), agentid)?;
         Ok(save_to_filepath(&agentpath, agent_string.as_bytes())?)
     }
@@ -274,16 +430,77 @@ impl FileLoader for Agent {
                 };
                 signed_filename
             }
-            _ => document_id.to_string(),
+            _ => {
+                check_document_key(document_id)?;
+                document_id.to_string()
+            }
         };
 
         let document_path =
             self.build_filepath(&"documents".to_string(), &documentoutput_filename)?;
-        info!("saving {:?} ", document_path);
-        Ok(save_to_filepath(
-            &document_path,
-            document_string.as_bytes(),
-        )?)
+        let saved = save_stored_file(&document_path, document_string)?;
+        let version_date = serde_json::from_str::<Value>(document_string)
+            .ok()
+            .and_then(|value| document_version_date(&value));
+        if let Some(version_date) = version_date {
+            let document_id = documentoutput_filename
+                .strip_suffix(".json")
+                .unwrap_or(&documentoutput_filename);
+            let mut index = self.read_document_index();
+            index.insert(document_id.to_string(), version_date.to_rfc3339());
+            // listing falls back to reading the document, so a stale index is only slower
+            if let Err(e) = self.write_document_index(&index) {
+                warn!("could not update the document index: {}", e);
+            }
+        }
+        Ok(saved)
+    }
+
+    fn fs_agreement_save(
+        &self,
+        agreement_id: &String,
+        document_string: &String,
+    ) -> Result<String, Box<dyn Error>> {
+        if let Err(e) = check_data_directory() {
+            error!("Failed to check data directory: {}", e);
+        }
+        let agreement_path = self.build_agreement_filepath(agreement_id)?;
+        save_stored_file(&agreement_path, document_string)
+    }
+
+    fn fs_agreement_load(&self, agreement_id: &String) -> Result<String, Box<dyn Error>> {
+        let agreement_path = self.build_agreement_filepath(agreement_id)?;
+        load_stored_file(&agreement_path)
+            .map_err(|e| format!("could not load agreement progress {}: {}", agreement_id, e))?
+            .ok_or_else(|| format!("no saved agreement progress for {}", agreement_id).into())
+    }
+
+    fn fs_log_append(&self, log_id: &String, document_key: &String) -> Result<(), Box<dyn Error>> {
+        if let Err(e) = check_data_directory() {
+            error!("Failed to check data directory: {}", e);
+        }
+        let mut document_keys = self.fs_log_keys(log_id)?;
+        document_keys.push(document_key.to_string());
+        let log_path = self.build_log_filepath(log_id)?;
+        if let Some(parent) = log_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // rewritten on every append, so no backup copies like save_to_filepath makes
+        let temp_path = write_temp_file(&log_path, (document_keys.join("\n") + "\n").as_bytes())?;
+        persist_temp_file(&temp_path, &log_path)?;
+        Ok(())
+    }
+
+    fn fs_log_keys(&self, log_id: &String) -> Result<Vec<String>, Box<dyn Error>> {
+        let log_path = self.build_log_filepath(log_id)?;
+        if !log_path.is_file() {
+            return Ok(Vec::new());
+        }
+        Ok(fs::read_to_string(log_path)?
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| line.to_string())
+            .collect())
     }
 
     fn fs_get_document_content(&self, document_filepath: String) -> Result<String, Box<dyn Error>> {
@@ -301,16 +518,139 @@ impl FileLoader for Agent {
         let mut file = File::open(&document_filepath)?;
         file.read_to_end(&mut contents)?;
 
-        // Compress the contents using gzip
-        let mut gz_encoder = GzEncoder::new(Vec::new(), Compression::default());
-        gz_encoder.write_all(&contents)?;
-        let compressed_contents = gz_encoder.finish()?;
+        encode_attachment_contents(&contents)
+    }
+}
+
+/// attachment bytes as stored in jacsFiles: gzipped, then base64 encoded.
+/// the attachment's sha256 is taken over this string
+pub fn encode_attachment_contents(contents: &[u8]) -> Result<String, Box<dyn Error>> {
+    // Compress the contents using gzip
+    let mut gz_encoder = GzEncoder::new(Vec::new(), Compression::default());
+    gz_encoder.write_all(contents)?;
+    let compressed_contents = gz_encoder.finish()?;
 
-        // Encode the compressed contents using base64
-        let base64_contents = base64::encode(&compressed_contents);
+    // Encode the compressed contents using base64
+    Ok(base64::encode(&compressed_contents))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Agent {
+    /// agreement ids become filenames, so only id characters are accepted
+    fn build_agreement_filepath(&self, agreement_id: &String) -> Result<PathBuf, Box<dyn Error>> {
+        if agreement_id.is_empty()
+            || !agreement_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == ':' || c == '_')
+        {
+            return Err(format!("invalid agreement id {:?}", agreement_id).into());
+        }
+        self.build_filepath(&"agreements".to_string(), agreement_id)
+    }
+
+    /// documents/.index.json maps saved document ids to their jacsVersionDate, so
+    /// fs_document_list can sort without opening every document. the leading dot keeps it
+    /// out of the listing. it holds no document content, only ids and dates
+    fn document_index_path(&self) -> Result<PathBuf, Box<dyn Error>> {
+        self.build_filepath(&"documents".to_string(), &".index".to_string())
+    }
+
+    fn read_document_index(&self) -> BTreeMap<String, String> {
+        self.document_index_path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn write_document_index(&self, index: &BTreeMap<String, String>) -> Result<(), Box<dyn Error>> {
+        let index_path = self.document_index_path()?;
+        if let Some(parent) = index_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = write_temp_file(&index_path, serde_json::to_string(index)?.as_bytes())?;
+        persist_temp_file(&temp_path, &index_path)?;
+        Ok(())
+    }
+
+    /// log ids become filenames, so they are held to the same characters as agreement ids
+    fn build_log_filepath(&self, log_id: &String) -> Result<PathBuf, Box<dyn Error>> {
+        if log_id.is_empty()
+            || !log_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == ':' || c == '_')
+        {
+            return Err(format!("invalid log id {:?}", log_id).into());
+        }
+        Ok(self
+            .build_filepath(&"logs".to_string(), log_id)?
+            .with_extension("keys"))
+    }
+}
+
+/// writes a document or agreement as JACS_ENCRYPT_STORAGE and JACS_COMPRESS_STORAGE say,
+/// adding .enc or .gz to the path
+#[cfg(not(target_arch = "wasm32"))]
+fn save_stored_file(path: &Path, contents: &String) -> Result<String, Box<dyn Error>> {
+    if use_encryption() {
+        let encrypted_path = enc_path(path);
+        info!("saving encrypted {:?} ", encrypted_path);
+        let encrypted = encrypt_with_storage_key(contents.as_bytes(), &storage_password()?)?;
+        return Ok(save_to_filepath(&encrypted_path, &encrypted)?);
+    }
+    if use_compression() {
+        let compressed_path = gz_path(path);
+        info!("saving compressed {:?} ", compressed_path);
+        let mut gz_encoder = GzEncoder::new(Vec::new(), Compression::default());
+        gz_encoder.write_all(contents.as_bytes())?;
+        return Ok(save_to_filepath(&compressed_path, &gz_encoder.finish()?)?);
+    }
+    info!("saving {:?} ", path);
+    Ok(save_to_filepath(&path.to_path_buf(), contents.as_bytes())?)
+}
 
-        Ok(base64_contents)
+/// reads whichever of the plain, .gz or .enc forms exists, None if none does
+#[cfg(not(target_arch = "wasm32"))]
+fn load_stored_file(path: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    if path.is_file() {
+        return Ok(Some(fs::read_to_string(path)?));
+    }
+    let compressed_path = gz_path(path);
+    if compressed_path.is_file() {
+        // a small .gz can inflate to gigabytes, so stop one byte past the document limit
+        let limit = max_document_bytes();
+        let compressed = fs::read(compressed_path)?;
+        let mut contents = Vec::new();
+        GzDecoder::new(compressed.as_slice())
+            .take(limit as u64 + 1)
+            .read_to_end(&mut contents)?;
+        if contents.len() > limit {
+            return Err(format!("decompresses to more than {} bytes", limit).into());
+        }
+        return Ok(Some(String::from_utf8(contents)?));
     }
+    let encrypted_path = enc_path(path);
+    if encrypted_path.is_file() {
+        let encrypted = fs::read(encrypted_path)?;
+        let decrypted = decrypt_with_storage_key(&encrypted, &storage_password()?)
+            .map_err(|e| format!("could not decrypt: {}", e))?;
+        return Ok(Some(String::from_utf8(decrypted)?));
+    }
+    Ok(None)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn gz_path(path: &Path) -> PathBuf {
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(".gz");
+    PathBuf::from(compressed)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn enc_path(path: &Path) -> PathBuf {
+    let mut encrypted = path.as_os_str().to_owned();
+    encrypted.push(".enc");
+    PathBuf::from(encrypted)
 }
 
 /// private Helper function to create a backup file name based on the current timestamp
@@ -391,6 +731,47 @@ fn save_file(file_path: &Path, filename: &String, content: &[u8]) -> std::io::Re
     save_to_filepath(&full_path, content)
 }
 
+/// first half of an atomic write: content goes to a hidden temp file next to `full_path`
+/// and is fsynced. until persist_temp_file renames it, `full_path` is untouched, so a
+/// crash at this point leaves the previous file intact
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_temp_file(full_path: &Path, content: &[u8]) -> std::io::Result<PathBuf> {
+    let file_name = full_path
+        .file_name()
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("no file name in {:?}", full_path),
+            )
+        })?
+        .to_string_lossy();
+    let temp_path = full_path.with_file_name(format!(".{}.{}.tmp", file_name, Uuid::new_v4()));
+    let mut file = File::create(&temp_path)?;
+    if let Err(e) = file.write_all(content).and_then(|_| file.sync_all()) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    Ok(temp_path)
+}
+
+/// second half of an atomic write: rename the temp file over `full_path`, then fsync the
+/// directory so the rename itself is durable. platforms that cannot fsync a directory
+/// skip that last step
+#[cfg(not(target_arch = "wasm32"))]
+pub fn persist_temp_file(temp_path: &Path, full_path: &Path) -> std::io::Result<()> {
+    if let Err(e) = fs::rename(temp_path, full_path) {
+        let _ = fs::remove_file(temp_path);
+        return Err(e);
+    }
+    if let Some(parent) = full_path.parent() {
+        match File::open(parent).and_then(|dir| dir.sync_all()) {
+            Ok(_) => {}
+            Err(e) => debug!("directory fsync not available for {:?}: {}", parent, e),
+        }
+    }
+    Ok(())
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 fn save_to_filepath(full_path: &PathBuf, content: &[u8]) -> std::io::Result<String> {
     if full_path.exists() {
@@ -406,7 +787,8 @@ fn save_to_filepath(full_path: &PathBuf, content: &[u8]) -> std::io::Result<Stri
         fs::create_dir_all(parent)?; // Create the directory path if it doesn't exist
     }
 
-    fs::write(full_path.clone(), content)?;
+    let temp_path = write_temp_file(full_path, content)?;
+    persist_temp_file(&temp_path, full_path)?;
     // .to_string_lossy().into_owned()
     match full_path.clone().into_os_string().into_string() {
         Ok(path_string) => Ok(path_string),
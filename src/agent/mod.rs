@@ -1,23 +1,36 @@
 pub mod agreement;
 pub mod boilerplate;
+pub mod bundle;
+pub mod cloudevents;
 pub mod document;
+pub mod document_log;
 pub mod loaders;
 pub mod security;
+pub mod stream;
 
 use crate::agent::boilerplate::BoilerPlate;
-use crate::agent::document::{Document, JACSDocument};
-use crate::crypt::hash::hash_public_key;
+use crate::agent::document::{
+    stamp_hash_algorithm, Document, JACSDocument, VerifiedSignatureCache,
+};
+use crate::crypt::hash::{hash_public_key, hashes_match};
 use std::fs;
 
-use crate::config::{get_default_dir, set_env_vars};
+use crate::config::{get_default_dir, set_env_vars, Config};
 
-use crate::crypt::aes_encrypt::{decrypt_private_key, encrypt_private_key};
+use crate::crypt::aes_encrypt::{
+    decrypt_private_key, decrypt_private_key_with_password, encrypt_private_key_in_memory,
+    encrypt_private_key_with_password,
+};
 
-use crate::crypt::KeyManager;
 use crate::crypt::JACS_AGENT_KEY_ALGORITHM;
-
-use crate::schema::utils::{resolve_schema, EmbeddedSchemaResolver, ValueExt};
-use crate::schema::Schema;
+use crate::crypt::{
+    algorithm_strength, enforce_pq_only, generate_key_pair, resolve_algorithm_name,
+    AlgorithmStrength, KeyManager, SignatureEncoding,
+};
+
+use crate::schema::utils::{parse_untrusted_json, resolve_schema, ValueExt};
+use crate::schema::{IdStrategy, Schema};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
 use chrono::prelude::*;
 use jsonschema::{Draft, JSONSchema};
 use loaders::FileLoader;
@@ -28,7 +41,8 @@ use std::collections::HashMap;
 use std::env;
 use std::error::Error;
 use std::fmt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use url::Url;
 use uuid::Uuid;
@@ -50,6 +64,24 @@ pub const JACS_VERSION_FIELDNAME: &str = "jacsVersion";
 pub const JACS_VERSION_DATE_FIELDNAME: &str = "jacsVersionDate";
 pub const JACS_PREVIOUS_VERSION_FIELDNAME: &str = "jacsLastVersion";
 
+/// digest used for jacsSha256. the field name predates configurable hashing,
+/// documents without jacsHashAlgorithm are always SHA-256
+pub const JACS_HASH_ALGORITHM_FIELDNAME: &str = "jacsHashAlgorithm";
+
+/// text encoding of the signature bytes. signatures without it are base64
+pub const SIGNATURE_ENCODING_FIELDNAME: &str = "signatureEncoding";
+
+/// version of the signature envelope and canonicalization, written into each signature.
+/// signatures without it predate the field and use format 1
+pub const SIGNATURE_FORMAT_FIELDNAME: &str = "jacsSignatureFormat";
+pub const CURRENT_SIGNATURE_FORMAT: i64 = 1;
+const SUPPORTED_SIGNATURE_FORMATS: [i64; 1] = [1];
+
+/// fields chaining documents into an append-only log
+pub const JACS_LOG_ID_FIELDNAME: &str = "jacsLogId";
+pub const JACS_LOG_INDEX_FIELDNAME: &str = "jacsLogIndex";
+pub const JACS_LOG_PREV_HASH_FIELDNAME: &str = "jacsPrevEntryHash";
+
 pub const JACS_IGNORE_FIELDS: [&str; 7] = [
     SHA256_FIELDNAME,
     AGENT_SIGNATURE_FIELDNAME,
@@ -117,6 +149,14 @@ pub struct Agent {
     public_key: Option<Vec<u8>>,
     private_key: Option<SecretPrivateKey>,
     key_algorithm: Option<String>,
+    /// agreement field used when a call does not name one
+    default_agreement_fieldname: Option<String>,
+    /// how new documents get ids, ahead of JACS_DOCUMENT_ID_STRATEGY
+    id_strategy: Option<IdStrategy>,
+    /// signatures verify_document_signature has already checked, None when disabled
+    verified_signatures: Option<Arc<Mutex<VerifiedSignatureCache>>>,
+    /// set by load_verifier_only: no private key, signers' keys come from the key directory
+    verifier_only: bool,
 }
 
 impl fmt::Display for Agent {
@@ -158,6 +198,10 @@ impl Agent {
             key_algorithm: None,
             public_key: None,
             private_key: None,
+            default_agreement_fieldname: None,
+            id_strategy: None,
+            verified_signatures: None,
+            verifier_only: false,
         })
     }
 
@@ -175,6 +219,31 @@ impl Agent {
         return self.load(&agent_string);
     }
 
+    /// sets up the agent to only verify documents, without loading or requiring a private
+    /// key. signers' public keys are looked up in key_directory by publicKeyHash, and
+    /// anything that needs to sign fails with a verifier-only mode error
+    pub fn load_verifier_only(
+        &mut self,
+        data_directory: &str,
+        key_directory: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        if !Path::new(key_directory).is_dir() {
+            return Err(format!("key directory {} does not exist", key_directory).into());
+        }
+        env::set_var("JACS_DATA_DIRECTORY", data_directory);
+        env::set_var("JACS_KEY_DIRECTORY", key_directory);
+        self.unset_self();
+        self.public_key = None;
+        self.private_key = None;
+        self.key_algorithm = None;
+        self.verifier_only = true;
+        Ok(())
+    }
+
+    pub fn is_verifier_only(&self) -> bool {
+        self.verifier_only
+    }
+
     pub fn ready(&mut self) -> bool {
         true
     }
@@ -183,18 +252,32 @@ impl Agent {
         &mut self,
         private_key: Vec<u8>,
         public_key: Vec<u8>,
-        key_algorithm: &String,
+        key_algorithm: &str,
     ) -> Result<(), Box<dyn Error>> {
-        let private_key_encrypted = encrypt_private_key(&private_key)?;
+        let private_key_encrypted = encrypt_private_key_in_memory(&private_key)?;
         self.private_key = Some(Secret::new(PrivateKey(private_key_encrypted))); //Some(private_key);
         self.public_key = Some(public_key);
-        //TODO check algo
-        self.key_algorithm = Some(key_algorithm.to_string());
+        // aliases such as ed25519 are stored under the name signatures record
+        self.key_algorithm = Some(resolve_algorithm_name(key_algorithm)?);
+        self.verifier_only = false;
         Ok(())
     }
 
+    /// algorithm of the loaded keys, or JACS_AGENT_KEY_ALGORITHM before any are loaded
+    pub(crate) fn key_algorithm(&self) -> Result<String, Box<dyn Error>> {
+        match &self.key_algorithm {
+            Some(key_algorithm) => Ok(key_algorithm.clone()),
+            None => resolve_algorithm_name(&env::var(JACS_AGENT_KEY_ALGORITHM)?),
+        }
+    }
+
     // todo keep this as private
     pub fn get_private_key(&self) -> Result<Secret<PrivateKey>, Box<dyn Error>> {
+        if self.verifier_only {
+            return Err(
+                "verifier-only mode: no private key is loaded, so this agent cannot sign".into(),
+            );
+        }
         match &self.private_key {
             Some(private_key) => {
                 // Ok(self.private_key.map(|secret| secret.into()).expect("REASON"))
@@ -249,6 +332,146 @@ impl Agent {
         return Ok(());
     }
 
+    /// load, refusing an agent whose self-signature algorithm is weaker than
+    /// `minimum_strength` (classical or post-quantum). a refused agent is left unloaded
+    pub fn load_with_min_strength(
+        &mut self,
+        agent_string: &String,
+        minimum_strength: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let minimum = AlgorithmStrength::from_str(minimum_strength)
+            .map_err(|_| format!("unknown algorithm strength {}", minimum_strength))?;
+        self.load(agent_string)?;
+        let signing_algorithm = self
+            .value
+            .as_ref()
+            .and_then(|value| value[AGENT_SIGNATURE_FIELDNAME].get_str("signingAlgorithm"))
+            .ok_or("agent signature has no signingAlgorithm")?;
+        let strength = algorithm_strength(&signing_algorithm)?;
+        if strength < minimum {
+            self.unset_self();
+            return Err(format!(
+                "agent algorithm {} is {}, below the required {}",
+                signing_algorithm, strength, minimum
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// take on the identity of an agent exported from another machine, without a key directory.
+    /// the private key is decrypted with `password`, the agent document is validated and its
+    /// self-signature checked with `public_key`, and a probe signature confirms the keys pair up.
+    /// in memory the key is re-encrypted with JACS_PRIVATE_KEY_PASSWORD like any loaded key
+    pub fn import_encrypted_agent(
+        &mut self,
+        agent_string: &String,
+        encrypted_private_key: &[u8],
+        public_key: Vec<u8>,
+        key_algorithm: &String,
+        password: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let private_key = decrypt_private_key_with_password(encrypted_private_key, password)
+            .map_err(|e| format!("could not decrypt private key for import: {}", e))?;
+        self.set_keys(private_key, public_key.clone(), key_algorithm)?;
+        if let Err(e) = self.check_imported_agent(agent_string, public_key, key_algorithm) {
+            // a failed import must not leave the imported keys behind
+            self.unset_self();
+            self.unset_keys();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn check_imported_agent(
+        &mut self,
+        agent_string: &String,
+        public_key: Vec<u8>,
+        key_algorithm: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        self.load(agent_string)
+            .map_err(|e| format!("imported agent failed validation: {}", e))?;
+        let probe = Uuid::new_v4().to_string();
+        let probe_signature = self.sign_string(&probe, None)?;
+        self.verify_string(
+            &probe,
+            &probe_signature,
+            public_key,
+            Some(key_algorithm.to_string()),
+            None,
+        )
+        .map_err(|e| format!("imported private key does not match public key: {}", e).into())
+    }
+
+    /// create and load a new agent without writing keys, config or the agent to disk.
+    /// keys use `algorithm`, whatever JACS_AGENT_KEY_ALGORITHM says. returns a JSON object of
+    /// agent_json, config_json, encrypted_private_key and public_key, the last two base64
+    /// encoded and the private key encrypted with `password`; import_encrypted_agent reverses it
+    pub fn create_agent_in_memory(
+        &mut self,
+        agent_json: &String,
+        password: &str,
+        algorithm: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        let key_algorithm = resolve_algorithm_name(algorithm)?;
+        let (private_key, public_key) = generate_key_pair(&key_algorithm)?;
+        let encrypted_private_key = encrypt_private_key_with_password(&private_key, password)?;
+        self.set_keys(private_key, public_key.clone(), &key_algorithm)?;
+        let instance = self.create_agent_and_load(agent_json, false, None)?;
+
+        let config = Config::new(
+            "https://hai.ai/schemas/jacs.config.schema.json".to_string(),
+            env::var("JACS_USE_FILESYSTEM").ok(),
+            env::var("JACS_USE_SECURITY").ok(),
+            env::var("JACS_DATA_DIRECTORY").ok(),
+            env::var("JACS_KEY_DIRECTORY").ok(),
+            env::var("JACS_AGENT_PRIVATE_KEY_FILENAME").ok(),
+            env::var("JACS_AGENT_PUBLIC_KEY_FILENAME").ok(),
+            Some(key_algorithm),
+            env::var("JACS_AGENT_SCHEMA_VERSION").ok(),
+            env::var("JACS_HEADER_SCHEMA_VERSION").ok(),
+            env::var("JACS_SIGNATURE_SCHEMA_VERSION").ok(),
+            None,
+            Some(self.get_lookup_id()?),
+        );
+        let created = json!({
+            "agent_json": serde_json::to_string_pretty(&instance)?,
+            "config_json": serde_json::to_string_pretty(&config)?,
+            "encrypted_private_key": STANDARD.encode(encrypted_private_key),
+            "public_key": STANDARD.encode(public_key),
+        });
+        Ok(created.to_string())
+    }
+
+    /// sign a fixed probe string and verify it with the agent's own public key, to catch a
+    /// bad key or password before the first real request. nothing is saved. returns
+    /// sign_ok, verify_ok, algorithm and duration_ms, plus error when a step failed
+    pub fn self_test(&mut self) -> Result<String, Box<dyn Error>> {
+        let started = std::time::Instant::now();
+        let algorithm = self.key_algorithm()?;
+        let probe = "jacs self test".to_string();
+        let signed = self.sign_string(&probe, None);
+        let verified = match &signed {
+            Ok(signature) => self
+                .get_public_key()
+                .and_then(|public_key| {
+                    self.verify_string(&probe, signature, public_key, Some(algorithm.clone()), None)
+                })
+                .map_err(|e| format!("verify failed: {}", e)),
+            Err(e) => Err(format!("sign failed: {}", e)),
+        };
+        let mut report = json!({
+            "sign_ok": signed.is_ok(),
+            "verify_ok": verified.is_ok(),
+            "algorithm": algorithm,
+            "duration_ms": started.elapsed().as_millis() as u64,
+        });
+        if let Err(e) = verified {
+            report["error"] = json!(e);
+        }
+        Ok(report.to_string())
+    }
+
     pub fn verify_self_signature(&mut self) -> Result<(), Box<dyn Error>> {
         let public_key = self.get_public_key()?;
         // validate header
@@ -279,6 +502,12 @@ impl Agent {
         self.value = None;
     }
 
+    fn unset_keys(&mut self) {
+        self.private_key = None;
+        self.public_key = None;
+        self.key_algorithm = None;
+    }
+
     pub fn get_agent_for_doc(
         &mut self,
         document_key: String,
@@ -322,8 +551,11 @@ impl Agent {
         original_public_key_hash: Option<String>,
         signature: Option<String>,
     ) -> Result<(), Box<dyn Error>> {
-        let (document_values_string, _) =
-            Agent::get_values_as_string(&json_value, fields.cloned(), signature_key_from)?;
+        let signature_format = Agent::signature_format(&json_value[signature_key_from])?;
+        let (document_values_string, _) = match signature_format {
+            1 => Agent::get_values_as_string(json_value, fields.cloned(), signature_key_from)?,
+            _ => return Err(format!("unsupported signature format {}", signature_format).into()),
+        };
         debug!(
             "signature_verification_procedure document_values_string:\n{}",
             document_values_string
@@ -345,7 +577,7 @@ impl Agent {
 
         let public_key_rehash = hash_public_key(public_key.clone());
 
-        if public_key_rehash != public_key_hash {
+        if !hashes_match(&public_key_hash, &public_key_rehash) {
             let error_message = format!(
                 "Incorrect public key used to verify signature public_key_rehash {} public_key_hash {} ",
                 public_key_rehash, public_key_hash
@@ -369,11 +601,21 @@ impl Agent {
                 .trim_matches('"')
                 .to_string(), signature , signature_base64);
 
+        let signature_encoding =
+            match json_value[signature_key_from].get_str(SIGNATURE_ENCODING_FIELDNAME) {
+                Some(encoding) => SignatureEncoding::from_str(&encoding)
+                    .map_err(|_| format!("unsupported signature encoding {}", encoding))?,
+                None => SignatureEncoding::Base64,
+            };
+
+        enforce_pq_only(public_key_enc_type.as_ref())?;
+
         self.verify_string(
             &document_values_string,
             &signature_base64,
             public_key,
             public_key_enc_type,
+            Some(signature_encoding),
         )
     }
 
@@ -415,6 +657,17 @@ impl Agent {
         json_value: &Value,
         fields: Option<&Vec<String>>,
         placement_key: &String,
+    ) -> Result<Value, Box<dyn Error>> {
+        self.signing_procedure_encoded(json_value, fields, placement_key, SignatureEncoding::Base64)
+    }
+
+    /// signing_procedure with the signature text in `encoding`, recorded in signatureEncoding
+    pub fn signing_procedure_encoded(
+        &mut self,
+        json_value: &Value,
+        fields: Option<&Vec<String>>,
+        placement_key: &String,
+        encoding: SignatureEncoding,
     ) -> Result<Value, Box<dyn Error>> {
         debug!("placement_key:\n{}", placement_key);
         let (document_values_string, accepted_fields) =
@@ -423,20 +676,49 @@ impl Agent {
             "signing_procedure document_values_string:\n\n{}\n\n",
             document_values_string
         );
-        let signature = self.sign_string(&document_values_string)?;
+        self.signature_for_values_string(&document_values_string, accepted_fields, encoding)
+    }
+
+    /// signs the string get_values_as_string built from `accepted_fields` and wraps it in a
+    /// signature object. agreement signing requests use this to sign without the document
+    fn signature_for_values_string(
+        &mut self,
+        document_values_string: &String,
+        accepted_fields: Vec<String>,
+        encoding: SignatureEncoding,
+    ) -> Result<Value, Box<dyn Error>> {
+        let signature = self.sign_string(document_values_string, Some(encoding))?;
         debug!("signing_procedure created signature :\n{}", signature);
+        let signing_algorithm = self.key_algorithm()?;
+        let public_key = self.get_public_key()?;
+        self.signature_object(
+            signature,
+            signing_algorithm,
+            public_key,
+            accepted_fields,
+            encoding,
+        )
+    }
+
+    /// wraps a signature made by this agent, with its own key or one held elsewhere, in a
+    /// signature object for `accepted_fields`
+    fn signature_object(
+        &self,
+        signature: String,
+        signing_algorithm: String,
+        public_key: Vec<u8>,
+        accepted_fields: Vec<String>,
+        encoding: SignatureEncoding,
+    ) -> Result<Value, Box<dyn Error>> {
         let binding = String::new();
         let agent_id = self.id.as_ref().unwrap_or(&binding);
         let agent_version = self.version.as_ref().unwrap_or(&binding);
         let date = Utc::now().to_rfc3339();
 
-        let signing_algorithm = env::var(JACS_AGENT_KEY_ALGORITHM)?;
-
         let serialized_fields = match to_value(accepted_fields) {
             Ok(value) => value,
             Err(err) => return Err(Box::new(err)),
         };
-        let public_key = self.get_public_key()?;
         let public_key_hash = hash_public_key(public_key);
         debug!("hash {:?} ", public_key_hash);
         //TODO fields must never include sha256 at top level
@@ -449,7 +731,9 @@ impl Agent {
             "signature":signature,
             "signingAlgorithm":signing_algorithm,
             "publicKeyHash": public_key_hash,
-            "fields": serialized_fields
+            "fields": serialized_fields,
+            SIGNATURE_FORMAT_FIELDNAME: CURRENT_SIGNATURE_FORMAT,
+            SIGNATURE_ENCODING_FIELDNAME: encoding.to_string()
         });
         // TODO add sha256 of public key
         // validate signature schema
@@ -458,6 +742,23 @@ impl Agent {
         return Ok(signature_document);
     }
 
+    /// the format a signature was made with, falling back to format 1 for legacy signatures
+    pub fn signature_format(signature: &Value) -> Result<i64, Box<dyn Error>> {
+        let signature_format = match signature.get(SIGNATURE_FORMAT_FIELDNAME) {
+            None => 1,
+            Some(format) => format.as_i64().ok_or_else(|| {
+                format!(
+                    "{} must be an integer: {}",
+                    SIGNATURE_FORMAT_FIELDNAME, format
+                )
+            })?,
+        };
+        if !SUPPORTED_SIGNATURE_FORMATS.contains(&signature_format) {
+            return Err(format!("unsupported signature format {}", signature_format).into());
+        }
+        Ok(signature_format)
+    }
+
     /// given a set of fields, return a single string
     /// this function critical to all signatures
     /// placement_key is where this signature will go, so it should not be using itself
@@ -516,11 +817,11 @@ impl Agent {
         let original_hash_string = doc[SHA256_FIELDNAME].as_str().unwrap_or("").to_string();
         let new_hash_string = self.hash_doc(doc)?;
 
-        if original_hash_string != new_hash_string {
+        if !hashes_match(&original_hash_string, &new_hash_string) {
             let error_message = format!(
                 "Hashes don't match for doc {:?} {:?}! {:?} != {:?}",
-                doc.get_str("jacsId").expect("REASON"),
-                doc.get_str("jacsVersion").expect("REASON"),
+                doc.get_str("jacsId").unwrap_or_default(),
+                doc.get_str("jacsVersion").unwrap_or_default(),
                 original_hash_string,
                 new_hash_string
             );
@@ -578,6 +879,7 @@ impl Agent {
         new_self["jacsVersionDate"] = json!(format!("{}", versioncreated));
 
         // generate new keys?
+        stamp_hash_algorithm(&mut new_self)?;
         // sign new version
         new_self[AGENT_SIGNATURE_FIELDNAME] =
             self.signing_procedure(&new_self, None, &AGENT_SIGNATURE_FIELDNAME.to_string())?;
@@ -647,8 +949,9 @@ impl Agent {
         _create_keys_algorithm: Option<&String>,
     ) -> Result<Value, Box<dyn std::error::Error + 'static>> {
         // validate schema json string
-        // make sure id and version are empty
-        let mut instance = self.schema.create(json)?;
+        // make sure id and version are empty. agents always get uuids, whatever the
+        // document id strategy
+        let mut instance = self.schema.create(json, IdStrategy::Uuid)?;
 
         self.id = instance.get_str("jacsId");
         self.version = instance.get_str("jacsVersion");
@@ -660,6 +963,7 @@ impl Agent {
             let _ = self.fs_load_keys()?;
         }
         instance["$schema"] = json!("https://hai.ai/schemas/agent/v1/agent.schema.json");
+        stamp_hash_algorithm(&mut instance)?;
         instance[AGENT_SIGNATURE_FIELDNAME] =
             self.signing_procedure(&instance, None, &AGENT_SIGNATURE_FIELDNAME.to_string())?;
         // write  file to disk at [jacs]/agents/
@@ -673,6 +977,39 @@ impl Agent {
     }
 }
 
+/// compares two agent documents for reconciling records across stores. returns JSON
+/// {same_id, same_key, same_version, equivalent}, where equivalent means the same jacsId
+/// signed with the same public key (by jacsSignature.publicKeyHash), so a newer version of
+/// one agent is equivalent while a different agent that reused its name is not.
+/// neither document is verified; do that first if the records come from untrusted stores
+pub fn agents_equivalent(agent_a: &str, agent_b: &str) -> Result<String, Box<dyn Error>> {
+    let identity = |agent_json: &str| -> Result<(String, String, String), Box<dyn Error>> {
+        let agent = parse_untrusted_json(agent_json)?;
+        let public_key_hash = agent
+            .get(AGENT_SIGNATURE_FIELDNAME)
+            .and_then(|signature| signature.get_str("publicKeyHash"))
+            .ok_or("agent has no jacsSignature.publicKeyHash")?;
+        Ok((
+            agent.get_str("jacsId").ok_or("agent has no jacsId")?,
+            agent
+                .get_str("jacsVersion")
+                .ok_or("agent has no jacsVersion")?,
+            public_key_hash,
+        ))
+    };
+    let (id_a, version_a, key_hash_a) = identity(agent_a)?;
+    let (id_b, version_b, key_hash_b) = identity(agent_b)?;
+    let same_id = id_a == id_b;
+    let same_key = hashes_match(&key_hash_a, &key_hash_b);
+    Ok(json!({
+        "same_id": same_id,
+        "same_key": same_key,
+        "same_version": same_id && version_a == version_b,
+        "equivalent": same_id && same_key,
+    })
+    .to_string())
+}
+
 /*
 
 todo
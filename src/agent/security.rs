@@ -1,10 +1,11 @@
+use crate::crypt::hash::hash_string;
 use crate::error;
 use log::info;
 
 use std::env;
 use std::error::Error;
 use std::fs::{self, Permissions};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
 /// off by default
@@ -83,17 +84,37 @@ fn is_executable(path: &std::path::Path) -> bool {
     false
 }
 
-fn quarantine_file(file_path: &Path) -> Result<(), Box<dyn Error>> {
+/// JACS_DATA_DIRECTORY/quarantine, created if missing
+fn quarantine_directory() -> Result<PathBuf, Box<dyn Error>> {
     let data_dir = env::var("JACS_DATA_DIRECTORY").expect("JACS_DATA_DIRECTORY");
-    let mut quarantine_dir = Path::new(&data_dir);
-    let binding = quarantine_dir.join("quarantine");
-    quarantine_dir = &binding;
+    let quarantine_dir = Path::new(&data_dir).join("quarantine");
 
     if !quarantine_dir.exists() {
-        fs::create_dir_all(quarantine_dir)?;
-        let permissions = Permissions::from_mode(0o644);
-        fs::set_permissions(quarantine_dir, permissions)?;
+        fs::create_dir_all(&quarantine_dir)?;
+        let permissions = Permissions::from_mode(0o755);
+        fs::set_permissions(&quarantine_dir, permissions)?;
     }
+    Ok(quarantine_dir)
+}
+
+/// writes a document that failed verification to the quarantine directory, named by the
+/// sha256 of its contents so resubmitting it does not pile up copies. returns the path
+pub fn quarantine_document(document_string: &str, reason: &str) -> Result<String, Box<dyn Error>> {
+    let dest_path = quarantine_directory()?.join(format!(
+        "{}.json",
+        hash_string(&document_string.to_string())
+    ));
+    error!(
+        "security: quarantining document as {:?}: {}",
+        dest_path, reason
+    );
+    fs::write(&dest_path, document_string)?;
+    fs::set_permissions(&dest_path, Permissions::from_mode(0o644))?;
+    Ok(dest_path.to_string_lossy().into_owned())
+}
+
+fn quarantine_file(file_path: &Path) -> Result<(), Box<dyn Error>> {
+    let quarantine_dir = quarantine_directory()?;
 
     let file_name = match file_path.file_name() {
         Some(name) => name,
@@ -0,0 +1,138 @@
+use crate::agent::document::max_document_bytes;
+use crate::agent::loaders::FileLoader;
+use crate::agent::Agent;
+use crate::agent::DOCUMENT_AGENT_SIGNATURE_FIELDNAME;
+use crate::crypt::hash::{hash_public_key, hashes_match};
+use crate::schema::utils::ValueExt;
+use serde_json::Value;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Read};
+
+/// outcome for one line of a JSONL stream
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerificationResult {
+    /// hash and signature verified. holds the document's id:version
+    Valid(String),
+    /// the line was not a JACS document or did not verify. holds the reason
+    Invalid(String),
+}
+
+/// verification of signed documents stored one per line
+pub trait StreamVerifier {
+    /// reads `reader` line by line, verifying each document against the signer's public key
+    /// from the key store, found by its publicKeyHash. `callback` gets the 1-based line
+    /// number and result. blank lines are skipped, a bad line is reported and the stream
+    /// continues. at most one line, capped at JACS_MAX_DOCUMENT_BYTES, is held in memory.
+    /// returns the number of documents checked; only read errors end the stream early
+    fn verify_jsonl<R: Read, F: FnMut(usize, VerificationResult)>(
+        &self,
+        reader: R,
+        callback: F,
+    ) -> Result<usize, Box<dyn Error>>;
+}
+
+impl StreamVerifier for Agent {
+    fn verify_jsonl<R: Read, F: FnMut(usize, VerificationResult)>(
+        &self,
+        reader: R,
+        mut callback: F,
+    ) -> Result<usize, Box<dyn Error>> {
+        let max_line_bytes = max_document_bytes();
+        let mut reader = BufReader::new(reader);
+        let mut line: Vec<u8> = Vec::new();
+        let mut line_number = 0;
+        let mut checked = 0;
+        loop {
+            line.clear();
+            let read = (&mut reader)
+                .take(max_line_bytes as u64 + 1)
+                .read_until(b'\n', &mut line)?;
+            if read == 0 {
+                break;
+            }
+            line_number += 1;
+            if line.len() > max_line_bytes && line.last() != Some(&b'\n') {
+                skip_rest_of_line(&mut reader, max_line_bytes)?;
+                checked += 1;
+                callback(
+                    line_number,
+                    VerificationResult::Invalid(format!(
+                        "line is larger than {} bytes",
+                        max_line_bytes
+                    )),
+                );
+                continue;
+            }
+            let text = match std::str::from_utf8(&line) {
+                Ok(text) => text.trim(),
+                Err(e) => {
+                    checked += 1;
+                    callback(
+                        line_number,
+                        VerificationResult::Invalid(format!("line is not UTF-8: {}", e)),
+                    );
+                    continue;
+                }
+            };
+            if text.is_empty() {
+                continue;
+            }
+            checked += 1;
+            let result = match self.verify_with_stored_key(text) {
+                Ok(value) => VerificationResult::Valid(format!(
+                    "{}:{}",
+                    value.get_str("jacsId").unwrap_or_default(),
+                    value.get_str("jacsVersion").unwrap_or_default()
+                )),
+                Err(e) => VerificationResult::Invalid(e.to_string()),
+            };
+            callback(line_number, result);
+        }
+        Ok(checked)
+    }
+}
+
+impl Agent {
+    /// header, hash and signature checks against the signer's public key from the key store,
+    /// found by its publicKeyHash. returns the validated document
+    pub(super) fn verify_with_stored_key(
+        &self,
+        document_string: &str,
+    ) -> Result<Value, Box<dyn Error>> {
+        let value = self.schema.validate_header(document_string)?;
+        self.verify_hash(&value)?;
+        let signature = &value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME];
+        let public_key_hash = signature
+            .get_str("publicKeyHash")
+            .ok_or("document signature has no publicKeyHash")?;
+        let public_key = self.fs_load_public_key(&public_key_hash)?;
+        if !hashes_match(&public_key_hash, &hash_public_key(public_key.clone())) {
+            return Err(format!("wrong public key for {}", public_key_hash).into());
+        }
+        self.signature_verification_procedure(
+            &value,
+            None,
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+            public_key,
+            signature.get_str("signingAlgorithm"),
+            None,
+            None,
+        )?;
+        Ok(value)
+    }
+}
+
+/// discards the remainder of an oversized line, a bounded chunk at a time
+fn skip_rest_of_line<R: BufRead>(reader: &mut R, chunk_bytes: usize) -> std::io::Result<()> {
+    let mut chunk: Vec<u8> = Vec::new();
+    loop {
+        chunk.clear();
+        let read = reader
+            .by_ref()
+            .take(chunk_bytes as u64)
+            .read_until(b'\n', &mut chunk)?;
+        if read == 0 || chunk.last() == Some(&b'\n') {
+            return Ok(());
+        }
+    }
+}
@@ -1,8 +1,12 @@
+use crate::agent::document::DEFAULT_MAX_DOCUMENT_BYTES;
+use crate::crypt::aes_encrypt::DEFAULT_KEY_KDF_ITERATIONS;
+use crate::schema::Schema;
 use log::debug;
 use log::info;
 use serde::Deserialize;
 use serde::Serialize;
 use std::env;
+use std::error::Error;
 use std::fs;
 use std::path::PathBuf;
 use uuid::Uuid;
@@ -23,6 +27,14 @@ pub struct Config {
     jacs_signature_schema_version: Option<String>,
     jacs_private_key_password: Option<String>,
     jacs_agent_id_and_version: Option<String>,
+    jacs_compress_storage: Option<String>,
+    jacs_encrypt_storage: Option<String>,
+    jacs_key_kdf: Option<String>,
+    jacs_key_kdf_iterations: Option<String>,
+    jacs_max_document_bytes: Option<String>,
+    jacs_document_hash_algorithm: Option<String>,
+    jacs_document_id_strategy: Option<String>,
+    jacs_pq_only: Option<String>,
 }
 
 impl Config {
@@ -55,10 +67,27 @@ impl Config {
             jacs_signature_schema_version,
             jacs_private_key_password,
             jacs_agent_id_and_version,
+            jacs_compress_storage: None,
+            jacs_encrypt_storage: None,
+            jacs_key_kdf: None,
+            jacs_key_kdf_iterations: None,
+            jacs_max_document_bytes: None,
+            jacs_document_hash_algorithm: None,
+            jacs_document_id_strategy: None,
+            jacs_pq_only: None,
         }
     }
 }
 
+/// checks a jacs.config.json document against the config schema before an agent is
+/// loaded with it. every problem is reported, one per line, prefixed with its JSON path
+pub fn validate_config_json(config_json: &str) -> Result<(), Box<dyn Error>> {
+    // the config schema is the same for every agent, header and signature version
+    let version = "v1".to_string();
+    Schema::new(&version, &version, &version)?.validate_config(config_json)?;
+    Ok(())
+}
+
 pub fn get_default_dir() -> PathBuf {
     env::var("JACS_DATA_DIRECTORY")
         .map(PathBuf::from)
@@ -99,6 +128,14 @@ pub fn set_env_vars() -> String {
             jacs_signature_schema_version: None,
             jacs_private_key_password: None,
             jacs_agent_id_and_version: None,
+            jacs_compress_storage: None,
+            jacs_encrypt_storage: None,
+            jacs_key_kdf: None,
+            jacs_key_kdf_iterations: None,
+            jacs_max_document_bytes: None,
+            jacs_document_hash_algorithm: None,
+            jacs_document_id_strategy: None,
+            jacs_pq_only: None,
         },
     };
     debug!("configs from file {:?}", config);
@@ -178,6 +215,47 @@ pub fn set_env_vars() -> String {
 
     env::set_var("JACS_AGENT_ID_AND_VERSION", &jacs_agent_id_and_version);
 
+    let jacs_compress_storage = config
+        .jacs_compress_storage
+        .unwrap_or_else(|| "false".to_string());
+    env::set_var("JACS_COMPRESS_STORAGE", &jacs_compress_storage);
+
+    let jacs_encrypt_storage = config
+        .jacs_encrypt_storage
+        .unwrap_or_else(|| "false".to_string());
+    env::set_var("JACS_ENCRYPT_STORAGE", &jacs_encrypt_storage);
+
+    let jacs_key_kdf = config
+        .jacs_key_kdf
+        .unwrap_or_else(|| "pbkdf2-sha256".to_string());
+    env::set_var("JACS_KEY_KDF", &jacs_key_kdf);
+
+    let jacs_key_kdf_iterations = config
+        .jacs_key_kdf_iterations
+        .unwrap_or_else(|| DEFAULT_KEY_KDF_ITERATIONS.to_string());
+    env::set_var("JACS_KEY_KDF_ITERATIONS", &jacs_key_kdf_iterations);
+
+    let jacs_max_document_bytes = config
+        .jacs_max_document_bytes
+        .unwrap_or_else(|| DEFAULT_MAX_DOCUMENT_BYTES.to_string());
+    env::set_var("JACS_MAX_DOCUMENT_BYTES", &jacs_max_document_bytes);
+
+    let jacs_document_hash_algorithm = config
+        .jacs_document_hash_algorithm
+        .unwrap_or_else(|| "sha256".to_string());
+    env::set_var(
+        "JACS_DOCUMENT_HASH_ALGORITHM",
+        &jacs_document_hash_algorithm,
+    );
+
+    let jacs_document_id_strategy = config
+        .jacs_document_id_strategy
+        .unwrap_or_else(|| "uuid".to_string());
+    env::set_var("JACS_DOCUMENT_ID_STRATEGY", &jacs_document_id_strategy);
+
+    let jacs_pq_only = config.jacs_pq_only.unwrap_or_else(|| "false".to_string());
+    env::set_var("JACS_PQ_ONLY", &jacs_pq_only);
+
     let loading_message = format!(
         r#"
         Loading JACS and Sophon env variables of:
@@ -193,6 +271,14 @@ pub fn set_env_vars() -> String {
             JACS_SIGNATURE_SCHEMA_VERSION:   {},
             JACS_PRIVATE_KEY_PASSWORD        {},
             JACS_AGENT_ID_AND_VERSION        {}
+            JACS_COMPRESS_STORAGE            {}
+            JACS_ENCRYPT_STORAGE             {}
+            JACS_KEY_KDF                     {}
+            JACS_KEY_KDF_ITERATIONS          {}
+            JACS_MAX_DOCUMENT_BYTES          {}
+            JACS_DOCUMENT_HASH_ALGORITHM     {}
+            JACS_DOCUMENT_ID_STRATEGY        {}
+            JACS_PQ_ONLY                     {}
         "#,
         jacs_use_security,
         jacs_use_filesystem,
@@ -206,6 +292,14 @@ pub fn set_env_vars() -> String {
         jacs_signature_schema_version,
         jacs_private_key_password,
         jacs_agent_id_and_version,
+        jacs_compress_storage,
+        jacs_encrypt_storage,
+        jacs_key_kdf,
+        jacs_key_kdf_iterations,
+        jacs_max_document_bytes,
+        jacs_document_hash_algorithm,
+        jacs_document_id_strategy,
+        jacs_pq_only,
     );
 
     info!("{}", loading_message);
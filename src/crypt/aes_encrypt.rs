@@ -4,24 +4,120 @@ use aes_gcm::{
     Aes256Gcm, Key, Nonce,
 };
 use rand::{thread_rng, Rng};
+use secrecy::zeroize::Zeroizing;
 use sha2::{Digest, Sha256};
 use std::env;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use strum_macros::{AsRefStr, Display, EnumString};
+
+/// how the AES key is derived from a password
+#[derive(Debug, Clone, Copy, PartialEq, AsRefStr, Display, EnumString)]
+pub enum KeyDerivation {
+    /// a single sha256 of password and salt. what key files were encrypted with before
+    /// the KDF was configurable; fast, so also used for the copy of the key held in memory
+    #[strum(serialize = "sha256")]
+    Sha256,
+    #[strum(serialize = "pbkdf2-sha256")]
+    Pbkdf2Sha256,
+}
+
+pub const JACS_KEY_KDF: &str = "JACS_KEY_KDF";
+pub const JACS_KEY_KDF_ITERATIONS: &str = "JACS_KEY_KDF_ITERATIONS";
+pub const DEFAULT_KEY_KDF_ITERATIONS: u32 = 600_000;
+/// most PBKDF2 iterations accepted, from config or from an encrypted header. a header can
+/// come from anyone, and u32::MAX iterations would take hours to derive
+pub const MAX_KDF_ITERATIONS: u32 = 10_000_000;
+
+/// encrypted keys start with this, followed by the KDF (1 byte) and its iteration count
+/// (4 bytes, big endian), so they decrypt with the parameters they were written with no
+/// matter what is configured later. data without it predates the header and is
+/// salt, nonce and ciphertext derived with KeyDerivation::Sha256
+const KDF_HEADER_MAGIC: &[u8; 8] = b"JACSKDF1";
+const KDF_HEADER_LEN: usize = 8 + 1 + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KdfParams {
+    pub kdf: KeyDerivation,
+    pub iterations: u32,
+}
+
+impl KdfParams {
+    const LEGACY: KdfParams = KdfParams {
+        kdf: KeyDerivation::Sha256,
+        iterations: 1,
+    };
+}
+
+/// JACS_KEY_KDF and JACS_KEY_KDF_ITERATIONS, defaulting to pbkdf2-sha256 with 600000 iterations
+pub fn configured_kdf_params() -> Result<KdfParams, Box<dyn std::error::Error>> {
+    let kdf = match env::var(JACS_KEY_KDF) {
+        Ok(name) if !name.is_empty() => KeyDerivation::from_str(&name)
+            .map_err(|_| format!("unknown {} {}", JACS_KEY_KDF, name))?,
+        _ => KeyDerivation::Pbkdf2Sha256,
+    };
+    let iterations = match env::var(JACS_KEY_KDF_ITERATIONS) {
+        Ok(count) if !count.is_empty() => count
+            .parse::<u32>()
+            .ok()
+            .filter(|count| (1..=MAX_KDF_ITERATIONS).contains(count))
+            .ok_or(format!("invalid {} {}", JACS_KEY_KDF_ITERATIONS, count))?,
+        _ => DEFAULT_KEY_KDF_ITERATIONS,
+    };
+    Ok(KdfParams { kdf, iterations })
+}
+
+fn derive_key(password: &str, salt: &[u8], params: &KdfParams) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    match params.kdf {
+        KeyDerivation::Sha256 => {
+            let mut hasher = Sha256::new();
+            hasher.update(password.as_bytes());
+            hasher.update(salt);
+            let hash = hasher.finalize();
+            key.copy_from_slice(&hash[..32]);
+        }
+        KeyDerivation::Pbkdf2Sha256 => {
+            pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, params.iterations, &mut key);
+        }
+    }
+    key
+}
 
 // Encrypt a private key with a password
 pub fn encrypt_private_key(private_key: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let password = env::var("JACS_PRIVATE_KEY_PASSWORD".to_string())?;
+    encrypt_private_key_with_password(private_key, &password)
+}
+
+// Encrypt the copy of a private key an Agent holds in memory. it is decrypted for every
+// signature, so it skips the configured KDF; key files and exports never use this
+pub fn encrypt_private_key_in_memory(
+    private_key: &[u8],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let password = env::var("JACS_PRIVATE_KEY_PASSWORD")?;
+    encrypt_private_key_with_params(private_key, &password, &KdfParams::LEGACY)
+}
+
+// Encrypt a private key with an explicit password instead of JACS_PRIVATE_KEY_PASSWORD
+pub fn encrypt_private_key_with_password(
+    private_key: &[u8],
+    password: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    encrypt_private_key_with_params(private_key, password, &configured_kdf_params()?)
+}
 
+// Encrypt with explicit KDF parameters, recorded in a header unless they are the legacy ones
+pub fn encrypt_private_key_with_params(
+    private_key: &[u8],
+    password: &str,
+    params: &KdfParams,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     // Generate a random salt
     let mut salt = [0u8; 16];
     thread_rng().fill(&mut salt[..]);
 
-    // Derive key using PBKDF2 with SHA-256
-    let mut key = [0u8; 32];
-    let mut hasher = Sha256::new();
-    hasher.update(password.as_bytes());
-    hasher.update(&salt);
-    let hash = hasher.finalize();
-    key.copy_from_slice(&hash[..32]);
+    let key = derive_key(password, &salt, params);
 
     // Create cipher instance
     let key = Key::<Aes256Gcm>::from_slice(&key);
@@ -35,8 +131,17 @@ pub fn encrypt_private_key(private_key: &[u8]) -> Result<Vec<u8>, Box<dyn std::e
         .encrypt(&nonce, private_key)
         .expect("encryption failure!");
 
-    // Combine the salt, nonce, and encrypted data into one Vec to return
-    let mut encrypted_key_with_salt_and_nonce = salt.to_vec();
+    // Combine the header, salt, nonce, and encrypted data into one Vec to return
+    let mut encrypted_key_with_salt_and_nonce = Vec::new();
+    if *params != KdfParams::LEGACY {
+        encrypted_key_with_salt_and_nonce.extend_from_slice(KDF_HEADER_MAGIC);
+        encrypted_key_with_salt_and_nonce.push(match params.kdf {
+            KeyDerivation::Sha256 => 0,
+            KeyDerivation::Pbkdf2Sha256 => 1,
+        });
+        encrypted_key_with_salt_and_nonce.extend_from_slice(&params.iterations.to_be_bytes());
+    }
+    encrypted_key_with_salt_and_nonce.extend_from_slice(&salt);
     encrypted_key_with_salt_and_nonce.extend_from_slice(nonce.as_slice());
     encrypted_key_with_salt_and_nonce.extend_from_slice(&encrypted_data);
 
@@ -48,7 +153,56 @@ pub fn decrypt_private_key(
     encrypted_key_with_salt_and_nonce: &[u8],
 ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let password = env::var("JACS_PRIVATE_KEY_PASSWORD".to_string())?;
+    decrypt_private_key_with_password(encrypted_key_with_salt_and_nonce, &password)
+}
+
+/// the KDF parameters encrypted data was written with, and the rest of it
+fn split_kdf_header(encrypted: &[u8]) -> Result<(KdfParams, &[u8]), Box<dyn std::error::Error>> {
+    if !encrypted.starts_with(KDF_HEADER_MAGIC) {
+        return Ok((KdfParams::LEGACY, encrypted));
+    }
+    if encrypted.len() < KDF_HEADER_LEN {
+        return Err("encrypted data is too short".into());
+    }
+    let kdf = match encrypted[8] {
+        0 => KeyDerivation::Sha256,
+        1 => KeyDerivation::Pbkdf2Sha256,
+        other => return Err(format!("unknown key derivation {}", other).into()),
+    };
+    let iterations = header_iterations(&encrypted[9..KDF_HEADER_LEN])?;
+    Ok((KdfParams { kdf, iterations }, &encrypted[KDF_HEADER_LEN..]))
+}
+
+/// a big endian iteration count read from an encrypted header, within 1..=MAX_KDF_ITERATIONS
+fn header_iterations(bytes: &[u8]) -> Result<u32, Box<dyn std::error::Error>> {
+    let mut iterations = [0u8; 4];
+    iterations.copy_from_slice(bytes);
+    let iterations = u32::from_be_bytes(iterations);
+    if !(1..=MAX_KDF_ITERATIONS).contains(&iterations) {
+        return Err(format!(
+            "encrypted header asks for {} KDF iterations, more than {} are refused",
+            iterations, MAX_KDF_ITERATIONS
+        )
+        .into());
+    }
+    Ok(iterations)
+}
+
+/// the KDF parameters an encrypted key was written with
+pub fn encrypted_key_kdf_params(
+    encrypted_key: &[u8],
+) -> Result<KdfParams, Box<dyn std::error::Error>> {
+    Ok(split_kdf_header(encrypted_key)?.0)
+}
 
+// Decrypt the private key with an explicit password
+// a wrong password fails the AES-GCM tag check and returns an error
+pub fn decrypt_private_key_with_password(
+    encrypted_key_with_salt_and_nonce: &[u8],
+    password: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let (params, encrypted_key_with_salt_and_nonce) =
+        split_kdf_header(encrypted_key_with_salt_and_nonce)?;
     if encrypted_key_with_salt_and_nonce.len() < 16 + 12 {
         return Err("encrypted data is too short".into());
     }
@@ -57,13 +211,7 @@ pub fn decrypt_private_key(
     let (salt, rest) = encrypted_key_with_salt_and_nonce.split_at(16);
     let (nonce, encrypted_data) = rest.split_at(12);
 
-    // Derive key using PBKDF2 with SHA-256
-    let mut key = [0u8; 32];
-    let mut hasher = Sha256::new();
-    hasher.update(password.as_bytes());
-    hasher.update(salt);
-    let hash = hasher.finalize();
-    key.copy_from_slice(&hash[..32]);
+    let key = derive_key(password, salt, &params);
 
     // Create cipher instance
     let key = Key::<Aes256Gcm>::from_slice(&key);
@@ -72,7 +220,161 @@ pub fn decrypt_private_key(
     // Decrypt private key
     let decrypted_data = cipher
         .decrypt(&Nonce::from_slice(nonce), encrypted_data)
-        .expect("decryption failure!");
+        .map_err(|_| "decryption failure! wrong password or corrupted key")?;
 
     Ok(decrypted_data)
 }
+
+// Re-encrypt a key with the configured KDF, e.g. to upgrade a key file written with older or
+// weaker parameters, optionally changing the password at the same time
+pub fn reencrypt_private_key(
+    encrypted_key: &[u8],
+    old_password: &str,
+    new_password: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let private_key = decrypt_private_key_with_password(encrypted_key, old_password)?;
+    encrypt_private_key_with_password(&private_key, new_password)
+}
+
+pub const JACS_STORAGE_KDF_ITERATIONS: &str = "JACS_STORAGE_KDF_ITERATIONS";
+
+/// stored documents encrypted with encrypt_with_storage_key start with this, then the
+/// PBKDF2 iteration count (4 bytes, big endian) and salt (16 bytes) of the storage key,
+/// then the file's own salt, nonce and ciphertext
+const STORAGE_HEADER_MAGIC: &[u8; 8] = b"JACSSTO1";
+const STORAGE_HEADER_LEN: usize = 8 + 4 + 16;
+
+/// the storage key this process encrypts with, derived from STORAGE_SALT
+struct CachedStorageKey {
+    /// sha256 of STORAGE_SALT and the password, so a changed password derives a new key
+    password_digest: [u8; 32],
+    iterations: u32,
+    key: Zeroizing<[u8; 32]>,
+}
+
+/// only this process's own storage key is kept; keys for other salts are never cached
+static STORAGE_KEY: Mutex<Option<CachedStorageKey>> = Mutex::new(None);
+/// salt for the storage key this process encrypts with, so it is derived only once
+static STORAGE_SALT: OnceLock<[u8; 16]> = OnceLock::new();
+
+/// JACS_STORAGE_KDF_ITERATIONS, the PBKDF2 iterations for the storage key, separate from
+/// the key file setting. defaults to DEFAULT_KEY_KDF_ITERATIONS; the cost is paid once per
+/// process rather than once per document
+pub fn configured_storage_kdf_iterations() -> Result<u32, Box<dyn std::error::Error>> {
+    match env::var(JACS_STORAGE_KDF_ITERATIONS) {
+        Ok(count) if !count.is_empty() => count
+            .parse::<u32>()
+            .ok()
+            .filter(|count| (1..=MAX_KDF_ITERATIONS).contains(count))
+            .ok_or_else(|| format!("invalid {} {}", JACS_STORAGE_KDF_ITERATIONS, count).into()),
+        _ => Ok(DEFAULT_KEY_KDF_ITERATIONS),
+    }
+}
+
+/// the PBKDF2 storage key for password, salt and iterations. the key for this process's
+/// own salt and configured iterations is derived once and cached. any other salt or
+/// count comes from a file header, e.g. one written by an earlier process, and is derived
+/// on every call without the lock, so hostile headers can't grow the cache or stall
+/// other storage calls
+fn storage_key(
+    password: &str,
+    salt: &[u8; 16],
+    iterations: u32,
+) -> Result<Zeroizing<[u8; 32]>, Box<dyn std::error::Error>> {
+    let derive = || {
+        let params = KdfParams {
+            kdf: KeyDerivation::Pbkdf2Sha256,
+            iterations,
+        };
+        Zeroizing::new(derive_key(password, salt, &params))
+    };
+    if STORAGE_SALT.get() != Some(salt)
+        || configured_storage_kdf_iterations().ok() != Some(iterations)
+    {
+        return Ok(derive());
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(password.as_bytes());
+    let password_digest: [u8; 32] = hasher.finalize().into();
+    let mut cached = STORAGE_KEY.lock().map_err(|e| e.to_string())?;
+    if let Some(cached) = cached.as_ref() {
+        if cached.iterations == iterations && cached.password_digest == password_digest {
+            return Ok(cached.key.clone());
+        }
+    }
+    let key = derive();
+    *cached = Some(CachedStorageKey {
+        password_digest,
+        iterations,
+        key: key.clone(),
+    });
+    Ok(key)
+}
+
+/// the per-file AES key: a single sha256 of the storage key and the file's salt
+fn storage_file_key(storage_key: &[u8; 32], file_salt: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    hasher.update(storage_key);
+    hasher.update(file_salt);
+    Zeroizing::new(hasher.finalize().into())
+}
+
+/// encrypts stored documents. the expensive PBKDF2 step runs once per process and
+/// password, each file then gets its own key from a random salt
+pub fn encrypt_with_storage_key(
+    data: &[u8],
+    password: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let salt = STORAGE_SALT.get_or_init(|| {
+        let mut salt = [0u8; 16];
+        thread_rng().fill(&mut salt[..]);
+        salt
+    });
+    let iterations = configured_storage_kdf_iterations()?;
+    let storage_key = storage_key(password, salt, iterations)?;
+
+    let mut file_salt = [0u8; 16];
+    thread_rng().fill(&mut file_salt[..]);
+    let file_key = storage_file_key(&storage_key, &file_salt);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(file_key.as_slice()));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let encrypted_data = cipher
+        .encrypt(&nonce, data)
+        .map_err(|_| "encryption failure!")?;
+
+    let mut encrypted = Vec::new();
+    encrypted.extend_from_slice(STORAGE_HEADER_MAGIC);
+    encrypted.extend_from_slice(&iterations.to_be_bytes());
+    encrypted.extend_from_slice(salt);
+    encrypted.extend_from_slice(&file_salt);
+    encrypted.extend_from_slice(nonce.as_slice());
+    encrypted.extend_from_slice(&encrypted_data);
+    Ok(encrypted)
+}
+
+/// decrypts what encrypt_with_storage_key wrote, in this or any earlier process
+pub fn decrypt_with_storage_key(
+    encrypted: &[u8],
+    password: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if !encrypted.starts_with(STORAGE_HEADER_MAGIC) {
+        return Err("not encrypted with a storage key".into());
+    }
+    if encrypted.len() < STORAGE_HEADER_LEN + 16 + 12 {
+        return Err("encrypted data is too short".into());
+    }
+    let iterations = header_iterations(&encrypted[8..12])?;
+    let mut salt = [0u8; 16];
+    salt.copy_from_slice(&encrypted[12..STORAGE_HEADER_LEN]);
+    let (file_salt, rest) = encrypted[STORAGE_HEADER_LEN..].split_at(16);
+    let (nonce, encrypted_data) = rest.split_at(12);
+
+    let storage_key = storage_key(password, &salt, iterations)?;
+    let file_key = storage_file_key(&storage_key, file_salt);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(file_key.as_slice()));
+    Ok(cipher
+        .decrypt(Nonce::from_slice(nonce), encrypted_data)
+        .map_err(|_| "decryption failure! wrong password or corrupted data")?)
+}
@@ -1,4 +1,31 @@
-use sha2::{Digest, Sha256};
+use sha2::{Digest, Sha256, Sha512};
+use std::env;
+use std::error::Error;
+use std::str::FromStr;
+use strum_macros::{AsRefStr, Display, EnumString};
+use subtle::ConstantTimeEq;
+
+/// digests available to `hash_string_with`, and for document hashes via
+/// JACS_DOCUMENT_HASH_ALGORITHM. documents record theirs in jacsHashAlgorithm
+#[derive(Debug, Clone, Copy, PartialEq, AsRefStr, Display, EnumString)]
+pub enum HashAlgorithm {
+    #[strum(serialize = "sha256")]
+    Sha256,
+    #[strum(serialize = "sha512")]
+    Sha512,
+    #[strum(serialize = "blake3")]
+    Blake3,
+}
+
+/// the digest new documents and agents are hashed with, from JACS_DOCUMENT_HASH_ALGORITHM.
+/// sha256 when unset
+pub fn configured_hash_algorithm() -> Result<HashAlgorithm, Box<dyn Error>> {
+    match env::var("JACS_DOCUMENT_HASH_ALGORITHM") {
+        Ok(algorithm) if !algorithm.is_empty() => HashAlgorithm::from_str(&algorithm)
+            .map_err(|_| format!("unsupported document hash algorithm {}", algorithm).into()),
+        _ => Ok(HashAlgorithm::Sha256),
+    }
+}
 
 pub fn hash_string(input_string: &String) -> String {
     let mut hasher = Sha256::new();
@@ -8,6 +35,63 @@ pub fn hash_string(input_string: &String) -> String {
     return hashed_string;
 }
 
+/// hex digest of a string with the chosen algorithm
+pub fn hash_string_with(input_string: &str, algorithm: HashAlgorithm) -> String {
+    match algorithm {
+        HashAlgorithm::Sha256 => hash_string(&input_string.to_string()),
+        HashAlgorithm::Sha512 => {
+            let mut hasher = Sha512::new();
+            hasher.update(input_string.as_bytes());
+            format!("{:x}", hasher.finalize())
+        }
+        HashAlgorithm::Blake3 => blake3::hash(input_string.as_bytes()).to_hex().to_string(),
+    }
+}
+
+/// compares two digests without short-circuiting on the first differing byte.
+/// only the lengths, which are public for a given algorithm, can end the comparison early
+pub fn hashes_match(expected: &str, actual: &str) -> bool {
+    expected.as_bytes().ct_eq(actual.as_bytes()).into()
+}
+
+/// how public_key_fingerprint renders a key's digest
+#[derive(Debug, Clone, Copy, PartialEq, AsRefStr, Display, EnumString)]
+pub enum FingerprintStyle {
+    /// the full SHA-256 digest as colon separated hex pairs, aa:bb:cc:...
+    #[strum(serialize = "colonhex")]
+    ColonHex,
+    /// the full digest as unpadded RFC 4648 base32
+    #[strum(serialize = "base32")]
+    Base32,
+    /// twelve BIP39 English words from the first 128 bits of the digest, to read aloud
+    #[strum(serialize = "words")]
+    Words,
+}
+
+/// a human comparable fingerprint of a public key. the digest is the same normalized
+/// SHA-256 as hash_public_key, so fingerprints agree with publicKeyHash
+pub fn public_key_fingerprint(
+    public_key: &[u8],
+    style: FingerprintStyle,
+) -> Result<String, Box<dyn Error>> {
+    let digest_hex = hash_public_key(public_key.to_vec());
+    let digest = (0..digest_hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digest_hex[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()?;
+    Ok(match style {
+        FingerprintStyle::ColonHex => digest
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<String>>()
+            .join(":"),
+        FingerprintStyle::Base32 => data_encoding::BASE32_NOPAD.encode(&digest),
+        FingerprintStyle::Words => bip39::Mnemonic::from_entropy(&digest[..16])
+            .map_err(|e| e.to_string())?
+            .to_string(),
+    })
+}
+
 pub fn hash_public_key(public_key_bytes: Vec<u8>) -> String {
     let (encoding, _) =
         encoding_rs::Encoding::for_bom(&public_key_bytes).unwrap_or((encoding_rs::UTF_8, 0));
@@ -1,3 +1,14 @@
+//! timing safety of the verification paths:
+//! - RSA-PSS: the `rsa` crate recomputes and checks the PSS encoding internally,
+//!   JACS never compares signature bytes itself
+//! - ring-Ed25519: `ring` verifies in constant time
+//! - pq-dilithium: `pqcrypto` wraps the reference implementation, which is constant time
+//! - AES-256-GCM private key decryption: the `aes-gcm` crate checks the tag in constant time
+//!
+//! the comparisons JACS does itself (document hashes, public key hashes, agreement
+//! hashes) go through `hash::hashes_match`, see benches/hash_compare.rs
+
+use secrecy::zeroize::Zeroizing;
 use secrecy::ExposeSecret;
 pub mod hash;
 pub mod pq;
@@ -7,14 +18,16 @@ pub mod rsawrapper;
 pub mod aes_encrypt;
 
 use crate::agent::Agent;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine as _;
 use std::env;
-use std::str::FromStr;
 
 #[cfg(not(target_arch = "wasm32"))]
 use crate::agent::loaders::FileLoader;
-use strum_macros::{AsRefStr, Display, EnumString};
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, Display, EnumIter, EnumString};
 
-#[derive(Debug, AsRefStr, Display, EnumString)]
+#[derive(Debug, AsRefStr, Display, EnumString, EnumIter)]
 enum CryptoSigningAlgorithm {
     #[strum(serialize = "RSA-PSS")]
     RsaPss,
@@ -24,48 +37,252 @@ enum CryptoSigningAlgorithm {
     PqDilithium,
 }
 
+/// how an algorithm holds up against a quantum attacker, weakest first, so policies can
+/// compare against a floor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, AsRefStr, Display, EnumString)]
+pub enum AlgorithmStrength {
+    #[strum(serialize = "classical")]
+    Classical,
+    #[strum(serialize = "post-quantum")]
+    PostQuantum,
+}
+
+/// the strength of a signing algorithm as named in jacsSignature.signingAlgorithm
+pub fn algorithm_strength(
+    key_algorithm: &str,
+) -> Result<AlgorithmStrength, Box<dyn std::error::Error>> {
+    let algo = CryptoSigningAlgorithm::from_name(key_algorithm)?;
+    Ok(match algo {
+        CryptoSigningAlgorithm::RsaPss | CryptoSigningAlgorithm::RingEd25519 => {
+            AlgorithmStrength::Classical
+        }
+        CryptoSigningAlgorithm::PqDilithium => AlgorithmStrength::PostQuantum,
+    })
+}
+
+/// a signing algorithm this build supports, for callers offering a choice of algorithm
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlgorithmInfo {
+    /// as used for JACS_AGENT_KEY_ALGORITHM and in jacsSignature.signingAlgorithm
+    pub name: String,
+    /// other names accepted wherever an algorithm is named, e.g. generate_key_pair
+    pub aliases: Vec<String>,
+    pub strength: AlgorithmStrength,
+    /// how the private and public keys are stored
+    pub key_encoding: String,
+}
+
+impl CryptoSigningAlgorithm {
+    /// parses a name or alias, ignoring case, e.g. "ed25519" gives RingEd25519
+    fn from_name(name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let lowercase = name.to_lowercase();
+        CryptoSigningAlgorithm::iter()
+            .find(|algo| {
+                algo.as_ref().to_lowercase() == lowercase
+                    || algo.aliases().contains(&lowercase.as_str())
+            })
+            .ok_or_else(|| format!("{} is not a known or implemented algorithm.", name).into())
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            CryptoSigningAlgorithm::RsaPss => &["rsa", "rsa-pss"],
+            CryptoSigningAlgorithm::RingEd25519 => &["ed25519"],
+            CryptoSigningAlgorithm::PqDilithium => &["dilithium", "pq"],
+        }
+    }
+
+    fn key_encoding(&self) -> &'static str {
+        match self {
+            CryptoSigningAlgorithm::RsaPss => "pkcs8-pem",
+            CryptoSigningAlgorithm::RingEd25519 => "pkcs8-der private, raw public",
+            CryptoSigningAlgorithm::PqDilithium => "raw",
+        }
+    }
+}
+
+/// every signing algorithm KeyManager can use in this build
+pub fn supported_algorithms() -> Vec<AlgorithmInfo> {
+    CryptoSigningAlgorithm::iter()
+        .map(|algo| AlgorithmInfo {
+            name: algo.to_string(),
+            aliases: algo
+                .aliases()
+                .iter()
+                .map(|alias| alias.to_string())
+                .collect(),
+            strength: algorithm_strength(algo.as_ref())
+                .expect("every supported algorithm has a strength"),
+            key_encoding: algo.key_encoding().to_string(),
+        })
+        .collect()
+}
+
+/// the algorithm name for a name or alias, ignoring case, e.g. "ed25519" gives "ring-Ed25519"
+pub fn resolve_algorithm_name(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(CryptoSigningAlgorithm::from_name(name)?.to_string())
+}
+
+pub const JACS_PQ_ONLY: &str = "JACS_PQ_ONLY";
+
+/// JACS_PQ_ONLY, off by default. when on, signatures from algorithms below post-quantum
+/// strength are refused however valid they are
+pub fn pq_only() -> bool {
+    let env_var_value = env::var(JACS_PQ_ONLY).unwrap_or_else(|_| "false".to_string());
+    matches!(env_var_value.to_lowercase().as_str(), "true" | "1")
+}
+
+/// Err unless the algorithm is post-quantum strength
+pub fn require_post_quantum(key_algorithm: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let strength = algorithm_strength(key_algorithm)?;
+    if strength < AlgorithmStrength::PostQuantum {
+        return Err(format!(
+            "signing algorithm {} is {}, {} is required",
+            key_algorithm,
+            strength,
+            AlgorithmStrength::PostQuantum
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// under JACS_PQ_ONLY, Err unless the algorithm is post-quantum strength. without one,
+/// the agent's configured JACS_AGENT_KEY_ALGORITHM is checked
+pub fn enforce_pq_only(key_algorithm: Option<&String>) -> Result<(), Box<dyn std::error::Error>> {
+    if !pq_only() {
+        return Ok(());
+    }
+    match key_algorithm {
+        Some(key_algorithm) => require_post_quantum(key_algorithm),
+        None => require_post_quantum(&env::var(JACS_AGENT_KEY_ALGORITHM)?),
+    }
+}
+
+/// text encodings for signature bytes. the key wrappers produce base64, other
+/// encodings are converted to and from it. base64url omits padding
+#[derive(Debug, Clone, Copy, PartialEq, Default, AsRefStr, Display, EnumString)]
+pub enum SignatureEncoding {
+    #[default]
+    #[strum(serialize = "base64")]
+    Base64,
+    #[strum(serialize = "base64url")]
+    Base64Url,
+    #[strum(serialize = "base58")]
+    Base58,
+}
+
+/// re-encodes a base64 signature from one of the key wrappers
+pub fn encode_signature(
+    signature_base64: &str,
+    encoding: SignatureEncoding,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if encoding == SignatureEncoding::Base64 {
+        return Ok(signature_base64.to_string());
+    }
+    let signature_bytes = STANDARD.decode(signature_base64)?;
+    Ok(match encoding {
+        SignatureEncoding::Base64 => signature_base64.to_string(),
+        SignatureEncoding::Base64Url => URL_SAFE_NO_PAD.encode(signature_bytes),
+        SignatureEncoding::Base58 => bs58::encode(signature_bytes).into_string(),
+    })
+}
+
+/// the base64 form of a signature in `encoding`, as the key wrappers expect
+pub fn decode_signature(
+    signature: &str,
+    encoding: SignatureEncoding,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let signature_bytes = match encoding {
+        SignatureEncoding::Base64 => return Ok(signature.to_string()),
+        SignatureEncoding::Base64Url => URL_SAFE_NO_PAD.decode(signature)?,
+        SignatureEncoding::Base58 => bs58::decode(signature).into_vec()?,
+    };
+    Ok(STANDARD.encode(signature_bytes))
+}
+
 pub const JACS_KEY_DIRECTORY: &str = "JACS_KEY_DIRECTORY";
 const JACS_AGENT_PRIVATE_KEY_PASSWORD: &str = "JACS_AGENT_PRIVATE_KEY_PASSWORD";
 pub const JACS_AGENT_PRIVATE_KEY_FILENAME: &str = "JACS_AGENT_PRIVATE_KEY_FILENAME";
 pub const JACS_AGENT_PUBLIC_KEY_FILENAME: &str = "JACS_AGENT_PUBLIC_KEY_FILENAME";
 pub const JACS_AGENT_KEY_ALGORITHM: &str = "JACS_AGENT_KEY_ALGORITHM";
 
+/// a new (private key, public key) pair for the algorithm, in memory only.
+/// KeyManager::generate_keys also saves the pair to the key directory
+pub fn generate_key_pair(
+    key_algorithm: &str,
+) -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>> {
+    let algo = CryptoSigningAlgorithm::from_name(key_algorithm)?;
+    let key_pair = match algo {
+        CryptoSigningAlgorithm::RsaPss => rsawrapper::generate_keys().map_err(|e| e.to_string())?,
+        CryptoSigningAlgorithm::RingEd25519 => {
+            ringwrapper::generate_keys().map_err(|e| e.to_string())?
+        }
+        CryptoSigningAlgorithm::PqDilithium => pq::generate_keys().map_err(|e| e.to_string())?,
+    };
+    Ok(key_pair)
+}
+
 pub trait KeyManager {
     fn generate_keys(&mut self) -> Result<(), Box<dyn std::error::Error>>;
-    fn sign_string(&mut self, data: &String) -> Result<String, Box<dyn std::error::Error>>;
+    /// signs with the configured algorithm. `encoding` defaults to base64
+    fn sign_string(
+        &mut self,
+        data: &String,
+        encoding: Option<SignatureEncoding>,
+    ) -> Result<String, Box<dyn std::error::Error>>;
+    /// `encoding` must be the one the signature was made with, base64 when None
     fn verify_string(
         &self,
         data: &String,
-        signature_base64: &String,
+        signature: &String,
+        public_key: Vec<u8>,
+        public_key_enc_type: Option<String>,
+        encoding: Option<SignatureEncoding>,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+    /// signs raw bytes with the configured algorithm, returning a base64 signature.
+    /// for binary payloads that are not valid UTF-8
+    fn sign_bytes(&mut self, data: &[u8]) -> Result<String, Box<dyn std::error::Error>>;
+    fn verify_bytes(
+        &self,
+        data: &[u8],
+        signature_base64: &str,
         public_key: Vec<u8>,
         public_key_enc_type: Option<String>,
     ) -> Result<(), Box<dyn std::error::Error>>;
+    /// signs each message as `messages` yields it, handing `on_result` its 0-based index
+    /// and base64 signature, so a stream of any length is signed without collecting it.
+    /// the private key is decrypted once, held for the whole batch and zeroized after,
+    /// even if on_result panics.
+    /// a message that fails to sign is reported and the batch continues.
+    /// returns the number of messages signed
+    fn sign_batch_streaming<I, F>(
+        &mut self,
+        messages: I,
+        on_result: F,
+    ) -> Result<usize, Box<dyn std::error::Error>>
+    where
+        I: Iterator<Item = String>,
+        F: FnMut(usize, Result<String, Box<dyn std::error::Error>>);
+}
+
+fn sign_bytes_with_key(
+    algo: &CryptoSigningAlgorithm,
+    key_vec: &[u8],
+    data: &[u8],
+) -> Result<String, Box<dyn std::error::Error>> {
+    match algo {
+        CryptoSigningAlgorithm::RsaPss => rsawrapper::sign_bytes(key_vec, data),
+        CryptoSigningAlgorithm::RingEd25519 => ringwrapper::sign_bytes(key_vec, data),
+        CryptoSigningAlgorithm::PqDilithium => pq::sign_bytes(key_vec, data),
+    }
 }
 
 impl KeyManager for Agent {
     /// this necessatates updateding the version of the agent
     fn generate_keys(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let key_algorithm = env::var(JACS_AGENT_KEY_ALGORITHM)?;
-        let (mut private_key, mut public_key) = (Vec::new(), Vec::new());
-        let algo = CryptoSigningAlgorithm::from_str(&key_algorithm).unwrap();
-        match algo {
-            CryptoSigningAlgorithm::RsaPss => {
-                (private_key, public_key) =
-                    rsawrapper::generate_keys().map_err(|e| e.to_string())?;
-            }
-            CryptoSigningAlgorithm::RingEd25519 => {
-                (private_key, public_key) =
-                    ringwrapper::generate_keys().map_err(|e| e.to_string())?;
-            }
-            CryptoSigningAlgorithm::PqDilithium => {
-                (private_key, public_key) = pq::generate_keys().map_err(|e| e.to_string())?;
-            }
-            _ => {
-                return Err(
-                    format!("{} is not a known or implemented algorithm.", key_algorithm).into(),
-                );
-            }
-        }
+        let (private_key, public_key) = generate_key_pair(&key_algorithm)?;
 
         let _ = self.set_keys(private_key, public_key, &key_algorithm);
         #[cfg(not(target_arch = "wasm32"))]
@@ -74,65 +291,83 @@ impl KeyManager for Agent {
         Ok(())
     }
 
-    fn sign_string(&mut self, data: &String) -> Result<String, Box<dyn std::error::Error>> {
-        let key_algorithm = env::var(JACS_AGENT_KEY_ALGORITHM)?;
-        let algo = CryptoSigningAlgorithm::from_str(&key_algorithm).unwrap();
-        match algo {
-            CryptoSigningAlgorithm::RsaPss => {
-                let binding = self.get_private_key()?;
-                let borrowed_key = binding.expose_secret();
-                let key_vec = borrowed_key.use_secret();
-
-                return rsawrapper::sign_string(key_vec.to_vec(), data);
-            }
-            CryptoSigningAlgorithm::RingEd25519 => {
-                let binding = self.get_private_key()?;
-                let borrowed_key = binding.expose_secret();
-                let key_vec = borrowed_key.use_secret();
-                return ringwrapper::sign_string(key_vec.to_vec(), data);
-            }
-            CryptoSigningAlgorithm::PqDilithium => {
-                let binding = self.get_private_key()?;
-                let borrowed_key = binding.expose_secret();
-                let key_vec = borrowed_key.use_secret();
-                return pq::sign_string(key_vec.to_vec(), data);
-            }
-            _ => {
-                return Err(
-                    format!("{} is not a known or implemented algorithm.", key_algorithm).into(),
-                );
-            }
-        }
+    fn sign_string(
+        &mut self,
+        data: &String,
+        encoding: Option<SignatureEncoding>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let signature_base64 = self.sign_bytes(data.as_bytes())?;
+        encode_signature(&signature_base64, encoding.unwrap_or_default())
     }
     fn verify_string(
         &self,
         data: &String,
-        signature_base64: &String,
+        signature: &String,
+        public_key: Vec<u8>,
+        public_key_enc_type: Option<String>,
+        encoding: Option<SignatureEncoding>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let signature_base64 = decode_signature(signature, encoding.unwrap_or_default())?;
+        self.verify_bytes(
+            data.as_bytes(),
+            &signature_base64,
+            public_key,
+            public_key_enc_type,
+        )
+    }
+    fn sign_bytes(&mut self, data: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+        let key_algorithm = self.key_algorithm()?;
+        let algo = CryptoSigningAlgorithm::from_name(&key_algorithm)?;
+        let binding = self.get_private_key()?;
+        let key_vec = Zeroizing::new(binding.expose_secret().use_secret());
+        sign_bytes_with_key(&algo, &key_vec, data)
+    }
+    fn verify_bytes(
+        &self,
+        data: &[u8],
+        signature_base64: &str,
         public_key: Vec<u8>,
         public_key_enc_type: Option<String>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let key_algorithm = env::var(JACS_AGENT_KEY_ALGORITHM)?;
         let algo = match public_key_enc_type {
-            Some(public_key_enc_type) => CryptoSigningAlgorithm::from_str(&public_key_enc_type)?,
-            None => CryptoSigningAlgorithm::from_str(&key_algorithm)?,
+            Some(public_key_enc_type) => CryptoSigningAlgorithm::from_name(&public_key_enc_type)?,
+            None => CryptoSigningAlgorithm::from_name(&self.key_algorithm()?)?,
         };
 
         match algo {
             CryptoSigningAlgorithm::RsaPss => {
-                return rsawrapper::verify_string(public_key, data, signature_base64)
+                rsawrapper::verify_bytes(public_key, data, signature_base64)
             }
             CryptoSigningAlgorithm::RingEd25519 => {
-                return ringwrapper::verify_string(public_key, data, signature_base64)
+                ringwrapper::verify_bytes(public_key, data, signature_base64)
             }
             CryptoSigningAlgorithm::PqDilithium => {
-                return pq::verify_string(public_key, data, signature_base64)
+                pq::verify_bytes(public_key, data, signature_base64)
             }
-            _ => {
-                return Err(
-                    format!("{} is not a known or implemented algorithm.", key_algorithm).into(),
-                );
+        }
+    }
+    fn sign_batch_streaming<I, F>(
+        &mut self,
+        messages: I,
+        mut on_result: F,
+    ) -> Result<usize, Box<dyn std::error::Error>>
+    where
+        I: Iterator<Item = String>,
+        F: FnMut(usize, Result<String, Box<dyn std::error::Error>>),
+    {
+        let key_algorithm = self.key_algorithm()?;
+        let algo = CryptoSigningAlgorithm::from_name(&key_algorithm)?;
+        let binding = self.get_private_key()?;
+        // zeroized when dropped, including when on_result panics
+        let key_vec = Zeroizing::new(binding.expose_secret().use_secret());
+        let mut signed = 0;
+        for (index, message) in messages.enumerate() {
+            let result = sign_bytes_with_key(&algo, &key_vec, message.as_bytes());
+            if result.is_ok() {
+                signed += 1;
             }
+            on_result(index, result);
         }
-        Ok(())
+        Ok(signed)
     }
 }
@@ -1,10 +1,9 @@
 use pqcrypto_dilithium::dilithium5::{
-    keypair, sign, verify_detached_signature, DetachedSignature, PublicKey, SecretKey,
+    detached_sign, keypair, verify_detached_signature, DetachedSignature, PublicKey, SecretKey,
 };
 use pqcrypto_traits::sign::DetachedSignature as DetachedSignatureTrait;
 use pqcrypto_traits::sign::PublicKey as PublicKeyTrait;
 use pqcrypto_traits::sign::SecretKey as SecretKeyTrait;
-use pqcrypto_traits::sign::SignedMessage as SignedMessageTrait;
 
 use std::error::Error;
 
@@ -14,8 +13,13 @@ pub fn generate_keys() -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>>
 }
 
 pub fn sign_string(secret_key: Vec<u8>, data: &String) -> Result<String, Box<dyn Error>> {
-    let mut secret_key_obj: SecretKey = SecretKey::from_bytes(&secret_key)?;
-    let signature = sign(data.as_bytes(), &secret_key_obj);
+    sign_bytes(&secret_key, data.as_bytes())
+}
+
+/// a detached signature, so verify_bytes can check it against the original data
+pub fn sign_bytes(secret_key: &[u8], data: &[u8]) -> Result<String, Box<dyn Error>> {
+    let secret_key_obj: SecretKey = SecretKey::from_bytes(secret_key)?;
+    let signature = detached_sign(data, &secret_key_obj);
     let signature_bytes = signature.as_bytes();
     let signature_base64 = base64::encode(signature_bytes);
     Ok(signature_base64)
@@ -25,10 +29,18 @@ pub fn verify_string(
     public_key: Vec<u8>,
     data: &String,
     signature_base64: &String,
+) -> Result<(), Box<dyn Error>> {
+    verify_bytes(public_key, data.as_bytes(), signature_base64)
+}
+
+pub fn verify_bytes(
+    public_key: Vec<u8>,
+    data: &[u8],
+    signature_base64: &str,
 ) -> Result<(), Box<dyn Error>> {
     let signature_bytes = base64::decode(signature_base64)?;
     let signature = DetachedSignature::from_bytes(&signature_bytes)?;
     let pk = PublicKey::from_bytes(&public_key)?;
-    verify_detached_signature(&signature, data.as_bytes(), &pk)
+    verify_detached_signature(&signature, data, &pk)
         .map_err(|e| format!("Verification failed: {:?}", e).into())
 }
@@ -17,9 +17,13 @@ pub fn generate_keys() -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>>
 }
 
 pub fn sign_string(secret_key: Vec<u8>, data: &String) -> Result<String, Box<dyn Error>> {
+    sign_bytes(&secret_key, data.as_bytes())
+}
+
+pub fn sign_bytes(secret_key: &[u8], data: &[u8]) -> Result<String, Box<dyn Error>> {
     let key_pair =
-        signature::Ed25519KeyPair::from_pkcs8(&secret_key).map_err(|e| KeyRejectedError(e))?;
-    let signature = key_pair.sign(data.as_bytes());
+        signature::Ed25519KeyPair::from_pkcs8(secret_key).map_err(|e| KeyRejectedError(e))?;
+    let signature = key_pair.sign(data);
     let signature_bytes = signature.as_ref();
     let signature_base64 = base64::encode(signature_bytes);
     Ok(signature_base64)
@@ -29,11 +33,19 @@ pub fn verify_string(
     public_key: Vec<u8>,
     data: &String,
     signature_base64: &String,
+) -> Result<(), Box<dyn Error>> {
+    verify_bytes(public_key, data.as_bytes(), signature_base64)
+}
+
+pub fn verify_bytes(
+    public_key: Vec<u8>,
+    data: &[u8],
+    signature_base64: &str,
 ) -> Result<(), Box<dyn Error>> {
     let signature_bytes = base64::decode(signature_base64)?;
     let public_key = UnparsedPublicKey::new(&signature::ED25519, public_key);
     public_key
-        .verify(data.as_bytes(), &signature_bytes)
+        .verify(data, &signature_bytes)
         .map_err(|e| RingError(e))?;
     Ok(())
 }
@@ -35,21 +35,30 @@ pub fn generate_keys() -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>>
 pub fn sign_string(
     private_key_content: Vec<u8>,
     data: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let signature_base64 = sign_bytes(&private_key_content, data.as_bytes())?;
+    debug!(
+        "xxx sign_string  sig: {}     --------CONTENT: {}",
+        signature_base64, data
+    );
+    Ok(signature_base64)
+}
+
+/// signs raw bytes, returning the signature base64 encoded
+pub fn sign_bytes(
+    private_key_content: &[u8],
+    data: &[u8],
 ) -> Result<String, Box<dyn std::error::Error>> {
     let private_key_content_converted =
-        std::str::from_utf8(&private_key_content).expect("Failed to convert bytes to string");
+        std::str::from_utf8(private_key_content).expect("Failed to convert bytes to string");
     let private_key = RsaPrivateKey::from_pkcs8_pem(&private_key_content_converted)?;
     let mut rng = thread_rng();
     let signing_key = BlindedSigningKey::<Sha256>::new(private_key);
-    let signature = signing_key.sign_with_rng(&mut rng, data.as_bytes());
+    let signature = signing_key.sign_with_rng(&mut rng, data);
     let signature_bytes = signature.to_bytes();
     let signature_base64 = encode(&signature_bytes);
     // TODO
     // assert_ne!(signature.to_bytes().as_ref(), data);
-    debug!(
-        "xxx sign_string  sig: {}     --------CONTENT: {}",
-        signature_base64, data
-    );
     Ok(signature_base64)
 }
 
@@ -57,6 +66,19 @@ pub fn verify_string(
     public_key_content: Vec<u8>,
     data: &String,
     signature_base64: &String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    debug!(
+        "xxx verify_string  sig: {}     --------CONTENT: {}",
+        signature_base64, data
+    );
+    verify_bytes(public_key_content, data.as_bytes(), signature_base64)
+}
+
+/// verifies a base64 signature over raw bytes
+pub fn verify_bytes(
+    public_key_content: Vec<u8>,
+    data: &[u8],
+    signature_base64: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let public_key_content_converted =
         std::str::from_utf8(&public_key_content).expect("Failed to convert bytes to string");
@@ -74,18 +96,13 @@ pub fn verify_string(
     let verifying_key = VerifyingKey::<Sha256>::from(public_key);
     debug!("verifying_key pem {:?}", verifying_key);
 
-    debug!(
-        "xxx verify_string  sig: {}     --------CONTENT: {}",
-        signature_base64, data
-    );
-
     let signature_bytes = decode(signature_base64)?;
     debug!("Decoded signature bytes: {:?}", signature_bytes);
 
     let signature = Signature::try_from(signature_bytes.as_slice())?;
     debug!("Created Signature object: {:?}", signature);
 
-    let result = verifying_key.verify(data.as_bytes(), &signature);
+    let result = verifying_key.verify(data, &signature);
 
     match result {
         Ok(()) => {
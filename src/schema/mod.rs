@@ -1,13 +1,17 @@
+use crate::crypt::hash::hash_string;
 use crate::schema::utils::ValueExt;
 use crate::schema::utils::CONFIG_SCHEMA_STRING;
 use chrono::prelude::*;
+use jsonschema::error::ValidationErrorKind;
 use jsonschema::SchemaResolver;
 use jsonschema::{Draft, JSONSchema};
 use log::{debug, error, warn};
 use serde_json::json;
 use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 
+use ulid::Ulid;
 use url::Url;
 use uuid::Uuid;
 
@@ -21,10 +25,13 @@ pub mod task_crud;
 pub mod tools_crud;
 pub mod utils;
 
-use utils::{EmbeddedSchemaResolver, DEFAULT_SCHEMA_STRINGS};
+use utils::{parse_untrusted_json, EmbeddedSchemaResolver, DEFAULT_SCHEMA_STRINGS};
 
+use std::env;
 use std::error::Error;
 use std::fmt;
+use std::str::FromStr;
+use strum_macros::{AsRefStr, Display, EnumString};
 
 // Custom error type
 #[derive(Debug)]
@@ -38,6 +45,98 @@ impl fmt::Display for ValidationError {
 
 impl Error for ValidationError {}
 
+/// how new documents get their jacsId and jacsVersion, from Agent::set_id_strategy or
+/// JACS_DOCUMENT_ID_STRATEGY.
+/// ids are opaque to verification, so documents made with any strategy load the same way
+#[derive(Debug, Clone, Copy, PartialEq, Default, AsRefStr, Display, EnumString)]
+pub enum IdStrategy {
+    /// random uuid v4
+    #[default]
+    #[strum(serialize = "uuid")]
+    Uuid,
+    /// lexically sortable by creation time
+    #[strum(serialize = "ulid")]
+    Ulid,
+    /// sha256 of the content the id is assigned to, so identical content gets the same id
+    #[strum(serialize = "content-hash")]
+    ContentHash,
+}
+
+impl IdStrategy {
+    /// a new id for `content`, which only ContentHash looks at
+    pub fn new_id(&self, content: &Value) -> String {
+        match self {
+            IdStrategy::Uuid => Uuid::new_v4().to_string(),
+            IdStrategy::Ulid => Ulid::new().to_string(),
+            IdStrategy::ContentHash => hash_string(&content.to_string()),
+        }
+    }
+}
+
+/// the id strategy for new documents and versions. agents always use uuids
+pub fn configured_id_strategy() -> Result<IdStrategy, Box<dyn Error>> {
+    match env::var("JACS_DOCUMENT_ID_STRATEGY") {
+        Ok(strategy) if !strategy.is_empty() => IdStrategy::from_str(&strategy)
+            .map_err(|_| format!("unsupported document id strategy {}", strategy).into()),
+        _ => Ok(IdStrategy::Uuid),
+    }
+}
+
+/// agent schema versions embedded in this build, e.g. ["v1"]
+pub fn supported_agent_schema_versions() -> Vec<String> {
+    let mut versions: Vec<String> = DEFAULT_SCHEMA_STRINGS
+        .keys()
+        .filter_map(|path| path.strip_prefix("schemas/agent/"))
+        .filter_map(|rest| rest.split('/').next())
+        .map(String::from)
+        .collect();
+    versions.sort();
+    versions
+}
+
+/// whether an agent document, made under whichever schema version its $schema names,
+/// still validates with this build. returns JSON {agent_schema_version,
+/// library_supported_versions, compatible}, plus error when it doesn't validate
+pub fn agent_schema_compatibility(agent_json: &str) -> Result<String, Box<dyn Error>> {
+    let agent = parse_untrusted_json(agent_json)?;
+    let schema_url = agent
+        .get_str("$schema")
+        .ok_or("agent document has no $schema")?;
+    let agent_schema_version = schema_url
+        .split('/')
+        .skip_while(|segment| *segment != "agent")
+        .nth(1)
+        .ok_or(format!("no agent schema version in {}", schema_url))?
+        .to_string();
+    let library_supported_versions = supported_agent_schema_versions();
+
+    let validation = if library_supported_versions.contains(&agent_schema_version) {
+        let header_version =
+            env::var("JACS_HEADER_SCHEMA_VERSION").unwrap_or_else(|_| "v1".to_string());
+        let signature_version =
+            env::var("JACS_SIGNATURE_SCHEMA_VERSION").unwrap_or_else(|_| "v1".to_string());
+        Schema::new(&agent_schema_version, &header_version, &signature_version)
+            .and_then(|schema| schema.validate_agent(agent_json))
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    } else {
+        Err(format!(
+            "agent schema version {} is not built into this library",
+            agent_schema_version
+        ))
+    };
+
+    let mut report = json!({
+        "agent_schema_version": agent_schema_version,
+        "library_supported_versions": library_supported_versions,
+        "compatible": validation.is_ok(),
+    });
+    if let Err(e) = validation {
+        report["error"] = json!(e);
+    }
+    Ok(serde_json::to_string(&report)?)
+}
+
 #[derive(Debug)]
 pub struct Schema {
     /// used to validate any JACS document
@@ -79,6 +178,82 @@ impl Schema {
         return self._extract_hai_fields(document, &schema_url, level, &mut processed_fields);
     }
 
+    /// the fields extract_hai_fields keeps at each level for documents of a schema, found from
+    /// the schema's hai tags without needing a document. returns {agent, meta, base}, each a
+    /// sorted list of field names; fields of embedded schemas are listed as parent.child.
+    /// as in extraction, meta includes the agent fields and base includes every field
+    pub fn hai_fields_for_schema(&self, schema_url: &str) -> Result<Value, Box<dyn Error>> {
+        let mut levels: BTreeMap<&str, BTreeSet<String>> = BTreeMap::new();
+        for level in ["agent", "meta", "base"] {
+            levels.insert(level, BTreeSet::new());
+        }
+        let mut visiting: Vec<String> = Vec::new();
+        self.collect_hai_fields(schema_url, "", &mut levels, &mut visiting)?;
+        Ok(json!(levels))
+    }
+
+    fn collect_hai_fields(
+        &self,
+        schema_url: &str,
+        prefix: &str,
+        levels: &mut BTreeMap<&str, BTreeSet<String>>,
+        visiting: &mut Vec<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        // schemas can embed themselves, e.g. tasks holding subtasks
+        if visiting.iter().any(|visited| visited == schema_url) {
+            return Ok(());
+        }
+        visiting.push(schema_url.to_string());
+        let url = Url::parse("https://hai.ai")?.join(schema_url)?;
+        let schema_value = EmbeddedSchemaResolver::new().resolve(&Value::Null, &url, schema_url)?;
+
+        let mut property_sets: Vec<&Value> = Vec::new();
+        if let Some(Value::Array(all_of)) = schema_value.get("allOf") {
+            for item in all_of {
+                if let Some(ref_schema_url) = item.get_str("$ref") {
+                    self.collect_hai_fields(&ref_schema_url, prefix, levels, visiting)?;
+                }
+                if let Some(properties) = item.get("properties") {
+                    property_sets.push(properties);
+                }
+            }
+        } else if let Some(properties) = schema_value.get("properties") {
+            property_sets.push(properties);
+        }
+
+        for properties in property_sets {
+            let properties = match properties.as_object() {
+                Some(properties) => properties,
+                None => continue,
+            };
+            for (field_name, field_schema) in properties {
+                let field_path = format!("{}{}", prefix, field_name);
+                let hai_level = field_schema.get_str("hai").unwrap_or_default();
+                let field_levels: &[&str] = match hai_level.as_str() {
+                    "agent" => &["agent", "meta", "base"],
+                    "meta" => &["meta", "base"],
+                    _ => &["base"],
+                };
+                for level in field_levels {
+                    if let Some(fields) = levels.get_mut(level) {
+                        fields.insert(field_path.clone());
+                    }
+                }
+                let embedded_schema_url = field_schema.get_str("$ref").or_else(|| {
+                    field_schema
+                        .get("items")
+                        .and_then(|items| items.get_str("$ref"))
+                });
+                if let Some(embedded_schema_url) = embedded_schema_url {
+                    let child_prefix = format!("{}.", field_path);
+                    self.collect_hai_fields(&embedded_schema_url, &child_prefix, levels, visiting)?;
+                }
+            }
+        }
+        visiting.pop();
+        Ok(())
+    }
+
     fn _extract_hai_fields(
         &self,
         document: &Value,
@@ -544,6 +719,8 @@ impl Schema {
         })
     }
 
+    /// checks a jacs.config.json document against the config schema. every problem is
+    /// reported, one per line, prefixed with its JSON path
     pub fn validate_config(
         &self,
         json: &str,
@@ -566,26 +743,56 @@ impl Schema {
             Ok(_) => Ok(instance.clone()),
             Err(errors) => {
                 error!("error validating config file");
-                let error_messages: Vec<String> =
-                    errors.into_iter().map(|e| e.to_string()).collect();
-                Err(error_messages
-                    .first()
-                    .cloned()
-                    .unwrap_or_else(|| {
-                        "Unexpected error during validation: no error messages found".to_string()
+                let error_messages: Vec<String> = errors
+                    .map(|e| {
+                        let path = e.instance_path.to_string();
+                        if path.is_empty() {
+                            e.to_string()
+                        } else {
+                            format!("{}: {}", path, e)
+                        }
                     })
-                    .into())
+                    .collect();
+                Err(format!("invalid config:\n{}", error_messages.join("\n")).into())
             }
         }
     }
 
     /// basic check this conforms to a schema
     /// validate header does not check hashes or signature
+    /// validate_header for documents from newer producers. properties the schema does not
+    /// allow are returned as JSON pointers instead of failing, any other error still fails
+    pub fn validate_header_lenient(
+        &self,
+        json: &str,
+    ) -> Result<(Value, Vec<String>), Box<dyn std::error::Error + 'static>> {
+        let instance = parse_untrusted_json(json).map_err(|e| format!("Invalid JSON: {}", e))?;
+        let mut unknown_fields: Vec<String> = Vec::new();
+        if let Err(errors) = self.headerschema.validate(&instance) {
+            for error in errors {
+                match &error.kind {
+                    ValidationErrorKind::AdditionalProperties { unexpected } => {
+                        for field in unexpected {
+                            unknown_fields.push(format!(
+                                "{}/{}",
+                                error.instance_path,
+                                field.replace('~', "~0").replace('/', "~1")
+                            ));
+                        }
+                    }
+                    _ => return Err(error.to_string().into()),
+                }
+            }
+        }
+        unknown_fields.sort();
+        Ok((instance, unknown_fields))
+    }
+
     pub fn validate_header(
         &self,
         json: &str,
     ) -> Result<Value, Box<dyn std::error::Error + 'static>> {
-        let instance: serde_json::Value = match serde_json::from_str(json) {
+        let instance: serde_json::Value = match parse_untrusted_json(json) {
             Ok(value) => {
                 debug!("validate json {:?}", value);
                 value
@@ -650,6 +857,40 @@ impl Schema {
         }
     }
 
+    /// checks a value against one of the embedded schemas, e.g.
+    /// "schemas/message/v1/message.schema.json". every failing field is reported
+    pub fn validate_embedded(
+        &self,
+        schema_path: &str,
+        json: &Value,
+    ) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        let schema_data = DEFAULT_SCHEMA_STRINGS
+            .get(schema_path)
+            .ok_or_else(|| format!("unknown embedded schema {}", schema_path))?;
+        let schema_value: Value = serde_json::from_str(schema_data)?;
+        let schema = JSONSchema::options()
+            .with_draft(Draft::Draft7)
+            .with_resolver(EmbeddedSchemaResolver::new())
+            .compile(&schema_value)
+            .map_err(|e| format!("Failed to compile {}: {}", schema_path, e))?;
+        let result = match schema.validate(json) {
+            Ok(()) => Ok(()),
+            Err(errors) => {
+                let error_messages: Vec<String> = errors
+                    .map(|e| format!("{}: {}", e.instance_path, e))
+                    .collect();
+                let error_message = format!(
+                    "does not match {}: {}",
+                    schema_path,
+                    error_messages.join(", ")
+                );
+                error!("{}", error_message);
+                Err(Box::new(ValidationError(error_message)) as Box<dyn std::error::Error>)
+            }
+        };
+        result
+    }
+
     /// basic check this conforms to a schema
     /// validate header does not check hashes or signature
     pub fn validate_signature(
@@ -719,10 +960,101 @@ impl Schema {
     }
 
     /// load a document that has data but no id or version
-    /// an id and version is assigned
+    /// an id and version is assigned with `id_strategy`
     /// header is validated
     /// document is reeturned
-    pub fn create(&self, json: &str) -> Result<Value, Box<dyn std::error::Error + 'static>> {
+    pub fn create(
+        &self,
+        json: &str,
+        id_strategy: IdStrategy,
+    ) -> Result<Value, Box<dyn std::error::Error + 'static>> {
+        let instance = self.new_header(json, id_strategy)?;
+        let validation_result = self.headerschema.validate(&instance);
+
+        match validation_result {
+            Ok(instance) => instance,
+            Err(errors) => {
+                let error_messages: Vec<String> =
+                    errors.into_iter().map(|e| e.to_string()).collect();
+                let error_message = error_messages.first().cloned().unwrap_or_else(|| {
+                    "Unexpected error during validation: no error messages found".to_string()
+                });
+                error!("{}", error_message);
+                return Err(Box::new(ValidationError(error_message))
+                    as Box<dyn std::error::Error + 'static>);
+            }
+        };
+
+        Ok(instance.clone())
+    }
+
+    /// create for a consumer on another header schema version: the document's $schema is
+    /// that version's header schema and it is validated against it. versions without an
+    /// embedded schema are refused, as is a document whose own $schema names another one
+    pub fn create_for_header_version(
+        &self,
+        json: &str,
+        header_version: &str,
+        id_strategy: IdStrategy,
+    ) -> Result<Value, Box<dyn std::error::Error + 'static>> {
+        let header_path = format!("schemas/header/{}/header.schema.json", header_version);
+        let headerdata = DEFAULT_SCHEMA_STRINGS
+            .get(&header_path)
+            .ok_or(format!("unknown header schema version {}", header_version))?;
+        let headerschema_result: Value = serde_json::from_str(headerdata)?;
+        let headerschema = match JSONSchema::options()
+            .with_draft(Draft::Draft7)
+            .with_resolver(EmbeddedSchemaResolver::new())
+            .compile(&headerschema_result)
+        {
+            Ok(schema) => schema,
+            Err(_) => {
+                return Err(format!("Failed to compile headerschema: {}", &header_path).into())
+            }
+        };
+
+        let header_url = format!("https://hai.ai/{}", header_path);
+        let mut document: Value = serde_json::from_str(json)?;
+        if let Some(declared_schema) = document.get_str("$schema") {
+            if declared_schema != header_url {
+                return Err(format!(
+                    "document declares $schema {}, not header version {}",
+                    declared_schema, header_version
+                )
+                .into());
+            }
+        } else if let Some(map) = document.as_object_mut() {
+            map.insert("$schema".to_string(), json!(header_url));
+        }
+        let instance = self.new_header(&document.to_string(), id_strategy)?;
+
+        if let Err(errors) = headerschema.validate(&instance) {
+            let error_messages: Vec<String> = errors.into_iter().map(|e| e.to_string()).collect();
+            let error_message = error_messages.first().cloned().unwrap_or_else(|| {
+                "Unexpected error during validation: no error messages found".to_string()
+            });
+            error!("{}", error_message);
+            return Err(Box::new(ValidationError(error_message)));
+        }
+        Ok(instance)
+    }
+
+    /// create without validating against the header schema. only for producers whose
+    /// output shape is already guaranteed, a malformed document would be signed as is
+    pub fn create_unchecked(
+        &self,
+        json: &str,
+        id_strategy: IdStrategy,
+    ) -> Result<Value, Box<dyn std::error::Error + 'static>> {
+        self.new_header(json, id_strategy)
+    }
+
+    /// parses a new document and assigns its id, version and dates
+    fn new_header(
+        &self,
+        json: &str,
+        id_strategy: IdStrategy,
+    ) -> Result<Value, Box<dyn std::error::Error + 'static>> {
         // create json string
         let mut instance: serde_json::Value = match serde_json::from_str(json) {
             Ok(value) => {
@@ -736,6 +1068,12 @@ impl Schema {
             }
         };
 
+        if !instance.is_object() {
+            let error_message = "New JACs documents must be JSON objects";
+            error!("{}", error_message);
+            return Err(error_message.into());
+        }
+
         // make sure there is no id or version field
         if instance.get_str("jacsId").is_some() || instance.get_str("jacsVersion").is_some() {
             let error_message = "New JACs documents should have no id or version";
@@ -743,14 +1081,14 @@ impl Schema {
             return Err(error_message.into());
         }
 
-        // assign id and version
-        let id = Uuid::new_v4().to_string();
-        let version = Uuid::new_v4().to_string();
+        // assign id and version. a content hash version covers the id, so it differs from it
+        let id = id_strategy.new_id(&instance);
+        instance["jacsId"] = json!(format!("{}", id));
+        let version = id_strategy.new_id(&instance);
         let original_version = version.clone();
         // let now: DateTime<Utc> = Utc::now();
         let versioncreated = Utc::now().to_rfc3339();
 
-        instance["jacsId"] = json!(format!("{}", id));
         instance["jacsVersion"] = json!(format!("{}", version));
         instance["jacsVersionDate"] = json!(format!("{}", versioncreated));
         instance["jacsOriginalVersion"] = json!(format!("{}", original_version));
@@ -761,23 +1099,7 @@ impl Schema {
             instance["$schema"] = json!(format!("{}", self.get_header_schema_url()));
         }
 
-        let validation_result = self.headerschema.validate(&instance);
-
-        match validation_result {
-            Ok(instance) => instance,
-            Err(errors) => {
-                let error_messages: Vec<String> =
-                    errors.into_iter().map(|e| e.to_string()).collect();
-                let error_message = error_messages.first().cloned().unwrap_or_else(|| {
-                    "Unexpected error during validation: no error messages found".to_string()
-                });
-                error!("{}", error_message);
-                return Err(Box::new(ValidationError(error_message))
-                    as Box<dyn std::error::Error + 'static>);
-            }
-        };
-
-        Ok(instance.clone())
+        Ok(instance)
     }
 
     // pub fn create_document(&self, json: &str) -> Result<Value, String> {
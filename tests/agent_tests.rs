@@ -1,7 +1,9 @@
+use jacs::agent::agents_equivalent;
 use jacs::agent::boilerplate::BoilerPlate;
+use jacs::agent::document::Document;
 
 mod utils;
-use utils::load_local_document;
+use utils::{load_local_document, load_test_agent_one};
 
 #[test]
 fn test_update_agent_and_verify_versions() {
@@ -64,3 +66,310 @@ fn test_validate_agent_json_raw() {
         result.unwrap_err()
     );
 }
+
+#[test]
+fn test_import_encrypted_agent() {
+    // cargo test   --test agent_tests test_import_encrypted_agent -- --nocapture
+    let agent_string = load_local_document(
+        &"examples/agent/48d074ec-84e2-4d26-adc5-0b2253f1e8ff:12ccba24-8997-47b1-9e6f-d699d7ab0e41.json"
+            .to_string(),
+    )
+    .unwrap();
+    let encrypted_private_key = std::fs::read("examples/keys/agent-one.private.pem.enc").unwrap();
+    let public_key = std::fs::read("examples/keys/agent-one.public.pem").unwrap();
+    let key_algorithm = "RSA-PSS".to_string();
+
+    let mut agent =
+        jacs::agent::Agent::new(&"v1".to_string(), &"v1".to_string(), &"v1".to_string())
+            .expect("Agent schema should have instantiated");
+    let result = agent.import_encrypted_agent(
+        &agent_string,
+        &encrypted_private_key,
+        public_key.clone(),
+        &key_algorithm,
+        "not the password",
+    );
+    assert!(result.is_err(), "wrong password should fail");
+    assert!(agent.get_id().is_err());
+
+    let other_public_key = std::fs::read("examples/keys/agent-two.public.pem").unwrap();
+    let result = agent.import_encrypted_agent(
+        &agent_string,
+        &encrypted_private_key,
+        other_public_key,
+        &key_algorithm,
+        "secretpassord",
+    );
+    assert!(result.is_err(), "mismatched public key should fail");
+    assert!(agent.get_id().is_err());
+    assert!(agent.get_private_key().is_err());
+    assert!(agent.get_public_key().is_err());
+
+    agent
+        .import_encrypted_agent(
+            &agent_string,
+            &encrypted_private_key,
+            public_key,
+            &key_algorithm,
+            "secretpassord",
+        )
+        .expect("import_encrypted_agent");
+    assert_eq!(
+        agent.get_id().unwrap(),
+        "48d074ec-84e2-4d26-adc5-0b2253f1e8ff"
+    );
+}
+
+#[test]
+fn test_create_agent_in_memory() {
+    // cargo test   --test agent_tests test_create_agent_in_memory -- --nocapture
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    let mut agent =
+        jacs::agent::Agent::new(&"v1".to_string(), &"v1".to_string(), &"v1".to_string())
+            .expect("Agent schema should have instantiated");
+    let key_directory = std::env::var("JACS_KEY_DIRECTORY").unwrap();
+    let key_files_before = std::fs::read_dir(&key_directory).unwrap().count();
+
+    let agent_json = load_local_document(&"examples/raw/myagent.new.json".to_string()).unwrap();
+    let created: serde_json::Value = serde_json::from_str(
+        &agent
+            .create_agent_in_memory(&agent_json, "in-memory-password", "RSA-PSS")
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        std::fs::read_dir(&key_directory).unwrap().count(),
+        key_files_before
+    );
+    let config: serde_json::Value =
+        serde_json::from_str(created["config_json"].as_str().unwrap()).unwrap();
+    assert_eq!(
+        config["jacs_agent_id_and_version"],
+        agent.get_lookup_id().unwrap()
+    );
+
+    let mut imported =
+        jacs::agent::Agent::new(&"v1".to_string(), &"v1".to_string(), &"v1".to_string())
+            .expect("Agent schema should have instantiated");
+    imported
+        .import_encrypted_agent(
+            &created["agent_json"].as_str().unwrap().to_string(),
+            &STANDARD
+                .decode(created["encrypted_private_key"].as_str().unwrap())
+                .unwrap(),
+            STANDARD
+                .decode(created["public_key"].as_str().unwrap())
+                .unwrap(),
+            &config["jacs_agent_key_algorithm"]
+                .as_str()
+                .unwrap()
+                .to_string(),
+            "in-memory-password",
+        )
+        .expect("import the in-memory agent");
+    assert_eq!(imported.get_id().unwrap(), agent.get_id().unwrap());
+}
+
+#[test]
+fn test_load_with_min_strength() {
+    // cargo test   --test agent_tests test_load_with_min_strength -- --nocapture
+    use jacs::crypt::{algorithm_strength, AlgorithmStrength};
+    assert_eq!(
+        algorithm_strength("pq-dilithium").unwrap(),
+        AlgorithmStrength::PostQuantum
+    );
+    assert_eq!(
+        algorithm_strength("ring-Ed25519").unwrap(),
+        AlgorithmStrength::Classical
+    );
+
+    let agent_string = load_local_document(
+        &"examples/agent/48d074ec-84e2-4d26-adc5-0b2253f1e8ff:12ccba24-8997-47b1-9e6f-d699d7ab0e41.json"
+            .to_string(),
+    )
+    .unwrap();
+    let mut agent =
+        jacs::agent::Agent::new(&"v1".to_string(), &"v1".to_string(), &"v1".to_string())
+            .expect("Agent schema should have instantiated");
+    // agent one signs with RSA-PSS
+    assert!(agent
+        .load_with_min_strength(&agent_string, "post-quantum")
+        .is_err());
+    assert!(agent.get_id().is_err());
+    assert!(agent
+        .load_with_min_strength(&agent_string, "strong")
+        .is_err());
+
+    agent
+        .load_with_min_strength(&agent_string, "classical")
+        .expect("load_with_min_strength");
+    assert_eq!(
+        agent.get_id().unwrap(),
+        "48d074ec-84e2-4d26-adc5-0b2253f1e8ff"
+    );
+}
+
+#[test]
+fn test_validate_config_json() {
+    // cargo test   --test agent_tests test_validate_config_json -- --nocapture
+    use jacs::config::validate_config_json;
+    let config = serde_json::json!({
+        "$schema": "https://hai.ai/schemas/jacs.config.schema.json",
+        "jacs_data_directory": "./examples",
+        "jacs_key_directory": "./examples/keys",
+        "jacs_agent_private_key_filename": "agent-one.private.pem.enc",
+        "jacs_agent_public_key_filename": "agent-one.public.pem",
+        "jacs_agent_key_algorithm": "RSA-PSS",
+        "jacs_agent_schema_version": "v1",
+        "jacs_header_schema_version": "v1",
+        "jacs_signature_schema_version": "v1"
+    });
+    validate_config_json(&config.to_string()).expect("valid config");
+
+    let mut broken = config.clone();
+    broken.as_object_mut().unwrap().remove("jacs_key_directory");
+    broken["jacs_document_hash_algorithm"] = serde_json::json!("md5");
+    let error = validate_config_json(&broken.to_string())
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("jacs_key_directory"));
+    assert!(error.contains("/jacs_document_hash_algorithm"));
+
+    assert!(validate_config_json("{not json").is_err());
+}
+
+#[test]
+fn test_agent_schema_compatibility() {
+    // cargo test   --test agent_tests test_agent_schema_compatibility -- --nocapture
+    use jacs::schema::agent_schema_compatibility;
+    let agent_string = load_local_document(
+        &"examples/agent/48d074ec-84e2-4d26-adc5-0b2253f1e8ff:12ccba24-8997-47b1-9e6f-d699d7ab0e41.json"
+            .to_string(),
+    )
+    .unwrap();
+    let report: serde_json::Value =
+        serde_json::from_str(&agent_schema_compatibility(&agent_string).unwrap()).unwrap();
+    assert_eq!(report["agent_schema_version"], "v1");
+    assert_eq!(
+        report["library_supported_versions"],
+        serde_json::json!(["v1"])
+    );
+    assert_eq!(report["compatible"], true);
+
+    let mut future_agent: serde_json::Value = serde_json::from_str(&agent_string).unwrap();
+    future_agent["$schema"] =
+        serde_json::json!("https://hai.ai/schemas/agent/v9/agent.schema.json");
+    let report: serde_json::Value =
+        serde_json::from_str(&agent_schema_compatibility(&future_agent.to_string()).unwrap())
+            .unwrap();
+    assert_eq!(report["agent_schema_version"], "v9");
+    assert_eq!(report["compatible"], false);
+    assert!(report["error"].is_string());
+}
+
+#[test]
+fn test_agent_self_test() {
+    // cargo test   --test agent_tests test_agent_self_test -- --nocapture
+    use jacs::crypt::generate_key_pair;
+    let mut agent = load_test_agent_one();
+    let report: serde_json::Value = serde_json::from_str(&agent.self_test().unwrap()).unwrap();
+    println!("{}", report);
+    assert_eq!(report["sign_ok"], true);
+    assert_eq!(report["verify_ok"], true);
+    assert_eq!(report["algorithm"], "RSA-PSS");
+    assert!(report["duration_ms"].is_u64());
+
+    // a private key that does not match the public key is caught
+    let (private_key, _) = generate_key_pair("RSA-PSS").unwrap();
+    let (_, public_key) = generate_key_pair("RSA-PSS").unwrap();
+    agent
+        .set_keys(private_key, public_key, &"RSA-PSS".to_string())
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_str(&agent.self_test().unwrap()).unwrap();
+    assert_eq!(report["sign_ok"], true);
+    assert_eq!(report["verify_ok"], false);
+    assert!(report["error"].is_string());
+}
+
+#[test]
+fn test_agents_equivalent() {
+    // cargo test   --test agent_tests test_agents_equivalent -- --nocapture
+    let agent_one = load_local_document(
+        &"examples/agent/48d074ec-84e2-4d26-adc5-0b2253f1e8ff:12ccba24-8997-47b1-9e6f-d699d7ab0e41.json"
+            .to_string(),
+    )
+    .unwrap();
+    let agent_two = load_local_document(
+        &"examples/agent/9f62bc98-b871-4c26-a5c9-29457e291448:15da36cb-a131-41cc-b1df-3afeec6acc74.json"
+            .to_string(),
+    )
+    .unwrap();
+    let compare = |a: &str, b: &str| -> serde_json::Value {
+        serde_json::from_str(&agents_equivalent(a, b).unwrap()).unwrap()
+    };
+
+    let same = compare(&agent_one, &agent_one);
+    assert_eq!(same["equivalent"], true);
+    assert_eq!(same["same_version"], true);
+
+    // a later version of the same agent, still signed with the same key
+    let mut updated: serde_json::Value = serde_json::from_str(&agent_one).unwrap();
+    updated["jacsVersion"] = serde_json::json!("00000000-0000-4000-8000-000000000000");
+    updated["name"] = serde_json::json!("renamed");
+    let updated = compare(&agent_one, &updated.to_string());
+    assert_eq!(updated["equivalent"], true);
+    assert_eq!(updated["same_version"], false);
+
+    // a different agent reusing the first one's id
+    let mut impostor: serde_json::Value = serde_json::from_str(&agent_two).unwrap();
+    impostor["jacsId"] =
+        serde_json::from_str::<serde_json::Value>(&agent_one).unwrap()["jacsId"].clone();
+    let impostor = compare(&agent_one, &impostor.to_string());
+    assert_eq!(impostor["same_id"], true);
+    assert_eq!(impostor["same_key"], false);
+    assert_eq!(impostor["equivalent"], false);
+
+    assert_eq!(compare(&agent_one, &agent_two)["equivalent"], false);
+}
+
+#[test]
+fn test_load_verifier_only() {
+    // cargo test   --test agent_tests test_load_verifier_only -- --nocapture
+    let mut signer = load_test_agent_one();
+    let document = signer
+        .create_document_and_load(&r#"{"memo": "verify me"}"#.to_string(), None, None)
+        .unwrap();
+    let document_string = document.getvalue().to_string();
+
+    let mut verifier =
+        jacs::agent::Agent::new(&"v1".to_string(), &"v1".to_string(), &"v1".to_string())
+            .expect("Agent schema should have instantiated");
+    let data_directory = std::env::var("JACS_DATA_DIRECTORY").unwrap();
+    let key_directory = std::env::var("JACS_KEY_DIRECTORY").unwrap();
+    assert!(verifier
+        .load_verifier_only(&data_directory, "no/such/key/directory")
+        .is_err());
+    verifier
+        .load_verifier_only(&data_directory, &key_directory)
+        .unwrap();
+    assert!(verifier.is_verifier_only());
+
+    let loaded = verifier.load_document(&document_string).unwrap();
+    verifier
+        .verify_document_signature(&loaded.getkey(), None, None, None, None)
+        .unwrap();
+
+    let mut tampered = document.getvalue().clone();
+    tampered["memo"] = serde_json::json!("verify something else");
+    tampered["jacsSha256"] = serde_json::json!(verifier.hash_doc(&tampered).unwrap());
+    let tampered = verifier.load_document(&tampered.to_string()).unwrap();
+    assert!(verifier
+        .verify_document_signature(&tampered.getkey(), None, None, None, None)
+        .is_err());
+
+    let error = verifier
+        .create_document_and_load(&r#"{"memo": "sign me"}"#.to_string(), None, None)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("verifier-only mode"), "{}", error);
+}
@@ -250,3 +250,568 @@ fn test_sign_agreement() {
         .unwrap();
     println!(" question {}, context {}", question, context);
 }
+
+#[test]
+fn test_reserved_agreement_fieldname_rejected() {
+    let DOCUMENT_PATH = format!("examples/documents/{}.json", DOCID);
+    // cargo test   --test agreement_test -- --nocapture test_reserved_agreement_fieldname_rejected
+    let mut agent = load_test_agent_one();
+    let agentids: Vec<String> = vec![agent.get_id().expect("REASON")];
+
+    let document_string = load_local_document(&DOCUMENT_PATH).unwrap();
+    let document = agent.load_document(&document_string).unwrap();
+    let document_key = document.getkey();
+
+    for fieldname in [
+        DOCUMENT_AGENT_SIGNATURE_FIELDNAME,
+        "jacsSha256",
+        "jacsHashAlgorithm",
+        "bad.field",
+        "",
+    ] {
+        let result = agent.create_agreement(
+            &document_key,
+            &agentids,
+            None,
+            None,
+            Some(fieldname.to_string()),
+        );
+        assert!(result.is_err(), "{:?} should be rejected", fieldname);
+    }
+    assert!(agent
+        .sign_agreement(
+            &document_key,
+            Some(DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string())
+        )
+        .is_err());
+    assert!(agent
+        .check_agreement(
+            &document_key,
+            Some(DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string())
+        )
+        .is_err());
+
+    agent
+        .create_agreement(
+            &document_key,
+            &agentids,
+            None,
+            None,
+            Some("customAgreement".to_string()),
+        )
+        .expect("custom agreement fieldname");
+}
+
+#[test]
+fn test_save_and_resume_agreement_progress() {
+    let DOCUMENT_PATH = format!("examples/documents/{}.json", DOCID);
+    // cargo test   --test agreement_test -- --nocapture test_save_and_resume_agreement_progress
+    let mut agent = load_test_agent_one();
+    let mut agent_two = load_test_agent_two();
+    let agentids: Vec<String> = vec![
+        agent.get_id().expect("REASON"),
+        agent_two.get_id().expect("REASON"),
+    ];
+
+    let document_string = load_local_document(&DOCUMENT_PATH).unwrap();
+    let document = agent.load_document(&document_string).unwrap();
+    let unsigned_doc = agent
+        .create_agreement(&document.getkey(), &agentids, None, None, None)
+        .expect("create_agreement");
+    let (agreement_id, waiting_on) = agent
+        .save_agreement_progress(&unsigned_doc.getkey(), None)
+        .expect("save_agreement_progress");
+    assert_eq!(agreement_id, unsigned_doc.id);
+    assert_eq!(waiting_on.len(), 2);
+    assert!(agentids.iter().all(|id| waiting_on.contains(id)));
+
+    // a separate process picks the agreement back up
+    let resumed = agent_two
+        .load_agreement_progress(&agreement_id)
+        .expect("load_agreement_progress");
+    assert_eq!(resumed.getkey(), unsigned_doc.getkey());
+    assert_eq!(resumed.agreement_unsigned_agents(None).unwrap().len(), 2);
+    let signed = agent_two
+        .sign_agreement(&resumed.getkey(), None)
+        .expect("sign_agreement");
+    assert_eq!(signed.agreement_unsigned_agents(None).unwrap().len(), 1);
+
+    assert!(agent_two
+        .load_agreement_progress(&"../keys/agent-one".to_string())
+        .is_err());
+}
+
+#[test]
+fn test_default_agreement_fieldname() {
+    let DOCUMENT_PATH = format!("examples/documents/{}.json", DOCID);
+    // cargo test   --test agreement_test -- --nocapture test_default_agreement_fieldname
+    let mut agent = load_test_agent_one();
+    let agentids: Vec<String> = vec![agent.get_id().expect("REASON")];
+    assert!(agent
+        .set_default_agreement_fieldname(DOCUMENT_AGENT_SIGNATURE_FIELDNAME)
+        .is_err());
+    agent
+        .set_default_agreement_fieldname("jacsInvoiceAgreement")
+        .expect("set_default_agreement_fieldname");
+
+    let document_string = load_local_document(&DOCUMENT_PATH).unwrap();
+    let document = agent.load_document(&document_string).unwrap();
+    let unsigned_doc = agent
+        .create_agreement(&document.getkey(), &agentids, None, None, None)
+        .expect("create_agreement");
+    assert!(unsigned_doc.value.get("jacsInvoiceAgreement").is_some());
+    assert!(unsigned_doc.value.get(AGENT_AGREEMENT_FIELDNAME).is_none());
+
+    let signed = agent
+        .sign_agreement(&unsigned_doc.getkey(), None)
+        .expect("sign_agreement");
+    agent
+        .check_agreement(&signed.getkey(), None)
+        .expect("check_agreement");
+    // an explicit fieldname still wins over the default
+    assert!(agent
+        .check_agreement(
+            &signed.getkey(),
+            Some(AGENT_AGREEMENT_FIELDNAME.to_string())
+        )
+        .is_err());
+}
+
+#[test]
+fn test_verify_agreement_reports_each_signer() {
+    let DOCUMENT_PATH = format!("examples/documents/{}.json", DOCID);
+    // cargo test   --test agreement_test -- --nocapture test_verify_agreement_reports_each_signer
+    let mut agent = load_test_agent_one();
+    let agent_two = load_test_agent_two();
+    let agentids: Vec<String> = vec![
+        agent.get_id().expect("REASON"),
+        agent_two.get_id().expect("REASON"),
+    ];
+
+    let document_string = load_local_document(&DOCUMENT_PATH).unwrap();
+    let document = agent.load_document(&document_string).unwrap();
+    let unsigned_doc = agent
+        .create_agreement(&document.getkey(), &agentids, None, None, None)
+        .expect("create_agreement");
+    let signed = agent
+        .sign_agreement(&unsigned_doc.getkey(), None)
+        .expect("sign_agreement");
+
+    // agent two has not signed yet, which check_agreement refuses outright
+    let report: serde_json::Value = serde_json::from_str(
+        &agent_two
+            .verify_agreement(&signed.to_string(), None)
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(report.as_array().unwrap().len(), 1);
+    assert_eq!(report[0]["agent_id"], agent.get_id().unwrap());
+    assert_eq!(report[0]["signature_valid"], true);
+
+    // a forged signature is reported rather than aborting the check
+    let mut forged = signed.value.clone();
+    forged[AGENT_AGREEMENT_FIELDNAME]["signatures"][0]["signature"] =
+        forged[DOCUMENT_AGENT_SIGNATURE_FIELDNAME]["signature"].clone();
+    forged["jacsSha256"] = serde_json::json!(agent.hash_doc(&forged).unwrap());
+    let report: serde_json::Value = serde_json::from_str(
+        &agent_two
+            .verify_agreement(&forged.to_string(), None)
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(report[0]["signature_valid"], false);
+}
+
+#[test]
+fn test_ordered_agreement_enforces_signing_order() {
+    let DOCUMENT_PATH = format!("examples/documents/{}.json", DOCID);
+    // cargo test   --test agreement_test -- --nocapture test_ordered_agreement_enforces_signing_order
+    let mut agent = load_test_agent_one();
+    let mut agent_two = load_test_agent_two();
+    let agentids: Vec<String> = vec![
+        agent_two.get_id().expect("REASON"),
+        agent.get_id().expect("REASON"),
+    ];
+
+    let document_string = load_local_document(&DOCUMENT_PATH).unwrap();
+    let document = agent.load_document(&document_string).unwrap();
+    let unsigned_doc = agent
+        .create_ordered_agreement(&document.getkey(), &agentids, None, None, None)
+        .expect("create_ordered_agreement");
+    assert_eq!(
+        unsigned_doc.agreement_next_expected_signer(None).unwrap(),
+        Some(agentids[0].clone())
+    );
+
+    // agent one is second in line
+    let refused = agent.sign_agreement(&unsigned_doc.getkey(), None);
+    assert!(refused.unwrap_err().to_string().contains(&agentids[0]));
+    let pending = agent.check_agreement(&unsigned_doc.getkey(), None);
+    assert!(pending
+        .unwrap_err()
+        .to_string()
+        .contains("next_expected_signer"));
+
+    let _ = agent_two.load_document(&unsigned_doc.to_string()).unwrap();
+    let first_signed = agent_two
+        .sign_agreement(&unsigned_doc.getkey(), None)
+        .expect("agent two signs first");
+    assert_eq!(
+        first_signed.agreement_next_expected_signer(None).unwrap(),
+        Some(agentids[1].clone())
+    );
+
+    let _ = agent.load_document(&first_signed.to_string()).unwrap();
+    let both_signed = agent
+        .sign_agreement(&first_signed.getkey(), None)
+        .expect("agent one signs second");
+    assert_eq!(
+        both_signed.agreement_next_expected_signer(None).unwrap(),
+        None
+    );
+    agent
+        .check_agreement(&both_signed.getkey(), None)
+        .expect("check_agreement");
+}
+
+#[test]
+fn test_list_agreements_on_one_document() {
+    let DOCUMENT_PATH = format!("examples/documents/{}.json", DOCID);
+    // cargo test   --test agreement_test -- --nocapture test_list_agreements_on_one_document
+    let mut agent = load_test_agent_one();
+    let agent_two = load_test_agent_two();
+    let agent_one_id = agent.get_id().expect("REASON");
+    let agent_two_id = agent_two.get_id().expect("REASON");
+
+    let document_string = load_local_document(&DOCUMENT_PATH).unwrap();
+    let document = agent.load_document(&document_string).unwrap();
+    let legal = agent
+        .create_agreement(
+            &document.getkey(),
+            &vec![agent_one_id.clone()],
+            Some(&"legal approval".to_string()),
+            None,
+            None,
+        )
+        .expect("create legal agreement");
+    let technical = agent
+        .create_agreement(
+            &legal.getkey(),
+            &vec![agent_one_id.clone(), agent_two_id.clone()],
+            Some(&"technical approval".to_string()),
+            None,
+            Some("technicalApproval".to_string()),
+        )
+        .expect("create technical agreement");
+    let signed = agent
+        .sign_agreement(&technical.getkey(), None)
+        .expect("sign legal agreement");
+
+    let listed: serde_json::Value =
+        serde_json::from_str(&agent.list_agreements(&signed.to_string()).unwrap()).unwrap();
+    let listed = listed.as_array().unwrap();
+    assert_eq!(listed.len(), 2);
+    let find = |fieldname: &str| {
+        listed
+            .iter()
+            .find(|agreement| agreement["agreement_fieldname"] == fieldname)
+            .unwrap()
+            .clone()
+    };
+    let legal_status = find(AGENT_AGREEMENT_FIELDNAME);
+    assert_eq!(legal_status["question"], "legal approval");
+    assert_eq!(legal_status["complete"], true);
+    let technical_status = find("technicalApproval");
+    assert_eq!(technical_status["complete"], false);
+    assert_eq!(
+        technical_status["unsigned_agents"],
+        serde_json::json!([agent_one_id, agent_two_id])
+    );
+}
+
+#[test]
+fn test_offline_agreement_signing_request() {
+    let DOCUMENT_PATH = format!("examples/documents/{}.json", DOCID);
+    // cargo test   --test agreement_test -- --nocapture test_offline_agreement_signing_request
+    let mut agent = load_test_agent_one();
+    let mut offline_agent = load_test_agent_two();
+    let agentids: Vec<String> = vec![
+        agent.get_id().expect("REASON"),
+        offline_agent.get_id().expect("REASON"),
+    ];
+
+    let document_string = load_local_document(&DOCUMENT_PATH).unwrap();
+    let document = agent.load_document(&document_string).unwrap();
+    let unsigned_doc = agent
+        .create_agreement(
+            &document.getkey(),
+            &agentids,
+            Some(&"ship it?".to_string()),
+            None,
+            None,
+        )
+        .expect("create_agreement");
+
+    let request = agent
+        .export_agreement_signing_request(&unsigned_doc.to_string(), None)
+        .unwrap();
+    let request_value: serde_json::Value = serde_json::from_str(&request).unwrap();
+    let terms: serde_json::Value = serde_json::from_str(
+        &offline_agent
+            .read_agreement_signing_request(&request)
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(terms["question"], "ship it?");
+
+    // only what the carried document produces gets signed
+    let mut tampered_payload = request_value.clone();
+    tampered_payload["signing_payload"] = serde_json::json!("pay mallory 1000");
+    let refused = offline_agent.sign_agreement_signing_request(&tampered_payload.to_string());
+    assert!(refused.unwrap_err().to_string().contains("does not match"));
+    let mut tampered_question = request_value.clone();
+    tampered_question["question"] = serde_json::json!("lunch?");
+    assert!(offline_agent
+        .read_agreement_signing_request(&tampered_question.to_string())
+        .is_err());
+    let mut tampered_document = request_value.clone();
+    tampered_document["document"][AGENT_AGREEMENT_FIELDNAME]["question"] =
+        serde_json::json!("lunch?");
+    assert!(offline_agent
+        .sign_agreement_signing_request(&tampered_document.to_string())
+        .is_err());
+
+    // the offline agent only ever sees the request
+    let response = offline_agent
+        .sign_agreement_signing_request(&request)
+        .unwrap();
+
+    let mut forged: serde_json::Value = serde_json::from_str(&response).unwrap();
+    forged["signature"] =
+        unsigned_doc.value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME]["signature"].clone();
+    assert!(agent
+        .import_agreement_signature(&unsigned_doc.to_string(), &forged.to_string(), None)
+        .is_err());
+
+    let imported = agent
+        .import_agreement_signature(&unsigned_doc.to_string(), &response, None)
+        .expect("import_agreement_signature");
+    assert!(agent
+        .import_agreement_signature(&imported.to_string(), &response, None)
+        .is_err());
+
+    let both_signed = agent
+        .sign_agreement(&imported.getkey(), None)
+        .expect("sign_agreement");
+    agent
+        .check_agreement(&both_signed.getkey(), None)
+        .expect("check_agreement");
+}
+
+#[test]
+fn test_merge_parallel_agreement_signatures() {
+    let DOCUMENT_PATH = format!("examples/documents/{}.json", DOCID);
+    // cargo test   --test agreement_test -- --nocapture test_merge_parallel_agreement_signatures
+    let mut agent = load_test_agent_one();
+    let mut agent_two = load_test_agent_two();
+    let agentids: Vec<String> = vec![
+        agent.get_id().expect("REASON"),
+        agent_two.get_id().expect("REASON"),
+    ];
+
+    let document_string = load_local_document(&DOCUMENT_PATH).unwrap();
+    let document = agent.load_document(&document_string).unwrap();
+    let unsigned_doc = agent
+        .create_agreement(
+            &document.getkey(),
+            &agentids,
+            Some(&"ship it?".to_string()),
+            None,
+            None,
+        )
+        .expect("create_agreement");
+
+    // the same copy goes out to both agents
+    let _ = agent_two.load_document(&unsigned_doc.to_string()).unwrap();
+    let signed_by_two = agent_two
+        .sign_agreement(&unsigned_doc.getkey(), None)
+        .expect("agent two signs");
+    let signed_by_one = agent
+        .sign_agreement(&unsigned_doc.getkey(), None)
+        .expect("agent one signs");
+
+    let merged = agent
+        .merge_agreement_signatures(&signed_by_one.to_string(), &signed_by_two.to_string(), None)
+        .expect("merge_agreement_signatures");
+    assert!(merged.agreement_unsigned_agents(None).unwrap().is_empty());
+    agent
+        .check_agreement(&merged.getkey(), None)
+        .expect("check_agreement");
+
+    // numbers are outside the agreement hash, but a copy that changes one still differs
+    let mut altered = signed_by_two.getvalue().clone();
+    altered["amount"] = serde_json::json!(11);
+    altered["jacsSha256"] = serde_json::json!(agent_two.hash_doc(&altered).unwrap());
+    assert!(agent
+        .merge_agreement_signatures(&signed_by_one.to_string(), &altered.to_string(), None)
+        .is_err());
+
+    // a copy asking a different question does not merge
+    let _ = agent_two.load_document(&document_string).unwrap();
+    let other_question = agent_two
+        .create_agreement(
+            &document.getkey(),
+            &agentids,
+            Some(&"ship it later?".to_string()),
+            None,
+            None,
+        )
+        .unwrap();
+    let other_signed = agent_two
+        .sign_agreement(&other_question.getkey(), None)
+        .unwrap();
+    assert!(agent
+        .merge_agreement_signatures(&signed_by_one.to_string(), &other_signed.to_string(), None)
+        .is_err());
+}
+
+#[test]
+fn test_pinned_agreement_rejects_other_keys() {
+    let DOCUMENT_PATH = format!("examples/documents/{}.json", DOCID);
+    // cargo test   --test agreement_test -- --nocapture test_pinned_agreement_rejects_other_keys
+    use jacs::crypt::hash::hash_public_key;
+    let mut agent = load_test_agent_one();
+    let mut agent_two = load_test_agent_two();
+    let agent_one_key_hash = hash_public_key(agent.get_public_key().unwrap());
+    // agent two is pinned to a key it does not hold
+    let signers: Vec<(String, String)> = vec![
+        (agent.get_id().expect("REASON"), agent_one_key_hash.clone()),
+        (agent_two.get_id().expect("REASON"), agent_one_key_hash),
+    ];
+
+    let document_string = load_local_document(&DOCUMENT_PATH).unwrap();
+    let document = agent.load_document(&document_string).unwrap();
+    let unsigned_doc = agent
+        .create_pinned_agreement(&document.getkey(), &signers, None, None, None)
+        .expect("create_pinned_agreement");
+    let signed_by_one = agent
+        .sign_agreement(&unsigned_doc.getkey(), None)
+        .expect("agent one holds its pinned key");
+
+    let _ = agent_two.load_document(&signed_by_one.to_string()).unwrap();
+    let refused = agent_two.sign_agreement(&signed_by_one.getkey(), None);
+    assert!(refused.unwrap_err().to_string().contains("key_mismatch"));
+
+    let request = agent
+        .export_agreement_signing_request(&signed_by_one.to_string(), None)
+        .unwrap();
+    let response = agent_two.sign_agreement_signing_request(&request).unwrap();
+    let imported = agent.import_agreement_signature(&signed_by_one.to_string(), &response, None);
+    assert!(imported.unwrap_err().to_string().contains("key_mismatch"));
+
+    // slip the signature in anyway
+    let mut value = signed_by_one.getvalue().clone();
+    value[AGENT_AGREEMENT_FIELDNAME]["signatures"]
+        .as_array_mut()
+        .unwrap()
+        .push(serde_json::from_str(&response).unwrap());
+    value["jacsSha256"] = serde_json::json!(agent.hash_doc(&value).unwrap());
+    let results: serde_json::Value =
+        serde_json::from_str(&agent.verify_agreement(&value.to_string(), None).unwrap()).unwrap();
+    assert_eq!(results[0]["key_mismatch"], false);
+    assert_eq!(results[1]["key_mismatch"], true);
+    assert_eq!(results[1]["signature_valid"], false);
+
+    let tampered = agent.load_document(&value.to_string()).unwrap();
+    let checked = agent.check_agreement(&tampered.getkey(), None);
+    assert!(checked.unwrap_err().to_string().contains("key_mismatch"));
+
+    // stripping the pins breaks the agreement hash, and the signatures cover that hash
+    value[AGENT_AGREEMENT_FIELDNAME]
+        .as_object_mut()
+        .unwrap()
+        .remove("agentPublicKeyHashes");
+    value["jacsSha256"] = serde_json::json!(agent.hash_doc(&value).unwrap());
+    assert!(agent.verify_agreement(&value.to_string(), None).is_err());
+    value["jacsAgreementHash"] = serde_json::json!(agent
+        .agreement_hash(value.clone(), &AGENT_AGREEMENT_FIELDNAME.to_string())
+        .unwrap());
+    value["jacsSha256"] = serde_json::json!(agent.hash_doc(&value).unwrap());
+    let results: serde_json::Value =
+        serde_json::from_str(&agent.verify_agreement(&value.to_string(), None).unwrap()).unwrap();
+    assert_eq!(results[0]["signature_valid"], false);
+    assert_eq!(results[1]["signature_valid"], false);
+}
+
+#[test]
+fn test_create_agreement_detailed() {
+    let DOCUMENT_PATH = format!("examples/documents/{}.json", DOCID);
+    // cargo test   --test agreement_test -- --nocapture test_create_agreement_detailed
+    let mut agent = load_test_agent_one();
+    let agentids: Vec<String> = vec![agent.get_id().expect("REASON")];
+    let document_string = load_local_document(&DOCUMENT_PATH).unwrap();
+    let document = agent.load_document(&document_string).unwrap();
+    let created: serde_json::Value = serde_json::from_str(
+        &agent
+            .create_agreement_detailed(&document.getkey(), &agentids, None, None, None)
+            .expect("create_agreement_detailed"),
+    )
+    .unwrap();
+    assert_eq!(created["agreement_field"], AGENT_AGREEMENT_FIELDNAME);
+    assert_eq!(created["requested_signers"], serde_json::json!(agentids));
+    assert_eq!(created["document_id"], document.id);
+
+    let document_key = created["document_key"].as_str().unwrap().to_string();
+    let stored = agent.get_document(&document_key).unwrap();
+    assert_eq!(
+        stored.to_string(),
+        created["document_json"].as_str().unwrap()
+    );
+    agent
+        .sign_agreement(&document_key, None)
+        .expect("sign by returned key");
+}
+
+#[test]
+fn test_duplicate_agreement_signature_policies() {
+    let DOCUMENT_PATH = format!("examples/documents/{}.json", DOCID);
+    // cargo test   --test agreement_test -- --nocapture test_duplicate_agreement_signature_policies
+    use jacs::agent::agreement::DuplicateSignaturePolicy;
+    let mut agent = load_test_agent_one();
+    let agentids: Vec<String> = vec![agent.get_id().expect("REASON")];
+    let document_string = load_local_document(&DOCUMENT_PATH).unwrap();
+    let document = agent.load_document(&document_string).unwrap();
+    let unsigned_doc = agent
+        .create_agreement(&document.getkey(), &agentids, None, None, None)
+        .expect("create_agreement");
+    let signed_doc = agent
+        .sign_agreement(&unsigned_doc.getkey(), None)
+        .expect("sign_agreement");
+
+    // a second, bad signature from the same agent appended after the good one
+    let mut value = signed_doc.getvalue().clone();
+    let signatures = value[AGENT_AGREEMENT_FIELDNAME]["signatures"]
+        .as_array_mut()
+        .unwrap();
+    let mut second = signatures[0].clone();
+    second["signature"] = serde_json::json!(agent
+        .sign_string(&"something else".to_string(), None)
+        .unwrap());
+    signatures.push(second);
+    value["jacsSha256"] = serde_json::json!(agent.hash_doc(&value).unwrap());
+    let duplicated = agent.load_document(&value.to_string()).unwrap();
+    let key = duplicated.getkey();
+
+    assert!(agent.check_agreement(&key, None).is_err());
+    agent
+        .check_agreement_with_policy(&key, None, Some(DuplicateSignaturePolicy::FirstWins))
+        .expect("first signature verifies");
+    assert!(agent
+        .check_agreement_with_policy(&key, None, Some(DuplicateSignaturePolicy::LastWins))
+        .is_err());
+    let error = agent
+        .check_agreement_with_policy(&key, None, Some(DuplicateSignaturePolicy::RejectDuplicates))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("duplicate_signatures"));
+}
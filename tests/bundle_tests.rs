@@ -0,0 +1,38 @@
+use jacs::agent::bundle::OfflineBundle;
+use jacs::agent::document::Document;
+mod utils;
+use utils::{load_test_agent_one, load_test_agent_two};
+
+#[test]
+fn test_offline_bundle_round_trip() {
+    // cargo test   --test bundle_tests -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&r#"{"evidence": "air gapped"}"#.to_string(), None, None)
+        .unwrap();
+    let bundle = agent.create_offline_bundle(&document.getkey()).unwrap();
+
+    // any agent instance can verify, the key comes from the bundle
+    let verifier = load_test_agent_two();
+    let verified = verifier.verify_offline_bundle(&bundle).unwrap();
+    assert_eq!(verified.getkey(), document.getkey());
+
+    let mut tampered: serde_json::Value = serde_json::from_str(&bundle).unwrap();
+    tampered["document"]["evidence"] = serde_json::json!("altered");
+    assert!(verifier
+        .verify_offline_bundle(&tampered.to_string())
+        .is_err());
+
+    let mut wrong_key: serde_json::Value = serde_json::from_str(&bundle).unwrap();
+    let other_key = std::fs::read("examples/keys/agent-two.public.pem").unwrap();
+    wrong_key["public_key"] = serde_json::json!(base64::encode(other_key));
+    assert!(verifier
+        .verify_offline_bundle(&wrong_key.to_string())
+        .is_err());
+
+    // bundles are untrusted input, so the document size limit applies
+    std::env::set_var("JACS_MAX_DOCUMENT_BYTES", "100");
+    let oversized = verifier.verify_offline_bundle(&bundle);
+    std::env::remove_var("JACS_MAX_DOCUMENT_BYTES");
+    assert!(oversized.unwrap_err().to_string().contains("byte limit"));
+}
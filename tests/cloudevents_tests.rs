@@ -0,0 +1,60 @@
+use jacs::agent::boilerplate::BoilerPlate;
+use jacs::agent::cloudevents::{CloudEvents, CLOUDEVENT_SIGNATURE_ATTRIBUTE};
+use jacs::crypt::KeyManager;
+mod utils;
+use utils::{load_test_agent_one, load_test_agent_two};
+
+#[test]
+fn test_sign_and_verify_cloudevent() {
+    // cargo test   --test cloudevents_tests -- --nocapture
+    let mut agent = load_test_agent_one();
+    let mut agent_two = load_test_agent_two();
+    let event = serde_json::json!({
+        "specversion": "1.0",
+        "id": "A234-1234-1234",
+        "source": "/orders",
+        "type": "com.example.order.created",
+        "datacontenttype": "application/json",
+        "data": {"order": 42}
+    });
+
+    let signed = agent.sign_cloudevent(&event.to_string()).unwrap();
+    let signer = agent_two.verify_cloudevent(&signed).unwrap();
+    assert_eq!(signer, agent.get_lookup_id().unwrap());
+
+    // data and attributes are both covered
+    let mut tampered: serde_json::Value = serde_json::from_str(&signed).unwrap();
+    tampered["data"]["order"] = serde_json::json!(43);
+    assert!(agent_two.verify_cloudevent(&tampered.to_string()).is_err());
+    let mut retyped: serde_json::Value = serde_json::from_str(&signed).unwrap();
+    retyped["type"] = serde_json::json!("com.example.order.cancelled");
+    assert!(agent_two.verify_cloudevent(&retyped.to_string()).is_err());
+
+    let mut unsigned: serde_json::Value = serde_json::from_str(&signed).unwrap();
+    unsigned
+        .as_object_mut()
+        .unwrap()
+        .remove(CLOUDEVENT_SIGNATURE_ATTRIBUTE);
+    assert!(agent_two.verify_cloudevent(&unsigned.to_string()).is_err());
+
+    // agent two's valid signature on an event claiming to be agent one
+    let mut impersonated: serde_json::Value =
+        serde_json::from_str(&agent_two.sign_cloudevent(&event.to_string()).unwrap()).unwrap();
+    impersonated["jacsagentid"] = serde_json::json!(agent.get_lookup_id().unwrap());
+    impersonated
+        .as_object_mut()
+        .unwrap()
+        .remove(CLOUDEVENT_SIGNATURE_ATTRIBUTE);
+    let forged_signature = agent_two
+        .sign_string(&impersonated.to_string(), None)
+        .unwrap();
+    impersonated[CLOUDEVENT_SIGNATURE_ATTRIBUTE] = serde_json::json!(forged_signature);
+    let error = agent
+        .verify_cloudevent(&impersonated.to_string())
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("registered key"), "{}", error);
+
+    // not a CloudEvent
+    assert!(agent.sign_cloudevent(r#"{"data": 1}"#).is_err());
+}
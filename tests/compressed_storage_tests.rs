@@ -0,0 +1,72 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use jacs::agent::document::Document;
+use jacs::agent::loaders::FileLoader;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+mod utils;
+use utils::load_test_agent_one;
+
+/// the storage settings are process-wide env vars, so tests that change them take turns
+static STORAGE_ENV: Mutex<()> = Mutex::new(());
+
+#[test]
+fn test_compressed_storage_round_trip() {
+    // cargo test   --test compressed_storage_tests test_compressed_storage_round_trip -- --nocapture
+    let _env = STORAGE_ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let mut agent = load_test_agent_one();
+    let payload = "the same sentence over and over. ".repeat(200);
+    let document = agent
+        .create_document_and_load(
+            &serde_json::json!({ "body": payload }).to_string(),
+            None,
+            None,
+        )
+        .unwrap();
+    let document_key = document.getkey();
+
+    // set after loading the agent, which resets env vars from the config
+    env::set_var("JACS_COMPRESS_STORAGE", "true");
+    agent
+        .save_document(&document_key, None, None, None)
+        .unwrap();
+    env::set_var("JACS_COMPRESS_STORAGE", "false");
+
+    let data_dir = PathBuf::from(env::var("JACS_DATA_DIRECTORY").unwrap()).join("documents");
+    let compressed_size = fs::metadata(data_dir.join(format!("{}.json.gz", document_key)))
+        .unwrap()
+        .len();
+    assert!((compressed_size as usize) < document.to_string().len() / 4);
+
+    let document_ids = agent.fs_document_list().unwrap();
+    assert!(document_ids.contains(&document_key));
+
+    let loaded_string = agent.fs_document_load(&document_key).unwrap();
+    let reloaded = agent.load_document(&loaded_string).unwrap();
+    assert_eq!(reloaded.getvalue(), document.getvalue());
+}
+
+#[test]
+fn test_compressed_document_over_limit_rejected() {
+    // cargo test   --test compressed_storage_tests test_compressed_document_over_limit_rejected -- --nocapture
+    let _env = STORAGE_ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let agent = load_test_agent_one();
+    let document_key = format!("{}:{}", uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+    let mut gz_encoder = GzEncoder::new(Vec::new(), Compression::best());
+    gz_encoder.write_all(&vec![b' '; 4 * 1024 * 1024]).unwrap();
+    let compressed = gz_encoder.finish().unwrap();
+    assert!(compressed.len() < 64 * 1024);
+
+    let data_dir = PathBuf::from(env::var("JACS_DATA_DIRECTORY").unwrap()).join("documents");
+    let bomb_path = data_dir.join(format!("{}.json.gz", document_key));
+    fs::write(&bomb_path, compressed).unwrap();
+    env::set_var("JACS_MAX_DOCUMENT_BYTES", (1024 * 1024).to_string());
+    let result = agent.fs_document_load(&document_key);
+    env::remove_var("JACS_MAX_DOCUMENT_BYTES");
+    fs::remove_file(&bomb_path).unwrap();
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("more than 1048576 bytes"), "{}", error);
+}
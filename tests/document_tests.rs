@@ -1,7 +1,15 @@
 use jacs::agent::boilerplate::BoilerPlate;
-use jacs::agent::document::Document;
+use jacs::agent::document::{
+    document_claimed_signer, document_content_hash, document_from_cbor, document_to_cbor,
+    explain_signature, max_document_bytes, signed_bytes_for_field, Document,
+    DEFAULT_MAX_DOCUMENT_BYTES,
+};
 use jacs::agent::loaders::FileLoader;
+use jacs::agent::stream::{StreamVerifier, VerificationResult};
 use jacs::crypt::KeyManager;
+use jacs::crypt::SignatureEncoding;
+use jacs::schema::utils::{parse_untrusted_json, MAX_JSON_DEPTH};
+use jacs::schema::IdStrategy;
 use jsonschema::{CompilationOptions, Draft, JSONSchema};
 mod utils;
 use utils::DOCTESTFILE;
@@ -9,6 +17,10 @@ use utils::DOCTESTFILE;
 use utils::{load_local_document, load_test_agent_one, load_test_agent_two};
 // use color_eyre::eyre::Result;
 use jacs::agent::DOCUMENT_AGENT_SIGNATURE_FIELDNAME;
+use jacs::agent::{
+    CURRENT_SIGNATURE_FORMAT, JACS_HASH_ALGORITHM_FIELDNAME, SIGNATURE_ENCODING_FIELDNAME,
+    SIGNATURE_FORMAT_FIELDNAME,
+};
 extern crate env_logger;
 use log::{error, info};
 
@@ -322,3 +334,998 @@ fn test_load_custom_schema_and_custom_document_and_update_and_verify_signature()
         Err(e) => panic!("Error in test_load_custom_schema_and_custom_document_and_update_and_verify_signature verifying document signature: {}", e),
     };
 }
+
+#[test]
+fn test_verify_hash_only() {
+    // cargo test   --test document_tests test_verify_hash_only -- --nocapture
+    let agent = load_test_agent_two();
+    let document_string = load_local_document(&DOCTESTFILE.to_string()).unwrap();
+    assert!(agent.verify_hash_only(&document_string).unwrap());
+
+    let mut tampered: serde_json::Value = serde_json::from_str(&document_string).unwrap();
+    tampered["jacsVersionDate"] = serde_json::json!("2020-01-01T00:00:00Z");
+    let result = agent.verify_hash_only(&tampered.to_string());
+    assert!(result.is_err(), "tampered document should fail hash check");
+}
+
+#[test]
+fn test_verify_document_multikey() {
+    // cargo test   --test document_tests test_verify_document_multikey -- --nocapture
+    let mut agent = load_test_agent_one();
+    let agent_two = load_test_agent_two();
+    let document = agent
+        .create_document_and_load(&r#"{"note": "rotation"}"#.to_string(), None, None)
+        .unwrap();
+    let document_string = document.to_string();
+
+    let old_key = (agent_two.get_public_key().unwrap(), "RSA-PSS".to_string());
+    let current_key = (agent.get_public_key().unwrap(), "RSA-PSS".to_string());
+    let index = agent
+        .verify_document_multikey(&document_string, vec![old_key.clone(), current_key])
+        .unwrap();
+    assert_eq!(index, 1);
+
+    let result = agent.verify_document_multikey(&document_string, vec![old_key]);
+    assert!(result.is_err(), "no candidate should verify");
+}
+
+#[test]
+fn test_patch_document() {
+    // cargo test   --test document_tests test_patch_document -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&r#"{"title": "draft", "tags": []}"#.to_string(), None, None)
+        .unwrap();
+    let document_key = document.getkey();
+
+    let patched = agent
+        .patch_document(
+            &document_key,
+            r#"[{"op": "replace", "path": "/title", "value": "final"},
+                {"op": "add", "path": "/tags/-", "value": "reviewed"}]"#,
+        )
+        .unwrap();
+    let value = patched.getvalue();
+    assert_eq!(value["title"], "final");
+    assert_eq!(value["tags"][0], "reviewed");
+    assert_eq!(value["jacsLastVersion"], document.getvalue()["jacsVersion"]);
+    agent
+        .verify_document_signature(&patched.getkey(), None, None, None, None)
+        .unwrap();
+
+    for path in ["/jacsId", "/jacsSignature/signature", "/jacsSha256", ""] {
+        let patch = format!(r#"[{{"op": "remove", "path": "{}"}}]"#, path);
+        assert!(
+            agent.patch_document(&patched.getkey(), &patch).is_err(),
+            "patch on {:?} should be rejected",
+            path
+        );
+    }
+}
+
+#[test]
+fn test_verify_document_cached() {
+    // cargo test   --test document_tests test_verify_document_cached -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&r#"{"note": "cache me"}"#.to_string(), None, None)
+        .unwrap();
+    let compact = serde_json::to_string(document.getvalue()).unwrap();
+    let pretty = document.to_string();
+
+    let content_hash = agent
+        .verify_document_and_hash(&compact, None, None)
+        .unwrap();
+    assert!(agent
+        .verify_document_cached(&pretty, &content_hash)
+        .unwrap());
+
+    let mut tampered = document.getvalue().clone();
+    tampered["note"] = serde_json::json!("changed");
+    assert!(!agent
+        .verify_document_cached(&tampered.to_string(), &content_hash)
+        .unwrap());
+}
+
+#[test]
+fn test_verify_document_typed() {
+    // cargo test   --test document_tests test_verify_document_typed -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&r#"{"note": "not a message"}"#.to_string(), None, None)
+        .unwrap();
+    let document_string = document.getvalue().to_string();
+
+    let value = agent
+        .verify_document_typed(
+            &document_string,
+            "schemas/header/v1/header.schema.json",
+            None,
+            None,
+        )
+        .unwrap();
+    assert_eq!(&value, document.getvalue());
+
+    let error = agent
+        .verify_document_typed(
+            &document_string,
+            "schemas/message/v1/message.schema.json",
+            None,
+            None,
+        )
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("taskID"), "{}", error);
+    assert!(agent
+        .verify_document_typed(&document_string, "schemas/nothing.json", None, None)
+        .is_err());
+}
+
+#[test]
+fn test_signature_format_dispatch() {
+    // cargo test   --test document_tests test_signature_format_dispatch -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&r#"{"note": "format"}"#.to_string(), None, None)
+        .unwrap();
+    assert_eq!(
+        document.getvalue()[DOCUMENT_AGENT_SIGNATURE_FIELDNAME][SIGNATURE_FORMAT_FIELDNAME],
+        CURRENT_SIGNATURE_FORMAT
+    );
+
+    // signatures made before the field existed verify as format 1
+    let mut legacy = document.getvalue().clone();
+    legacy[DOCUMENT_AGENT_SIGNATURE_FIELDNAME]
+        .as_object_mut()
+        .unwrap()
+        .remove(SIGNATURE_FORMAT_FIELDNAME);
+    let legacy_doc = agent.store_jacs_document(&legacy).unwrap();
+    agent
+        .verify_document_signature(&legacy_doc.getkey(), None, None, None, None)
+        .unwrap();
+
+    let mut future = document.getvalue().clone();
+    future[DOCUMENT_AGENT_SIGNATURE_FIELDNAME][SIGNATURE_FORMAT_FIELDNAME] = serde_json::json!(2);
+    let future_doc = agent.store_jacs_document(&future).unwrap();
+    let result = agent.verify_document_signature(&future_doc.getkey(), None, None, None, None);
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("unsupported signature format 2"));
+}
+
+#[test]
+fn test_document_content_hash_matches_stored_hash() {
+    // cargo test   --test document_tests test_document_content_hash -- --nocapture
+    let document_string = load_local_document(&DOCTESTFILE.to_string()).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&document_string).unwrap();
+    let content_hash = document_content_hash(&document_string).unwrap();
+    assert_eq!(content_hash, value["jacsSha256"].as_str().unwrap());
+
+    // formatting does not change the hash
+    let compact = serde_json::to_string(&value).unwrap();
+    assert_eq!(document_content_hash(&compact).unwrap(), content_hash);
+}
+
+#[test]
+fn test_max_document_bytes() {
+    // cargo test   --test document_tests test_max_document_bytes -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document_string = load_local_document(&DOCTESTFILE.to_string()).unwrap();
+
+    let result = agent.load_document_with_max_bytes(&document_string, 100);
+    assert!(result.unwrap_err().to_string().contains("byte limit"));
+
+    agent
+        .load_document_with_max_bytes(&document_string, document_string.len())
+        .expect("document at the limit should load");
+    assert_eq!(max_document_bytes(), DEFAULT_MAX_DOCUMENT_BYTES);
+}
+
+#[test]
+fn test_get_document_keys_ordered_by_version_date() {
+    // cargo test   --test document_tests test_get_document_keys_ordered -- --nocapture
+    let mut agent = load_test_agent_one();
+    let mut created = Vec::new();
+    for n in 0..3 {
+        let document = agent
+            .create_document_and_load(&format!(r#"{{"n": {}}}"#, n), None, None)
+            .unwrap();
+        created.push(document.getkey());
+    }
+    let ordered: Vec<String> = agent
+        .get_document_keys()
+        .into_iter()
+        .filter(|key| created.contains(key))
+        .collect();
+    assert_eq!(ordered, created);
+}
+
+#[test]
+fn test_document_hash_algorithm_dispatch() {
+    // cargo test   --test document_tests test_document_hash_algorithm_dispatch -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&r#"{"regulated": true}"#.to_string(), None, None)
+        .unwrap();
+    // the sha256 default is implied, not recorded
+    assert!(document
+        .getvalue()
+        .get(JACS_HASH_ALGORITHM_FIELDNAME)
+        .is_none());
+
+    // a SHA-512 document, as produced with jacs_document_hash_algorithm = sha512
+    let mut value = document.getvalue().clone();
+    value[JACS_HASH_ALGORITHM_FIELDNAME] = serde_json::json!("sha512");
+    value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME] = agent
+        .signing_procedure(
+            &value,
+            None,
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+        )
+        .unwrap();
+    value["jacsSha256"] = serde_json::json!(agent.hash_doc(&value).unwrap());
+    assert_eq!(value["jacsSha256"].as_str().unwrap().len(), 128);
+
+    let value_string = value.to_string();
+    assert!(agent.verify_hash_only(&value_string).unwrap());
+    assert_eq!(
+        document_content_hash(&value_string).unwrap(),
+        value["jacsSha256"].as_str().unwrap()
+    );
+
+    // downgrading the recorded algorithm breaks the hash
+    value[JACS_HASH_ALGORITHM_FIELDNAME] = serde_json::json!("sha256");
+    assert!(agent.verify_hash_only(&value.to_string()).is_err());
+}
+
+#[test]
+fn test_sign_and_verify_detached() {
+    // cargo test   --test document_tests test_sign_and_verify_detached -- --nocapture
+    let mut agent = load_test_agent_one();
+    let agent_two = load_test_agent_two();
+    let document_string = r#"{"invoice": 1042, "amount": "19.99"}"#;
+    let signature_json = agent.sign_document_detached(document_string, None).unwrap();
+    let signature: serde_json::Value = serde_json::from_str(&signature_json).unwrap();
+    assert_eq!(signature["agentID"], agent.get_id().unwrap());
+    assert!(signature.get("invoice").is_none());
+
+    let public_key = agent.get_public_key().unwrap();
+    agent
+        .verify_detached(
+            document_string,
+            &signature_json,
+            public_key.clone(),
+            "RSA-PSS".to_string(),
+        )
+        .unwrap();
+
+    let altered = r#"{"invoice": 1042, "amount": "1999.00"}"#;
+    assert!(agent
+        .verify_detached(altered, &signature_json, public_key, "RSA-PSS".to_string())
+        .is_err());
+    assert!(agent
+        .verify_detached(
+            document_string,
+            &signature_json,
+            agent_two.get_public_key().unwrap(),
+            "RSA-PSS".to_string(),
+        )
+        .is_err());
+}
+
+#[test]
+fn test_detached_signature_encoding_recorded() {
+    // cargo test   --test document_tests test_detached_signature_encoding_recorded -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document_string = r#"{"memo": "settle in base58"}"#;
+    let signature_json = agent
+        .sign_document_detached(document_string, Some(SignatureEncoding::Base58))
+        .unwrap();
+    let signature: serde_json::Value = serde_json::from_str(&signature_json).unwrap();
+    assert_eq!(signature[SIGNATURE_ENCODING_FIELDNAME], "base58");
+    agent
+        .verify_detached(
+            document_string,
+            &signature_json,
+            agent.get_public_key().unwrap(),
+            "RSA-PSS".to_string(),
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_load_document_rejects_duplicate_keys_and_deep_nesting() {
+    // cargo test   --test document_tests test_load_document_rejects_duplicate_keys_and_deep_nesting -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document_string = load_local_document(&DOCTESTFILE.to_string()).unwrap();
+
+    // a second jacsId after the real one would otherwise win silently
+    let duplicated = document_string.replacen(
+        "{",
+        r#"{"jacsId": "00000000-0000-0000-0000-000000000000","#,
+        1,
+    );
+    let error = agent.load_document(&duplicated).unwrap_err();
+    assert!(error.to_string().contains("duplicate key"), "{}", error);
+
+    let nested = format!(
+        r#"{{"deep": {}1{}}}"#,
+        "[".repeat(MAX_JSON_DEPTH + 1),
+        "]".repeat(MAX_JSON_DEPTH + 1)
+    );
+    let error = parse_untrusted_json(&nested).unwrap_err();
+    assert!(error.to_string().contains("nested deeper"), "{}", error);
+    assert!(agent.load_document(&nested).is_err());
+
+    let shallow = format!(r#"{{"deep": {}1{}}}"#, "[".repeat(8), "]".repeat(8));
+    assert!(parse_untrusted_json(&shallow).is_ok());
+    assert!(agent.load_document(&document_string).is_ok());
+}
+
+#[test]
+fn test_verify_document_self_contained() {
+    // cargo test   --test document_tests test_verify_document_self_contained -- --nocapture
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    let mut agent = load_test_agent_one();
+    let agent_two = load_test_agent_two();
+    let document = agent
+        .create_document_and_load(&r#"{"portable": "yes"}"#.to_string(), None, None)
+        .unwrap();
+    assert!(agent_two
+        .verify_document_self_contained(&document.to_string())
+        .is_err());
+
+    let embed = |public_key: Vec<u8>| {
+        let mut value = document.getvalue().clone();
+        value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME]["publicKey"] =
+            serde_json::json!(STANDARD.encode(public_key));
+        value["jacsSha256"] = serde_json::json!(agent.hash_doc(&value).unwrap());
+        value.to_string()
+    };
+    assert!(agent_two
+        .verify_document_self_contained(&embed(agent.get_public_key().unwrap()))
+        .unwrap());
+    // a key that does not hash to publicKeyHash is refused
+    assert!(agent_two
+        .verify_document_self_contained(&embed(agent_two.get_public_key().unwrap()))
+        .is_err());
+}
+
+#[test]
+fn test_create_document_unchecked_skips_header_validation() {
+    // cargo test   --test document_tests test_create_document_unchecked_skips_header_validation -- --nocapture
+    let mut agent = load_test_agent_one();
+    // jacsFiles must be an array, so the header schema rejects this
+    let off_schema = r#"{"jacsFiles": "not-an-array", "batch": "7"}"#.to_string();
+    assert!(agent
+        .create_document_and_load(&off_schema, None, None)
+        .is_err());
+
+    let document = agent
+        .create_document_and_load_unchecked(&off_schema, None, None)
+        .unwrap();
+    assert!(document.getvalue()["jacsId"].is_string());
+    assert!(agent.verify_hash(document.getvalue()).unwrap());
+    agent
+        .signature_verification_procedure(
+            document.getvalue(),
+            None,
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+            agent.get_public_key().unwrap(),
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+    // the input still has to be a new JSON object
+    assert!(agent
+        .create_document_and_load_unchecked(&r#"["not", "an", "object"]"#.to_string(), None, None)
+        .is_err());
+}
+
+#[test]
+fn test_verify_jsonl_reports_each_line() {
+    // cargo test   --test document_tests test_verify_jsonl_reports_each_line -- --nocapture
+    let mut agent = load_test_agent_one();
+    let first = agent
+        .create_document_and_load(&r#"{"entry": 1}"#.to_string(), None, None)
+        .unwrap();
+    let second = agent
+        .create_document_and_load(&r#"{"entry": 2}"#.to_string(), None, None)
+        .unwrap();
+    let mut tampered = second.getvalue().clone();
+    tampered["entry"] = serde_json::json!(3);
+
+    let log = format!(
+        "{}\n\n{{not json\n{}\n{}\n",
+        first.getvalue(),
+        tampered,
+        second.getvalue()
+    );
+    let mut results = Vec::new();
+    let checked = agent
+        .verify_jsonl(log.as_bytes(), |line, result| results.push((line, result)))
+        .unwrap();
+    assert_eq!(checked, 4);
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0], (1, VerificationResult::Valid(first.getkey())));
+    assert!(matches!(results[1], (3, VerificationResult::Invalid(_))));
+    assert!(matches!(results[2], (4, VerificationResult::Invalid(_))));
+    assert_eq!(results[3], (5, VerificationResult::Valid(second.getkey())));
+}
+
+#[test]
+fn test_document_cbor_round_trip_still_verifies() {
+    // cargo test   --test document_tests test_document_cbor_round_trip_still_verifies -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(
+            &r#"{"reading": 21.5, "count": 3, "tags": ["a", "b"], "nested": {"z": null, "a": true}}"#
+                .to_string(),
+            None,
+            None,
+        )
+        .unwrap();
+    let document_string = document.to_string();
+    let cbor = document_to_cbor(&document_string).unwrap();
+    assert!(cbor.len() < document_string.len());
+
+    let restored = document_from_cbor(&cbor).unwrap();
+    let mut agent_two = load_test_agent_two();
+    let reloaded = agent_two.load_document(&restored).unwrap();
+    assert_eq!(reloaded.getvalue(), document.getvalue());
+    agent_two
+        .verify_document_signature(
+            &reloaded.getkey(),
+            None,
+            None,
+            Some(agent.get_public_key().unwrap()),
+            None,
+        )
+        .unwrap();
+    assert_eq!(
+        document_content_hash(&restored).unwrap(),
+        document.getvalue()["jacsSha256"].as_str().unwrap()
+    );
+
+    // {"a": 1, "a": 2}
+    assert!(document_from_cbor(&[0xa2, 0x61, b'a', 0x01, 0x61, b'a', 0x02]).is_err());
+    // a bare integer is not a document
+    assert!(document_from_cbor(&[0x01]).is_err());
+}
+
+#[test]
+fn test_document_claimed_signer_is_unverified() {
+    // cargo test   --test document_tests test_document_claimed_signer_is_unverified -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&r#"{"route": "me"}"#.to_string(), None, None)
+        .unwrap();
+    let (agent_id, agent_version) = document_claimed_signer(&document.to_string()).unwrap();
+    assert_eq!(agent_id, agent.get_id().unwrap());
+    assert_eq!(agent_version, agent.get_version().unwrap());
+
+    // nothing is checked, a forged claim comes back as written
+    let mut forged = document.getvalue().clone();
+    forged[DOCUMENT_AGENT_SIGNATURE_FIELDNAME]["agentID"] = serde_json::json!("someone-else");
+    let (claimed_id, _) = document_claimed_signer(&forged.to_string()).unwrap();
+    assert_eq!(claimed_id, "someone-else");
+
+    assert!(document_claimed_signer(r#"{"jacsId": "unsigned"}"#).is_err());
+}
+
+#[test]
+fn test_explain_signature_lists_signed_fields() {
+    // cargo test   --test document_tests test_explain_signature_lists_signed_fields -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&r#"{"memo": "pay", "amount": 10}"#.to_string(), None, None)
+        .unwrap();
+    let mut value = document.getvalue().clone();
+    value["addedLater"] = serde_json::json!("not signed");
+
+    let explained: serde_json::Value =
+        serde_json::from_str(&explain_signature(&value.to_string()).unwrap()).unwrap();
+    let signed_fields = explained["signed_fields"].as_array().unwrap();
+    let excluded_fields = explained["excluded_fields"].as_array().unwrap();
+    assert!(signed_fields.contains(&serde_json::json!("memo")));
+    // only string values go into the signed string
+    assert!(!signed_fields.contains(&serde_json::json!("amount")));
+    assert!(excluded_fields.contains(&serde_json::json!("amount")));
+    assert!(excluded_fields.contains(&serde_json::json!("addedLater")));
+    assert!(excluded_fields.contains(&serde_json::json!(DOCUMENT_AGENT_SIGNATURE_FIELDNAME)));
+
+    // the preview is exactly what was signed
+    agent
+        .verify_string(
+            &explained["canonical_preview"].as_str().unwrap().to_string(),
+            &value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME]["signature"]
+                .as_str()
+                .unwrap()
+                .to_string(),
+            agent.get_public_key().unwrap(),
+            None,
+            None,
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_id_strategies_and_non_uuid_documents() {
+    // cargo test   --test document_tests test_id_strategies_and_non_uuid_documents -- --nocapture
+    use std::str::FromStr;
+    let content = serde_json::json!({"sku": "A-1"});
+    assert_eq!(IdStrategy::from_str("ulid").unwrap(), IdStrategy::Ulid);
+    assert_eq!(IdStrategy::default(), IdStrategy::Uuid);
+    let ulid = IdStrategy::Ulid.new_id(&content);
+    assert_eq!(ulid.len(), 26);
+    // sortable across milliseconds, ties within one are random
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    assert!(ulid < IdStrategy::Ulid.new_id(&content));
+    assert_eq!(
+        IdStrategy::ContentHash.new_id(&content),
+        IdStrategy::ContentHash.new_id(&serde_json::json!({"sku": "A-1"}))
+    );
+
+    // a document whose ids are ULIDs, as made with jacs_document_id_strategy = ulid
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&content.to_string(), None, None)
+        .unwrap();
+    let mut value = document.getvalue().clone();
+    value["jacsId"] = serde_json::json!(IdStrategy::Ulid.new_id(&content));
+    let version = IdStrategy::Ulid.new_id(&content);
+    value["jacsVersion"] = serde_json::json!(version);
+    value["jacsOriginalVersion"] = serde_json::json!(version);
+    value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME] = agent
+        .signing_procedure(
+            &value,
+            None,
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+        )
+        .unwrap();
+    value["jacsSha256"] = serde_json::json!(agent.hash_doc(&value).unwrap());
+
+    let mut agent_two = load_test_agent_two();
+    let loaded = agent_two.load_document(&value.to_string()).unwrap();
+    assert_eq!(
+        loaded.getkey(),
+        format!("{}:{}", value["jacsId"].as_str().unwrap(), version)
+    );
+    agent_two
+        .verify_document_signature(
+            &loaded.getkey(),
+            None,
+            None,
+            Some(agent.get_public_key().unwrap()),
+            None,
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_document_id_outside_pattern_rejected() {
+    // cargo test   --test document_tests test_document_id_outside_pattern_rejected -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&r#"{"sku": "C-3"}"#.to_string(), None, None)
+        .unwrap();
+    let mut value = document.getvalue().clone();
+    value["jacsId"] = serde_json::json!("../../agent/escaped");
+    value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME] = agent
+        .signing_procedure(
+            &value,
+            None,
+            &DOCUMENT_AGENT_SIGNATURE_FIELDNAME.to_string(),
+        )
+        .unwrap();
+    value["jacsSha256"] = serde_json::json!(agent.hash_doc(&value).unwrap());
+    assert!(agent.load_document(&value.to_string()).is_err());
+    assert!(agent
+        .fs_document_save(
+            &"../../agent/escaped:1".to_string(),
+            &value.to_string(),
+            None
+        )
+        .is_err());
+}
+
+#[test]
+fn test_verify_document_against_pinned_agent() {
+    // cargo test   --test document_tests test_verify_document_against_pinned_agent -- --nocapture
+    let mut agent = load_test_agent_one();
+    let mut agent_two = load_test_agent_two();
+    // the agent document exactly as saved, with the key pinned beside it
+    let snapshot = load_local_document(
+        &"examples/agent/48d074ec-84e2-4d26-adc5-0b2253f1e8ff:12ccba24-8997-47b1-9e6f-d699d7ab0e41.json"
+            .to_string(),
+    )
+    .unwrap();
+    let pinned_key = agent.get_public_key().unwrap();
+    let document = agent
+        .create_document_and_load(&r#"{"archived": true}"#.to_string(), None, None)
+        .unwrap();
+
+    assert!(agent_two
+        .verify_document_against_agent(&document.to_string(), &snapshot, pinned_key.clone())
+        .unwrap());
+
+    // another signer, and a key that isn't the snapshot's, both fail
+    let other = agent_two
+        .create_document_and_load(&r#"{"archived": true}"#.to_string(), None, None)
+        .unwrap();
+    assert!(agent_two
+        .verify_document_against_agent(&other.to_string(), &snapshot, pinned_key)
+        .is_err());
+    let error = agent_two
+        .verify_document_against_agent(
+            &document.to_string(),
+            &snapshot,
+            agent_two.get_public_key().unwrap(),
+        )
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("publicKeyHash"), "{}", error);
+}
+
+#[test]
+fn test_verification_cache_is_bound_to_content_and_key() {
+    // cargo test   --test document_tests test_verification_cache_is_bound_to_content_and_key -- --nocapture
+    let mut agent = load_test_agent_one();
+    let agent_two = load_test_agent_two();
+    agent.set_verification_cache(true);
+    let document = agent
+        .create_document_and_load(&r#"{"immutable": "v1"}"#.to_string(), None, None)
+        .unwrap();
+    let key = document.getkey();
+    for _ in 0..2 {
+        agent
+            .verify_document_signature(&key, None, None, None, None)
+            .unwrap();
+    }
+    // a cached success does not carry over to another key
+    assert!(agent
+        .verify_document_signature(
+            &key,
+            None,
+            None,
+            Some(agent_two.get_public_key().unwrap()),
+            None
+        )
+        .is_err());
+
+    // same id and version, different content: the cache must not vouch for it
+    let mut tampered = document.getvalue().clone();
+    tampered["immutable"] = serde_json::json!("v2");
+    tampered["jacsSha256"] = serde_json::json!(agent.hash_doc(&tampered).unwrap());
+    let reloaded = agent.load_document(&tampered.to_string()).unwrap();
+    assert_eq!(reloaded.getkey(), key);
+    assert!(agent
+        .verify_document_signature(&key, None, None, None, None)
+        .is_err());
+
+    agent.clear_verification_cache().unwrap();
+    agent.set_verification_cache(false);
+    agent.clear_verification_cache().unwrap();
+}
+
+#[test]
+fn test_verify_document_lenient_reports_unknown_fields() {
+    // cargo test   --test document_tests test_verify_document_lenient_reports_unknown_fields -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&r#"{"memo": "pay 10"}"#.to_string(), None, None)
+        .unwrap();
+    // a newer producer adds a signature attribute this schema version does not know
+    let mut newer = document.getvalue().clone();
+    newer["jacsSignature"]["futureAttribute"] = serde_json::json!("v2");
+    newer["jacsSha256"] = serde_json::json!(agent.hash_doc(&newer).unwrap());
+    let newer_string = newer.to_string();
+
+    assert!(agent
+        .verify_document_and_hash(&newer_string, None, None)
+        .is_err());
+    let result: serde_json::Value = serde_json::from_str(
+        &agent
+            .verify_document_lenient(&newer_string, None, None)
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(result["valid"], true);
+    assert_eq!(
+        result["unknown_fields"],
+        serde_json::json!(["/jacsSignature/futureAttribute"])
+    );
+
+    // unknown fields do not excuse a bad signature
+    newer["memo"] = serde_json::json!("pay 1000");
+    newer["jacsSha256"] = serde_json::json!(agent.hash_doc(&newer).unwrap());
+    let result: serde_json::Value = serde_json::from_str(
+        &agent
+            .verify_document_lenient(&newer.to_string(), None, None)
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(result["valid"], false);
+    assert!(result["error"].is_string());
+}
+
+#[test]
+fn test_verify_attachment_after_extraction() {
+    // cargo test   --test document_tests test_verify_attachment_after_extraction -- --nocapture
+    use jacs::agent::document::verify_attachment;
+    let mut agent = load_test_agent_one();
+    let attachment_path = "examples/raw/mobius.jpeg".to_string();
+    let document = agent
+        .create_document_and_load(
+            &r#"{"caption": "a mobius strip"}"#.to_string(),
+            Some(vec![attachment_path.clone()]),
+            Some(false),
+        )
+        .unwrap();
+    let document_json = document.to_string();
+
+    let mut extracted = std::fs::read(&attachment_path).unwrap();
+    assert!(verify_attachment(&document_json, 0, &extracted).unwrap());
+    let last = extracted.len() - 1;
+    extracted[last] ^= 0x01;
+    assert!(!verify_attachment(&document_json, 0, &extracted).unwrap());
+    assert!(verify_attachment(&document_json, 1, &extracted).is_err());
+}
+
+#[test]
+fn test_create_document_from_template() {
+    // cargo test   --test document_tests test_create_document_from_template -- --nocapture
+    use std::collections::HashMap;
+    let mut agent = load_test_agent_one();
+    let template = r#"{
+        "title": "Invoice {{invoice_number}} for {{ customer }}",
+        "customer": "{{customer}}",
+        "amount": "{{amount}}",
+        "lines": ["{{first_line}}"]
+    }"#;
+    let mut variables: HashMap<String, serde_json::Value> = HashMap::new();
+    variables.insert("invoice_number".to_string(), serde_json::json!(42));
+    variables.insert("customer".to_string(), serde_json::json!("ACME \"Corp\""));
+    variables.insert("amount".to_string(), serde_json::json!(99.5));
+
+    // first_line is still missing
+    let refused = agent.create_document_from_template(template, &variables);
+    assert!(refused.unwrap_err().to_string().contains("first_line"));
+
+    variables.insert(
+        "first_line".to_string(),
+        serde_json::json!({"item": "widget", "quantity": 3}),
+    );
+    let document = agent
+        .create_document_from_template(template, &variables)
+        .unwrap();
+    let value = document.getvalue();
+    assert_eq!(value["title"], "Invoice 42 for ACME \"Corp\"");
+    assert_eq!(value["customer"], "ACME \"Corp\"");
+    assert_eq!(value["amount"], 99.5);
+    assert_eq!(value["lines"][0]["quantity"], 3);
+    agent
+        .verify_document_signature(&document.getkey(), None, None, None, None)
+        .unwrap();
+}
+
+#[test]
+fn test_documents_signed_by_earlier_releases_still_verify() {
+    // cargo test   --test document_tests test_documents_signed_by_earlier_releases_still_verify -- --nocapture
+    use jacs::agent::loaders::FileLoader;
+    // tests/fixtures/releases/<version> holds documents signed by that release. the 0.2.13
+    // ones predate jacsSignatureFormat, so they also cover the format 1 fallback for
+    // documents that record no version. add a directory for each release to extend this
+    let mut agent = load_test_agent_one();
+    let mut entries: Vec<_> = std::fs::read_dir("tests/fixtures/releases")
+        .unwrap()
+        .flat_map(|release| std::fs::read_dir(release.unwrap().path()).unwrap())
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    entries.sort();
+    assert!(!entries.is_empty(), "no release fixtures found");
+    for path in entries {
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        let document_string = std::fs::read_to_string(&path).unwrap();
+        let result = agent.load_document(&document_string).and_then(|document| {
+            let signature = &document.getvalue()["jacsSignature"];
+            let public_key = agent
+                .fs_load_public_key(&signature["publicKeyHash"].as_str().unwrap().to_string())?;
+            agent.verify_document_signature(
+                &document.getkey(),
+                None,
+                None,
+                Some(public_key),
+                signature["signingAlgorithm"].as_str().map(String::from),
+            )
+        });
+        assert!(result.is_ok(), "{} no longer verifies: {:?}", name, result);
+    }
+}
+
+#[test]
+fn test_verify_and_store_quarantines_rejected_documents() {
+    // cargo test   --test document_tests test_verify_and_store_quarantines_rejected_documents -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&r#"{"memo": "ingest me"}"#.to_string(), None, None)
+        .unwrap();
+    let document_key = agent
+        .verify_and_store(&document.getvalue().to_string())
+        .unwrap();
+    assert_eq!(document_key, document.getkey());
+    let data_dir = std::path::PathBuf::from(std::env::var("JACS_DATA_DIRECTORY").unwrap());
+    let stored = agent.fs_document_load(&document_key).unwrap();
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&stored).unwrap(),
+        document.getvalue().clone()
+    );
+
+    let mut tampered = document.getvalue().clone();
+    tampered["memo"] = serde_json::json!("ingest something else");
+    let tampered_string = tampered.to_string();
+    let error = agent
+        .verify_and_store(&tampered_string)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("quarantined"));
+    let quarantined = std::fs::read_dir(data_dir.join("quarantine"))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| std::fs::read_to_string(path).unwrap_or_default() == tampered_string)
+        .unwrap();
+    std::fs::remove_file(quarantined).unwrap();
+}
+
+#[test]
+fn test_signed_bytes_for_field_verify_independently() {
+    // cargo test   --test document_tests test_signed_bytes_for_field_verify_independently -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&r#"{"memo": "disputed"}"#.to_string(), None, None)
+        .unwrap();
+    let document_string = document.getvalue().to_string();
+    let signed_bytes =
+        signed_bytes_for_field(&document_string, DOCUMENT_AGENT_SIGNATURE_FIELDNAME).unwrap();
+    let signature = document.getvalue()[DOCUMENT_AGENT_SIGNATURE_FIELDNAME]["signature"]
+        .as_str()
+        .unwrap();
+    agent
+        .verify_bytes(
+            &signed_bytes,
+            signature,
+            agent.get_public_key().unwrap(),
+            None,
+        )
+        .unwrap();
+
+    let explained: serde_json::Value =
+        serde_json::from_str(&explain_signature(&document_string).unwrap()).unwrap();
+    assert_eq!(
+        explained["canonical_preview"].as_str().unwrap().as_bytes(),
+        signed_bytes.as_slice()
+    );
+    assert!(signed_bytes_for_field(&document_string, "memo").is_err());
+    assert!(signed_bytes_for_field(&document_string, "jacsRegistration").is_err());
+}
+
+#[test]
+fn test_external_signature_round_trip() {
+    // cargo test   --test document_tests test_external_signature_round_trip -- --nocapture
+    let mut agent = load_test_agent_one();
+    // the agent's own key stands in for one held in an HSM
+    let public_key = agent.get_public_key().unwrap();
+    let agent_value: serde_json::Value = serde_json::from_str(&agent.as_string().unwrap()).unwrap();
+    let algorithm = agent_value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME]["signingAlgorithm"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let prepared: serde_json::Value = serde_json::from_str(
+        &agent
+            .prepare_external_signature(r#"{"memo": "signed elsewhere"}"#)
+            .unwrap(),
+    )
+    .unwrap();
+    let signing_payload = prepared["signing_payload"].as_str().unwrap().to_string();
+    assert_eq!(
+        prepared["digest"].as_str().unwrap(),
+        jacs::crypt::hash::hash_string(&signing_payload)
+    );
+    let prepared_document = prepared["document"].to_string();
+
+    let wrong_signature = agent
+        .sign_string(&"something else".to_string(), None)
+        .unwrap();
+    assert!(agent
+        .apply_external_signature(
+            &prepared_document,
+            &wrong_signature,
+            public_key.clone(),
+            algorithm.clone(),
+        )
+        .is_err());
+
+    // a valid signature by some other key can't be recorded as this agent's
+    let mut other = load_test_agent_two();
+    let other_value: serde_json::Value = serde_json::from_str(&other.as_string().unwrap()).unwrap();
+    let error = agent
+        .apply_external_signature(
+            &prepared_document,
+            &other.sign_string(&signing_payload, None).unwrap(),
+            other.get_public_key().unwrap(),
+            other_value[DOCUMENT_AGENT_SIGNATURE_FIELDNAME]["signingAlgorithm"]
+                .as_str()
+                .unwrap()
+                .to_string(),
+        )
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("registered key"), "{}", error);
+
+    let signature = agent.sign_string(&signing_payload, None).unwrap();
+    let document = agent
+        .apply_external_signature(
+            &prepared_document,
+            &signature,
+            public_key.clone(),
+            algorithm.clone(),
+        )
+        .unwrap();
+    assert_eq!(document.getvalue()["memo"], "signed elsewhere");
+    agent
+        .verify_document_and_hash(
+            &document.getvalue().to_string(),
+            Some(public_key),
+            Some(algorithm),
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_create_document_for_header_version() {
+    // cargo test   --test document_tests test_create_document_for_header_version -- --nocapture
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load_with_version(
+            &r#"{"memo": "for an older consumer"}"#.to_string(),
+            None,
+            None,
+            Some("v1"),
+        )
+        .unwrap();
+    assert_eq!(
+        document.getvalue()["$schema"],
+        "https://hai.ai/schemas/header/v1/header.schema.json"
+    );
+    agent
+        .verify_document_and_hash(&document.getvalue().to_string(), None, None)
+        .unwrap();
+
+    let error = agent
+        .create_document_and_load_with_version(
+            &r#"{"memo": "from the future"}"#.to_string(),
+            None,
+            None,
+            Some("v9"),
+        )
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("unknown header schema version v9"));
+    assert!(agent
+        .create_document_and_load_with_version(
+            &r#"{"$schema": "https://hai.ai/schemas/task/v1/task.schema.json"}"#.to_string(),
+            None,
+            None,
+            Some("v1"),
+        )
+        .is_err());
+}
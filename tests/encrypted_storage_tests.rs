@@ -0,0 +1,122 @@
+use jacs::agent::agreement::Agreement;
+use jacs::agent::boilerplate::BoilerPlate;
+use jacs::agent::document::Document;
+use jacs::agent::loaders::FileLoader;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+mod utils;
+use utils::{load_test_agent_one, load_test_agent_two};
+
+/// the storage settings are process-wide env vars, so tests that change them take turns
+static STORAGE_ENV: Mutex<()> = Mutex::new(());
+
+#[test]
+fn test_encrypted_storage_round_trip() {
+    // cargo test   --test encrypted_storage_tests -- --nocapture
+    let _env = STORAGE_ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let mut agent = load_test_agent_one();
+    let secret = "account 0123-4567, do not print";
+    let document = agent
+        .create_document_and_load(
+            &serde_json::json!({ "body": secret }).to_string(),
+            None,
+            None,
+        )
+        .unwrap();
+    let document_key = document.getkey();
+
+    // set after loading the agent, which resets env vars from the config
+    env::set_var("JACS_ENCRYPT_STORAGE", "true");
+    env::remove_var("JACS_STORAGE_PASSWORD");
+    assert!(agent
+        .save_document(&document_key, None, None, None)
+        .is_err());
+    env::set_var("JACS_STORAGE_PASSWORD", "storage-secret");
+    agent
+        .save_document(&document_key, None, None, None)
+        .unwrap();
+    env::set_var("JACS_ENCRYPT_STORAGE", "false");
+
+    let data_dir = PathBuf::from(env::var("JACS_DATA_DIRECTORY").unwrap()).join("documents");
+    let on_disk = fs::read(data_dir.join(format!("{}.json.enc", document_key))).unwrap();
+    assert!(!String::from_utf8_lossy(&on_disk).contains(secret));
+    assert!(agent.fs_document_list().unwrap().contains(&document_key));
+
+    let loaded_string = agent.fs_document_load(&document_key).unwrap();
+    let reloaded = agent.load_document(&loaded_string).unwrap();
+    assert_eq!(reloaded.getvalue(), document.getvalue());
+    agent
+        .verify_document_signature(&document_key, None, None, None, None)
+        .unwrap();
+
+    env::set_var("JACS_STORAGE_PASSWORD", "wrong-secret");
+    assert!(agent.fs_document_load(&document_key).is_err());
+}
+
+#[test]
+fn test_encrypted_agreement_progress() {
+    // cargo test   --test encrypted_storage_tests test_encrypted_agreement_progress -- --nocapture
+    let _env = STORAGE_ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let mut agent = load_test_agent_one();
+    let agent_two = load_test_agent_two();
+    let secret = "merger terms, not public yet";
+    let document = agent
+        .create_document_and_load(
+            &serde_json::json!({ "terms": secret }).to_string(),
+            None,
+            None,
+        )
+        .unwrap();
+    let agentids = vec![agent.get_id().unwrap(), agent_two.get_id().unwrap()];
+    let unsigned_doc = agent
+        .create_agreement(&document.getkey(), &agentids, None, None, None)
+        .unwrap();
+
+    env::set_var("JACS_ENCRYPT_STORAGE", "true");
+    env::set_var("JACS_STORAGE_PASSWORD", "storage-secret");
+    let (agreement_id, waiting_on) = agent
+        .save_agreement_progress(&unsigned_doc.getkey(), None)
+        .unwrap();
+    env::set_var("JACS_ENCRYPT_STORAGE", "false");
+    assert_eq!(waiting_on.len(), 2);
+
+    let agreements_dir = PathBuf::from(env::var("JACS_DATA_DIRECTORY").unwrap()).join("agreements");
+    let on_disk = fs::read(agreements_dir.join(format!("{}.json.enc", agreement_id))).unwrap();
+    assert!(!String::from_utf8_lossy(&on_disk).contains(secret));
+    let resumed = agent.load_agreement_progress(&agreement_id).unwrap();
+    assert_eq!(resumed.getkey(), unsigned_doc.getkey());
+    fs::remove_file(agreements_dir.join(format!("{}.json.enc", agreement_id))).unwrap();
+}
+
+#[test]
+fn test_storage_key_derivation() {
+    // cargo test   --test encrypted_storage_tests test_storage_key_derivation -- --nocapture
+    use jacs::crypt::aes_encrypt::{decrypt_with_storage_key, encrypt_with_storage_key};
+    let _env = STORAGE_ENV.lock().unwrap_or_else(|e| e.into_inner());
+    env::set_var("JACS_STORAGE_KDF_ITERATIONS", "1000");
+    let first = encrypt_with_storage_key(b"first document", "storage-secret").unwrap();
+    let second = encrypt_with_storage_key(b"second document", "storage-secret").unwrap();
+    // one storage key per process, but every file gets its own salt and nonce
+    assert_eq!(first[..28], second[..28]);
+    assert_ne!(first[28..56], second[28..56]);
+    assert_eq!(
+        decrypt_with_storage_key(&first, "storage-secret").unwrap(),
+        b"first document"
+    );
+    assert!(decrypt_with_storage_key(&first, "wrong-secret").is_err());
+
+    // another salt means another storage key, derived fresh rather than from the cache
+    let mut foreign = first.clone();
+    foreign[12] ^= 1;
+    assert!(decrypt_with_storage_key(&foreign, "storage-secret").is_err());
+
+    let mut hostile = second.clone();
+    hostile[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert!(decrypt_with_storage_key(&hostile, "storage-secret").is_err());
+
+    env::set_var("JACS_STORAGE_KDF_ITERATIONS", "0");
+    assert!(encrypt_with_storage_key(b"third document", "storage-secret").is_err());
+    env::remove_var("JACS_STORAGE_KDF_ITERATIONS");
+}
@@ -0,0 +1,26 @@
+use jacs::agent::document::Document;
+use jacs::agent::JACS_HASH_ALGORITHM_FIELDNAME;
+use std::env;
+mod utils;
+use utils::load_test_agent_one;
+
+#[test]
+fn test_configured_document_hash_algorithm() {
+    // cargo test   --test hash_algorithm_tests -- --nocapture
+    let mut agent = load_test_agent_one();
+    // set after loading the agent, which resets env vars from the config
+    for (algorithm, digest_length) in [("sha512", 128), ("blake3", 64)] {
+        env::set_var("JACS_DOCUMENT_HASH_ALGORITHM", algorithm);
+        let document = agent
+            .create_document_and_load(&r#"{"regulated": true}"#.to_string(), None, None)
+            .unwrap();
+        let value = document.getvalue();
+        assert_eq!(value[JACS_HASH_ALGORITHM_FIELDNAME], algorithm);
+        assert_eq!(value["jacsSha256"].as_str().unwrap().len(), digest_length);
+        assert!(agent.verify_hash_only(&value.to_string()).unwrap());
+        agent
+            .verify_document_signature(&document.getkey(), None, None, None, None)
+            .unwrap();
+    }
+    env::remove_var("JACS_DOCUMENT_HASH_ALGORITHM");
+}
@@ -0,0 +1,66 @@
+use jacs::agent::boilerplate::BoilerPlate;
+use jacs::agent::document::Document;
+use jacs::schema::IdStrategy;
+use std::env;
+use std::fs;
+mod utils;
+use utils::load_test_agent_one;
+
+#[test]
+fn test_configured_document_id_strategy() {
+    // cargo test   --test id_strategy_tests -- --nocapture
+    let mut agent = load_test_agent_one();
+    // set after loading the agent, which resets env vars from the config
+    env::set_var("JACS_DOCUMENT_ID_STRATEGY", "ulid");
+    let document = agent
+        .create_document_and_load(&r#"{"sku": "A-1"}"#.to_string(), None, None)
+        .unwrap();
+    let value = document.getvalue();
+    for field in ["jacsId", "jacsVersion"] {
+        let id = value[field].as_str().unwrap();
+        assert_eq!(id.len(), 26);
+        assert!(id.chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+    agent
+        .verify_document_signature(&document.getkey(), None, None, None, None)
+        .unwrap();
+
+    env::set_var("JACS_DOCUMENT_ID_STRATEGY", "content-hash");
+    let first = agent
+        .create_document_and_load(&r#"{"sku": "B-2"}"#.to_string(), None, None)
+        .unwrap();
+    let second = agent
+        .create_document_and_load(&r#"{"sku": "B-2"}"#.to_string(), None, None)
+        .unwrap();
+    let first_id = first.getvalue()["jacsId"].as_str().unwrap().to_string();
+    assert_eq!(first_id.len(), 64);
+    assert_eq!(first_id, second.getvalue()["jacsId"].as_str().unwrap());
+    assert_ne!(first_id, first.getvalue()["jacsVersion"].as_str().unwrap());
+    agent
+        .verify_document_signature(&first.getkey(), None, None, None, None)
+        .unwrap();
+
+    // the agent's own strategy wins over the env setting
+    agent.set_id_strategy(Some(IdStrategy::Ulid));
+    let document = agent
+        .create_document_and_load(&r#"{"sku": "C-3"}"#.to_string(), None, None)
+        .unwrap();
+    assert_eq!(document.getvalue()["jacsId"].as_str().unwrap().len(), 26);
+    agent.set_id_strategy(None);
+
+    // agents keep uuids whatever the document strategy
+    let mut new_agent =
+        jacs::agent::Agent::new(&"v1".to_string(), &"v1".to_string(), &"v1".to_string()).unwrap();
+    env::set_var("JACS_DOCUMENT_ID_STRATEGY", "ulid");
+    let agent_json = fs::read_to_string("examples/raw/myagent.new.json").unwrap();
+    new_agent
+        .create_agent_and_load(&agent_json, false, None)
+        .unwrap();
+    for id in [
+        new_agent.get_id().unwrap(),
+        new_agent.get_version().unwrap(),
+    ] {
+        assert!(uuid::Uuid::parse_str(&id).is_ok(), "{} is not a uuid", id);
+    }
+    env::remove_var("JACS_DOCUMENT_ID_STRATEGY");
+}
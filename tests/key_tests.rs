@@ -6,6 +6,9 @@ mod utils;
 
 use jacs::crypt::hash::hash_public_key;
 use jacs::crypt::hash::hash_string as jacs_hash_string;
+use jacs::crypt::hash::{
+    hash_string_with, hashes_match, public_key_fingerprint, FingerprintStyle, HashAlgorithm,
+};
 use utils::{load_local_document, load_test_agent_one, load_test_agent_two};
 
 #[test]
@@ -122,3 +125,171 @@ hCmTebk/ToIKWZ+YeOMbi38CAwEAAQ==
     // let public_key_string_lossy_nnl = String::from_utf8_lossy(public_key_no_newline).to_string();
     // let public_key_rehash3_nnl = jacs_hash_string(&public_key_no_newline);
 }
+
+#[test]
+fn test_hash_string_with_vectors() {
+    // cargo test   --test key_tests -- --nocapture
+    let input = "abc";
+    assert_eq!(
+        hash_string_with(input, HashAlgorithm::Sha256),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        hash_string_with(input, HashAlgorithm::Sha256),
+        jacs_hash_string(&input.to_string())
+    );
+    assert_eq!(
+        hash_string_with(input, HashAlgorithm::Sha512),
+        "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+    );
+    assert_eq!(
+        hash_string_with(input, HashAlgorithm::Blake3),
+        "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+    );
+    assert_eq!(
+        "blake3".parse::<HashAlgorithm>().unwrap(),
+        HashAlgorithm::Blake3
+    );
+}
+
+#[test]
+fn test_hashes_match() {
+    let digest = jacs_hash_string(&"abc".to_string());
+    assert!(hashes_match(&digest, &digest.clone()));
+    assert!(!hashes_match(
+        &digest,
+        &jacs_hash_string(&"abd".to_string())
+    ));
+    assert!(!hashes_match(&digest, &digest[..63]));
+    assert!(!hashes_match(&digest, ""));
+}
+
+#[test]
+fn test_public_key_fingerprint_styles() {
+    // cargo test   --test key_tests test_public_key_fingerprint_styles -- --nocapture
+    let public_key = std::fs::read("tests/fixtures/public_key_no_newline.pem").unwrap();
+    assert_eq!(
+        public_key_fingerprint(&public_key, FingerprintStyle::ColonHex).unwrap(),
+        "2c:9c:c6:36:1e:20:03:17:3d:f8:6b:9c:26:7b:38:91:19:33:19:da:7f:e7:c6:f4:2c:b0:fb:e5:b3:0d:7c:0d"
+    );
+    assert_eq!(
+        public_key_fingerprint(&public_key, FingerprintStyle::Base32).unwrap(),
+        "FSOMMNQ6EABROPPYNOOCM6ZYSEMTGGO2P7T4N5BMWD56LMYNPQGQ"
+    );
+    assert_eq!(
+        public_key_fingerprint(&public_key, FingerprintStyle::Words).unwrap(),
+        "clutch tower mirror detail ability merit wave aspect order critic soda cart"
+    );
+    // the same digest as publicKeyHash, so a trailing newline does not change it
+    let with_newline = std::fs::read("tests/fixtures/public_key_with_newline.pem").unwrap();
+    assert_eq!(
+        public_key_fingerprint(&with_newline, FingerprintStyle::ColonHex)
+            .unwrap()
+            .replace(":", ""),
+        hash_public_key(with_newline)
+    );
+}
+
+#[test]
+fn test_key_encryption_records_kdf_params() {
+    // cargo test   --test key_tests test_key_encryption_records_kdf_params -- --nocapture
+    use jacs::crypt::aes_encrypt::{
+        decrypt_private_key_with_password, encrypt_private_key_with_params,
+        encrypted_key_kdf_params, reencrypt_private_key, KdfParams, KeyDerivation,
+    };
+    let private_key = b"not really a private key".to_vec();
+    let legacy_params = KdfParams {
+        kdf: KeyDerivation::Sha256,
+        iterations: 1,
+    };
+    // a key file written before the KDF was configurable
+    let legacy = encrypt_private_key_with_params(&private_key, "old", &legacy_params).unwrap();
+    assert_eq!(encrypted_key_kdf_params(&legacy).unwrap(), legacy_params);
+    assert_eq!(
+        decrypt_private_key_with_password(&legacy, "old").unwrap(),
+        private_key
+    );
+
+    let tuned_params = KdfParams {
+        kdf: KeyDerivation::Pbkdf2Sha256,
+        iterations: 1000,
+    };
+    let tuned = encrypt_private_key_with_params(&private_key, "old", &tuned_params).unwrap();
+    assert_eq!(encrypted_key_kdf_params(&tuned).unwrap(), tuned_params);
+    assert_eq!(
+        decrypt_private_key_with_password(&tuned, "old").unwrap(),
+        private_key
+    );
+    assert!(decrypt_private_key_with_password(&tuned, "wrong").is_err());
+    // a header asking for an absurd iteration count is refused instead of hanging
+    let mut hostile = tuned.clone();
+    hostile[9..13].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert!(decrypt_private_key_with_password(&hostile, "old").is_err());
+
+    let upgraded = reencrypt_private_key(&legacy, "old", "new").unwrap();
+    assert_eq!(
+        encrypted_key_kdf_params(&upgraded).unwrap().kdf,
+        KeyDerivation::Pbkdf2Sha256
+    );
+    assert_eq!(
+        decrypt_private_key_with_password(&upgraded, "new").unwrap(),
+        private_key
+    );
+    assert!(reencrypt_private_key(&legacy, "wrong", "new").is_err());
+}
+
+#[test]
+fn test_sign_batch_streaming() {
+    // cargo test   --test key_tests test_sign_batch_streaming -- --nocapture
+    let mut agent = load_test_agent_one();
+    let messages = (0..5).map(|n| format!("message {}", n));
+    let mut signatures = Vec::new();
+    let signed = agent
+        .sign_batch_streaming(messages, |index, result| {
+            signatures.push((index, result.unwrap()))
+        })
+        .unwrap();
+    assert_eq!(signed, 5);
+    let public_key = agent.get_public_key().unwrap();
+    for (index, signature) in signatures {
+        agent
+            .verify_string(
+                &format!("message {}", index),
+                &signature,
+                public_key.clone(),
+                None,
+                None,
+            )
+            .unwrap();
+    }
+}
+
+#[test]
+fn test_supported_algorithms() {
+    // cargo test   --test key_tests test_supported_algorithms -- --nocapture
+    use jacs::crypt::{resolve_algorithm_name, supported_algorithms, AlgorithmStrength};
+    let algorithms = supported_algorithms();
+    let names: Vec<&str> = algorithms.iter().map(|info| info.name.as_str()).collect();
+    assert_eq!(names, vec!["RSA-PSS", "ring-Ed25519", "pq-dilithium"]);
+    let dilithium = algorithms
+        .iter()
+        .find(|info| info.name == "pq-dilithium")
+        .unwrap();
+    assert_eq!(dilithium.strength, AlgorithmStrength::PostQuantum);
+
+    for info in &algorithms {
+        assert_eq!(resolve_algorithm_name(&info.name).unwrap(), info.name);
+        for alias in &info.aliases {
+            assert_eq!(resolve_algorithm_name(alias).unwrap(), info.name);
+        }
+    }
+    assert_eq!(resolve_algorithm_name("Ed25519").unwrap(), "ring-Ed25519");
+    assert!(resolve_algorithm_name("dsa").is_err());
+
+    // aliases are accepted wherever an algorithm is named
+    assert_eq!(
+        jacs::crypt::algorithm_strength("dilithium").unwrap(),
+        AlgorithmStrength::PostQuantum
+    );
+    assert!(jacs::crypt::generate_key_pair("rsa").is_ok());
+}
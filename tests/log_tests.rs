@@ -0,0 +1,68 @@
+use jacs::agent::document::Document;
+use jacs::agent::document_log::DocumentLog;
+use jacs::agent::JACS_LOG_PREV_HASH_FIELDNAME;
+use serde_json::json;
+mod utils;
+use utils::load_test_agent_one;
+
+#[test]
+fn test_append_and_verify_log() {
+    // cargo test   --test log_tests -- --nocapture
+    let mut agent = load_test_agent_one();
+    let log_id = format!("audit-trail-{}", uuid::Uuid::new_v4());
+    for step in 0..3 {
+        agent
+            .append_to_log(&log_id, json!({ "event": format!("step {}", step) }))
+            .unwrap();
+    }
+    assert_eq!(agent.get_log_entries(&log_id).len(), 3);
+    assert!(agent.verify_log(&log_id).unwrap());
+}
+
+#[test]
+fn test_tampered_log_reports_first_bad_entry() {
+    let mut agent = load_test_agent_one();
+    let log_id = format!("tampered-trail-{}", uuid::Uuid::new_v4());
+    for step in 0..3 {
+        agent
+            .append_to_log(&log_id, json!({ "event": format!("step {}", step) }))
+            .unwrap();
+    }
+
+    let entries = agent.get_log_entries(&log_id);
+    let mut tampered = entries[1].value.clone();
+    tampered[JACS_LOG_PREV_HASH_FIELDNAME] = json!("not the previous hash");
+    agent.store_jacs_document(&tampered).unwrap();
+
+    let error = agent.verify_log(&log_id).unwrap_err().to_string();
+    println!("{}", error);
+    assert!(error.contains("broken at entry 1"));
+}
+
+#[test]
+fn test_log_survives_restart() {
+    // cargo test   --test log_tests test_log_survives_restart -- --nocapture
+    let log_id = format!("restart-trail-{}", uuid::Uuid::new_v4());
+    let mut agent = load_test_agent_one();
+    for step in 0..2 {
+        agent
+            .append_to_log(&log_id, json!({ "event": format!("step {}", step) }))
+            .unwrap();
+    }
+
+    // verify_log loads a saved log by itself
+    let mut fresh = load_test_agent_one();
+    assert!(fresh.verify_log(&log_id).unwrap());
+
+    let mut restarted = load_test_agent_one();
+    assert_eq!(restarted.load_log(&log_id).unwrap(), 2);
+    assert!(restarted.verify_log(&log_id).unwrap());
+
+    let mut continued = load_test_agent_one();
+    let entry = continued
+        .append_to_log(&log_id, json!({ "event": "after restart" }))
+        .unwrap();
+    assert_eq!(entry.value["jacsLogIndex"], 2);
+    assert!(continued.verify_log(&log_id).unwrap());
+    assert!(continued.load_log(&"../escape".to_string()).is_err());
+}
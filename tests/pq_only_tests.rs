@@ -0,0 +1,62 @@
+use jacs::agent::document::Document;
+use jacs::agent::DOCUMENT_AGENT_SIGNATURE_FIELDNAME;
+use std::env;
+use std::sync::Mutex;
+mod utils;
+use utils::load_test_agent_one;
+
+// both tests flip JACS_PQ_ONLY, which is process-wide
+static PQ_ONLY_ENV: Mutex<()> = Mutex::new(());
+
+#[test]
+fn test_pq_only_rejects_classical_signatures() {
+    // cargo test   --test pq_only_tests -- --nocapture
+    let _env = PQ_ONLY_ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let mut agent = load_test_agent_one();
+    let document = agent
+        .create_document_and_load(&r#"{"memo": "signed classically"}"#.to_string(), None, None)
+        .unwrap();
+    let document_string = document.getvalue().to_string();
+    let signing_algorithm = document.getvalue()[DOCUMENT_AGENT_SIGNATURE_FIELDNAME]
+        ["signingAlgorithm"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // a valid signature, refused for its algorithm
+    let error = agent
+        .verify_document_pq_only(&document_string)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("post-quantum"));
+    agent
+        .verify_document_and_hash(&document_string, None, Some(signing_algorithm.clone()))
+        .unwrap();
+
+    // set after loading the agent, which resets env vars from the config
+    env::set_var("JACS_PQ_ONLY", "true");
+    let refused =
+        agent.verify_document_and_hash(&document_string, None, Some(signing_algorithm.clone()));
+    env::set_var("JACS_PQ_ONLY", "false");
+    assert!(refused.unwrap_err().to_string().contains("post-quantum"));
+}
+
+#[test]
+fn test_pq_only_applies_to_cached_verifications() {
+    let _env = PQ_ONLY_ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let mut agent = load_test_agent_one();
+    agent.set_verification_cache(true);
+    let document = agent
+        .create_document_and_load(&r#"{"memo": "verified once"}"#.to_string(), None, None)
+        .unwrap();
+    let document_key = document.getkey();
+    agent
+        .verify_document_signature(&document_key, None, None, None, None)
+        .unwrap();
+
+    // the check is cached now, but the policy still applies
+    env::set_var("JACS_PQ_ONLY", "true");
+    let refused = agent.verify_document_signature(&document_key, None, None, None, None);
+    env::set_var("JACS_PQ_ONLY", "false");
+    assert!(refused.unwrap_err().to_string().contains("post-quantum"));
+}
@@ -1,10 +1,10 @@
 mod utils;
 use jacs::agent::boilerplate::BoilerPlate;
-use jacs::crypt::KeyManager;
+use jacs::crypt::{generate_key_pair, KeyManager};
 use secrecy::ExposeSecret;
 use std::env;
 use std::fs;
-use utils::load_test_agent_one;
+use utils::assert_signs_in_every_encoding;
 
 fn set_enc_to_pq() {
     env::set_var("JACS_AGENT_PRIVATE_KEY_FILENAME", "test-pq-private.pem");
@@ -49,3 +49,31 @@ fn test_pq_create_and_verify_signature() {
         std::str::from_utf8(&public).expect("Failed to convert bytes to string")
     );
 }
+
+#[test]
+fn test_pq_signature_verifies() {
+    // cargo test   --test pq_tests test_pq_signature_verifies -- --nocapture
+    let (private_key, public_key) = jacs::crypt::pq::generate_keys().unwrap();
+    let data = "signed with dilithium".to_string();
+    let signature = jacs::crypt::pq::sign_string(private_key, &data).unwrap();
+    jacs::crypt::pq::verify_string(public_key.clone(), &data, &signature).unwrap();
+    assert!(
+        jacs::crypt::pq::verify_string(public_key, &"something else".to_string(), &signature)
+            .is_err()
+    );
+}
+
+#[test]
+fn test_pq_signature_encodings() {
+    // cargo test   --test pq_tests test_pq_signature_encodings -- --nocapture
+    let mut agent =
+        jacs::agent::Agent::new(&"v1".to_string(), &"v1".to_string(), &"v1".to_string()).unwrap();
+    set_enc_to_pq();
+    let key_algorithm = "pq-dilithium".to_string();
+    let (private_key, public_key) = generate_key_pair(&key_algorithm).unwrap();
+    agent
+        .set_keys(private_key, public_key.clone(), &key_algorithm)
+        .unwrap();
+
+    assert_signs_in_every_encoding(&mut agent, public_key);
+}
@@ -1,10 +1,10 @@
 mod utils;
 use jacs::agent::boilerplate::BoilerPlate;
-use jacs::crypt::KeyManager;
+use jacs::crypt::{generate_key_pair, KeyManager};
 use secrecy::ExposeSecret;
 use std::env;
 use std::fs;
-use utils::load_test_agent_one;
+use utils::assert_signs_in_every_encoding;
 
 fn set_enc_to_ring() {
     env::set_var(
@@ -57,3 +57,42 @@ fn test_ring_Ed25519_create_and_verify_signature() {
         std::str::from_utf8(&public).expect("Failed to convert bytes to string")
     );
 }
+
+#[test]
+fn test_ring_ed25519_signature_encodings() {
+    // cargo test   --test ring_tests test_ring_ed25519_signature_encodings -- --nocapture
+    let mut agent =
+        jacs::agent::Agent::new(&"v1".to_string(), &"v1".to_string(), &"v1".to_string()).unwrap();
+    set_enc_to_ring();
+    let key_algorithm = "ring-Ed25519".to_string();
+    let (private_key, public_key) = generate_key_pair(&key_algorithm).unwrap();
+    agent
+        .set_keys(private_key, public_key.clone(), &key_algorithm)
+        .unwrap();
+
+    assert_signs_in_every_encoding(&mut agent, public_key);
+}
+
+#[test]
+fn test_create_agent_in_memory_with_algorithm() {
+    // the algorithm argument wins over JACS_AGENT_KEY_ALGORITHM, which the config sets to RSA-PSS
+    let mut agent =
+        jacs::agent::Agent::new(&"v1".to_string(), &"v1".to_string(), &"v1".to_string()).unwrap();
+    let agent_json = fs::read_to_string("examples/raw/myagent.new.json").unwrap();
+    let created: serde_json::Value = serde_json::from_str(
+        &agent
+            .create_agent_in_memory(&agent_json, "in-memory-password", "ring-Ed25519")
+            .unwrap(),
+    )
+    .unwrap();
+    let config: serde_json::Value =
+        serde_json::from_str(created["config_json"].as_str().unwrap()).unwrap();
+    assert_eq!(config["jacs_agent_key_algorithm"], "ring-Ed25519");
+    let agent_value: serde_json::Value =
+        serde_json::from_str(created["agent_json"].as_str().unwrap()).unwrap();
+    assert_eq!(
+        agent_value["jacsSignature"]["signingAlgorithm"],
+        "ring-Ed25519"
+    );
+    agent.verify_self_signature().unwrap();
+}
@@ -4,7 +4,7 @@ use std::env;
 mod utils;
 use jacs::agent::boilerplate::BoilerPlate;
 use jacs::crypt::KeyManager;
-use utils::load_test_agent_one;
+use utils::{assert_signs_in_every_encoding, load_test_agent_one};
 
 fn set_enc_to_rsa() {
     env::set_var("JACS_AGENT_PRIVATE_KEY_FILENAME", "rsa_pss_private.pem");
@@ -66,3 +66,35 @@ fn test_rsa_create_and_verify_signature() {
     //     verify_result.err()
     // );
 }
+
+#[test]
+fn test_rsa_signature_encodings() {
+    // cargo test   --test rsa_tests test_rsa_signature_encodings -- --nocapture
+    let mut agent = load_test_agent_one();
+    set_enc_to_rsa();
+    let public_key = agent.get_public_key().unwrap();
+
+    assert_signs_in_every_encoding(&mut agent, public_key);
+}
+
+#[test]
+fn test_rsa_sign_and_verify_bytes() {
+    // cargo test   --test rsa_tests test_rsa_sign_and_verify_bytes -- --nocapture
+    let mut agent = load_test_agent_one();
+    set_enc_to_rsa();
+    let public_key = agent.get_public_key().unwrap();
+
+    // not valid UTF-8, so it could not round trip through sign_string
+    let data: Vec<u8> = vec![0x89, 0x50, 0x4e, 0x47, 0x00, 0xff, 0xfe, 0x0d, 0x0a];
+    assert!(std::str::from_utf8(&data).is_err());
+    let signature = agent.sign_bytes(&data).unwrap();
+    agent
+        .verify_bytes(&data, &signature, public_key.clone(), None)
+        .unwrap();
+
+    let mut altered = data.clone();
+    altered[5] = 0xfd;
+    assert!(agent
+        .verify_bytes(&altered, &signature, public_key, None)
+        .is_err());
+}
@@ -0,0 +1,77 @@
+use jacs::agent::document::Document;
+use jacs::agent::loaders::{persist_temp_file, write_temp_file, FileLoader};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+mod utils;
+use utils::load_test_agent_one;
+
+fn scratch_file(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("jacs_atomic_write_tests");
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+#[test]
+fn test_interrupted_write_leaves_original() {
+    // cargo test   --test storage_tests -- --nocapture
+    let path = scratch_file("interrupted.json");
+    fs::write(&path, b"original").unwrap();
+
+    // process dies after the temp file is written but before the rename
+    let temp_path = write_temp_file(&path, b"replacement").unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"original");
+    fs::remove_file(&temp_path).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"original");
+}
+
+#[test]
+fn test_completed_write_replaces_original() {
+    let path = scratch_file("completed.json");
+    fs::write(&path, b"original").unwrap();
+
+    let temp_path = write_temp_file(&path, b"replacement").unwrap();
+    persist_temp_file(&temp_path, &path).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"replacement");
+    assert!(!temp_path.exists());
+}
+
+#[test]
+fn test_document_list_sorts_from_index() {
+    // cargo test   --test storage_tests test_document_list_sorts_from_index -- --nocapture
+    let mut agent = load_test_agent_one();
+    let mut document_keys = Vec::new();
+    for note in ["listed first", "listed second"] {
+        let document = agent
+            .create_document_and_load(&serde_json::json!({ "note": note }).to_string(), None, None)
+            .unwrap();
+        agent
+            .save_document(&document.getkey(), None, None, None)
+            .unwrap();
+        document_keys.push(document.getkey());
+    }
+    let position = |document_ids: &Vec<String>, document_key: &String| {
+        document_ids
+            .iter()
+            .position(|id| id == document_key)
+            .unwrap()
+    };
+    let document_ids = agent.fs_document_list().unwrap();
+    assert!(
+        position(&document_ids, &document_keys[0]) < position(&document_ids, &document_keys[1])
+    );
+
+    // listing trusts the index rather than opening each document
+    let index_path = PathBuf::from(env::var("JACS_DATA_DIRECTORY").unwrap())
+        .join("documents")
+        .join(".index.json");
+    let mut index: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&index_path).unwrap()).unwrap();
+    index[&document_keys[0]] = serde_json::json!("2999-01-01T00:00:00+00:00");
+    fs::write(&index_path, index.to_string()).unwrap();
+    let document_ids = agent.fs_document_list().unwrap();
+    assert!(
+        position(&document_ids, &document_keys[0]) > position(&document_ids, &document_keys[1])
+    );
+    assert!(!document_ids.iter().any(|id| id.starts_with('.')));
+}
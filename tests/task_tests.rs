@@ -41,6 +41,37 @@ fn test_hai_fields_custom_schema_and_custom_document() {
     print_fields(&agent, value.clone())
 }
 
+#[test]
+fn test_hai_fields_for_task_schema() {
+    let agent = load_test_agent_one();
+    let fields = agent
+        .schema
+        .hai_fields_for_schema("schemas/task/v1/task.schema.json")
+        .unwrap();
+    let listed = |level: &str, field: &str| {
+        fields[level]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|listed| listed == field)
+    };
+    assert!(listed("agent", "jacsTaskName"));
+    assert!(listed("meta", "jacsTaskName"));
+    assert!(listed("base", "jacsTaskName"));
+    assert!(!listed("agent", "jacsTaskState"));
+    assert!(listed("meta", "jacsTaskState"));
+    // header fields come in through allOf, embedded schemas' fields under their parent
+    assert!(listed("meta", "jacsId"));
+    assert!(listed("base", "jacsTaskCustomer"));
+    assert!(listed("base", "jacsTaskCustomer.signature"));
+    assert!(!listed("meta", "jacsTaskCustomer"));
+
+    assert!(agent
+        .schema
+        .hai_fields_for_schema("schemas/nothing/v1/nothing.schema.json")
+        .is_err());
+}
+
 #[test]
 fn test_create_task_with_actions() {
     // cargo test   --test task_tests test_create_task_with_actions -- --nocapture
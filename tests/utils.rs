@@ -2,6 +2,7 @@ use jacs::agent::boilerplate::BoilerPlate;
 use jacs::agent::document::Document;
 use jacs::agent::loaders::FileLoader;
 use jacs::agent::Agent;
+use jacs::crypt::{KeyManager, SignatureEncoding};
 use log::debug;
 use std::error::Error;
 use std::fs;
@@ -150,6 +151,29 @@ pub fn load_test_agent_two() -> Agent {
     agent
 }
 
+/// signs and verifies with the agent's key in every signature encoding, and checks a
+/// base58 signature is not accepted as the default base64
+#[cfg(test)]
+pub fn assert_signs_in_every_encoding(agent: &mut Agent, public_key: Vec<u8>) {
+    let data = "signed in every encoding".to_string();
+    for encoding in [
+        SignatureEncoding::Base64,
+        SignatureEncoding::Base64Url,
+        SignatureEncoding::Base58,
+    ] {
+        let signature = agent.sign_string(&data, Some(encoding)).unwrap();
+        agent
+            .verify_string(&data, &signature, public_key.clone(), None, Some(encoding))
+            .unwrap();
+    }
+    let base58_signature = agent
+        .sign_string(&data, Some(SignatureEncoding::Base58))
+        .unwrap();
+    assert!(agent
+        .verify_string(&data, &base58_signature, public_key, None, None)
+        .is_err());
+}
+
 #[cfg(test)]
 pub fn load_local_document(filepath: &String) -> Result<String, Box<dyn Error>> {
     let current_dir = env::current_dir()?;